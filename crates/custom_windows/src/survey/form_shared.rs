@@ -1,17 +1,23 @@
-use std::io::Read;
+use std::io::{Read, Seek, SeekFrom};
 use std::{fs, thread};
 use std::path::PathBuf;
+use std::time::Duration;
 
 use anyhow::{Context, bail};
 use indexmap::IndexMap;
+use sha2::{Digest, Sha256};
 use overlay_types::toasts;
 use portal2_sdk::Engine;
 
 use super::{get_answer_dir, get_timestamp, save_files, set_request_status, get_survey_dir};
-use super::{GLOBAL_SURVEY_CONFIG, SERVER_URL, SERVER_URL_FILE};
+use super::{GLOBAL_SURVEY_CONFIG, SERVER_URL, SERVER_URL_SURVEYS, SERVER_URL_UPLOAD_SESSIONS};
 use super::{WATERMARK_TEXT, WATERMARK_FONT, WATERMARK_COLOR, WATERMARK_ALIGN};
 use super::types::*;
 
+const UPLOAD_CHUNK_SIZE: u64 = 2 * 1024 * 1024;
+const UPLOAD_MAX_RETRIES: u32 = 6;
+const SUBMIT_MAX_RETRIES: u32 = 3;
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum FormAction {
     Submitted,
@@ -101,22 +107,109 @@ impl WidgetForm {
         self.scroll_to_top = true;
     }
 
-    fn send_file(mod_key: &str, file_path: &PathBuf) -> anyhow::Result<(String, String)> {
+    /// Uploads a file with the resumable upload protocol: the file is sent in chunks,
+    /// and after a network error the upload continues from the offset the server has received.
+    fn send_file(mod_key: &str, submission_key: &str, file_path: &PathBuf) -> anyhow::Result<(String, String)> {
         let file = fs::File::open(file_path)?;
         let file_name = file_path.file_name().and_then(|s| s.to_str()).unwrap_or_default();
         let len = file.metadata().context("Failed to read metadata")?.len();
 
-        let upload_res = ureq::post(SERVER_URL_FILE)
-            .header("X-Moderator-Key", mod_key)
-            .header("X-File-Name", file_name)
-            .header("Content-Length", &len.to_string())
-            .send(ureq::SendBody::from_owned_reader(file.take(len)))
-            .context("Failed to upload file")?;
+        let agent: ureq::Agent = ureq::Agent::config_builder()
+            .timeout_global(Some(Duration::from_secs(180)))
+            .build()
+            .into();
+
+        // Retried session creation returns the same upload, thanks to the idempotency key
+        let idempotency_key = format!("{}-{}", submission_key, file_name);
+        let mut attempt = 0;
+        let session_json: serde_json::Value = loop {
+            let result = agent.post(SERVER_URL_UPLOAD_SESSIONS)
+                .header("X-Moderator-Key", mod_key)
+                .header("X-Idempotency-Key", &idempotency_key)
+                .header("X-File-Name", file_name)
+                .header("X-Upload-Length", &len.to_string())
+                .send_empty();
 
-        let upload_json: serde_json::Value = upload_res
-            .into_body()
-            .read_json()
-            .context("Failed to parse response JSON")?;
+            match result {
+                Ok(response) => break response.into_body().read_json().context("Failed to parse response JSON")?,
+                Err(ureq::Error::StatusCode(410)) => {
+                    bail!("The moderator key was revoked, '{}' was not sent", file_name)
+                }
+                Err(ureq::Error::StatusCode(429)) => {
+                    bail!("The upload limit of this mod on the server was reached, '{}' was not sent", file_name)
+                }
+                Err(ureq::Error::StatusCode(code)) if code != 409 && code < 500 => {
+                    bail!("Failed to start upload: HTTP {}", code)
+                }
+                Err(e) if attempt >= UPLOAD_MAX_RETRIES => {
+                    return Err(anyhow::Error::new(e).context("Failed to start upload"))
+                }
+                Err(e) => {
+                    attempt += 1;
+                    log::warn!("Starting upload of '{}' failed: {}. Retrying...", file_name, e);
+                    thread::sleep(Duration::from_secs(1 << attempt));
+                }
+            }
+        };
+
+        let upload_id = session_json["upload_id"]
+            .as_str()
+            .context("No upload_id in response")?;
+        let session_url = format!("{}/{}", SERVER_URL_UPLOAD_SESSIONS, upload_id);
+
+        let mut offset = 0;
+        let mut failures = 0;
+        while offset < len {
+            let chunk_len = (len - offset).min(UPLOAD_CHUNK_SIZE);
+            let result = Self::send_chunk(&agent, &session_url, mod_key, &file, offset, chunk_len);
+
+            match result {
+                Ok(new_offset) => {
+                    offset = new_offset;
+                    failures = 0;
+                }
+                Err(e) => {
+                    failures += 1;
+                    if failures > UPLOAD_MAX_RETRIES {
+                        return Err(e.context(format!("Upload of '{}' failed after {} retries", file_name, UPLOAD_MAX_RETRIES)));
+                    }
+
+                    log::warn!("Uploading '{}' interrupted at {} / {} bytes: {}. Retrying...", file_name, offset, len, e);
+                    thread::sleep(Duration::from_secs(1 << failures));
+
+                    // The server may have stored part of the chunk, continue from what it actually has
+                    if let Ok(server_offset) = Self::query_upload_offset(&agent, &session_url, mod_key) {
+                        offset = server_offset;
+                    }
+                }
+            }
+        }
+
+        // Finalizing twice returns the same file, a lost response can be retried like a chunk
+        let mut failures = 0;
+        let upload_json: serde_json::Value = loop {
+            let result = agent.post(format!("{}/finalize", session_url))
+                .header("X-Moderator-Key", mod_key)
+                .send_empty();
+
+            match result {
+                Ok(response) => break response.into_body().read_json().context("Failed to parse response JSON")?,
+                Err(ureq::Error::StatusCode(410)) => {
+                    bail!("The moderator key was revoked, '{}' was not sent", file_name)
+                }
+                Err(ureq::Error::StatusCode(code)) if code != 409 && code < 500 => {
+                    bail!("Failed to finalize upload: HTTP {}", code)
+                }
+                Err(e) if failures >= UPLOAD_MAX_RETRIES => {
+                    return Err(anyhow::Error::new(e).context(format!("Finalizing '{}' failed after {} retries", file_name, UPLOAD_MAX_RETRIES)))
+                }
+                Err(e) => {
+                    failures += 1;
+                    log::warn!("Finalizing upload of '{}' failed: {}. Retrying...", file_name, e);
+                    thread::sleep(Duration::from_secs(1 << failures));
+                }
+            }
+        };
 
         let file_id = upload_json["file_id"]
             .as_str()
@@ -125,6 +218,57 @@ impl WidgetForm {
         Ok((file_id.to_string(), file_name.to_string()))
     }
 
+    /// Sends `chunk_len` bytes of `file` starting at `offset` and returns the new server-side offset
+    fn send_chunk(
+        agent: &ureq::Agent,
+        session_url: &str,
+        mod_key: &str,
+        mut file: &fs::File,
+        offset: u64,
+        chunk_len: u64,
+    ) -> anyhow::Result<u64> {
+        file.seek(SeekFrom::Start(offset))?;
+        let mut chunk = file.take(chunk_len);
+
+        let response_json: serde_json::Value = agent.put(session_url)
+            .header("X-Moderator-Key", mod_key)
+            .header("X-Upload-Offset", &offset.to_string())
+            .header("Content-Length", &chunk_len.to_string())
+            .send(ureq::SendBody::from_reader(&mut chunk))?
+            .into_body()
+            .read_json()?;
+
+        response_json["offset"].as_u64().context("No offset in response")
+    }
+
+    /// Registers the survey config on the server, so it knows the question types of the answers.
+    /// The server stores each distinct config once per key, registering the same one again is a no-op.
+    fn register_survey(mod_key: &str, survey_id: &str, definition: &[u8], hash: &str) -> anyhow::Result<()> {
+        let agent: ureq::Agent = ureq::Agent::config_builder()
+            .timeout_global(Some(Duration::from_secs(15)))
+            .build()
+            .into();
+
+        agent.post(SERVER_URL_SURVEYS)
+            .header("Content-Type", "application/json")
+            .header("X-Moderator-Key", mod_key)
+            .header("X-Survey-Id", survey_id)
+            .header("X-Survey-Hash", hash)
+            .send(definition)
+            .context("Failed to register survey definition")?;
+        Ok(())
+    }
+
+    fn query_upload_offset(agent: &ureq::Agent, session_url: &str, mod_key: &str) -> anyhow::Result<u64> {
+        let response_json: serde_json::Value = agent.get(session_url)
+            .header("X-Moderator-Key", mod_key)
+            .call()?
+            .into_body()
+            .read_json()?;
+
+        response_json["offset"].as_u64().context("No offset in response")
+    }
+
     /// Collects all data and saves it to a structured JSON file.
     /// The provided `base_data` HashMap is used as a base, and common information
     /// (user, answers, etc.) is added to it before serialization.
@@ -168,6 +312,10 @@ impl WidgetForm {
         let game_timestamp = client.get_last_time_stamp();
 
         let config_path = self.config_path.clone();
+
+        // The exact bytes registered on the server, the submission refers to them by hash
+        let survey_definition = serde_json::to_vec(&self.config)?;
+        let survey_hash = format!("{:x}", Sha256::digest(&survey_definition));
         let (survey_with_demo, survey_with_logs, survey_with_recording) = (
             self.config.send_with_demo, self.config.send_with_logs, self.config.send_with_recording
         );
@@ -177,6 +325,9 @@ impl WidgetForm {
             client.execute_client_cmd_unrestricted(hook_cmd);
         }
 
+        // Identifies this submission on the server, so retries after a lost response are not stored twice
+        let submission_key = format!("{}-{}-{}", user_xuid, submission_timestamp, config_stem_of(&self.config_path));
+
         // Peace of shit, but it's... works :>
         thread::spawn(move || {
             let config = GLOBAL_SURVEY_CONFIG.get()
@@ -191,9 +342,10 @@ impl WidgetForm {
                         let mut guard = save_files::LOGS_FILE.lock().unwrap();
                         guard.take()
                     };
-                    let handle = thread::spawn(|| {
+                    let submission_key = submission_key.clone();
+                    let handle = thread::spawn(move || {
                         if let Some(log_file) = file_to_process {
-                            return Self::send_file(&config.mod_key, &log_file)
+                            return Self::send_file(&config.mod_key, &submission_key, &log_file)
                         }
                         bail!("Does not have logs!")
                     });
@@ -202,9 +354,10 @@ impl WidgetForm {
 
                 if config.save_demos && survey_with_demo {
                     save_files::stop_demo_recording();
+                    let submission_key = submission_key.clone();
                     let handle = thread::spawn(move || {
                         if let Ok(zip_file) = save_files::pack_demos() {
-                            return Self::send_file(&config.mod_key, &zip_file)
+                            return Self::send_file(&config.mod_key, &submission_key, &zip_file)
                         }
                         bail!("Failed to pack demos!")
                     });
@@ -218,9 +371,10 @@ impl WidgetForm {
                         guard.take()
                     };
 
-                    let handle = thread::spawn(|| {
+                    let submission_key = submission_key.clone();
+                    let handle = thread::spawn(move || {
                         if let Some(video_file) = file_to_process {
-                            return Self::send_file(&config.mod_key, &video_file)
+                            return Self::send_file(&config.mod_key, &submission_key, &video_file)
                         }
                         bail!("Does not have video!")
                     });
@@ -237,11 +391,7 @@ impl WidgetForm {
             }
 
             // Generate dynamic filename and path
-            let config_stem = PathBuf::from(&config_path)
-                .file_stem()
-                .and_then(|s| s.to_str())
-                .unwrap_or("unknown_config")
-                .to_string();
+            let config_stem = config_stem_of(&config_path);
 
             // Create the final structure
             let submission = FormSubmission {
@@ -254,6 +404,7 @@ impl WidgetForm {
                 answers,
                 custom_embed_color,
                 files,
+                survey_hash: Some(survey_hash.clone()),
 
                 extra_data: extra_data.unwrap_or_default(),
             };
@@ -277,15 +428,36 @@ impl WidgetForm {
             }
 
             set_request_status("sending...");
+            if let Err(e) = Self::register_survey(&config.mod_key, &submission.survey_id, &survey_definition, &survey_hash) {
+                log::warn!("Survey definition was not registered, the server will treat answers as text: {}", e);
+            }
+
             let agent: ureq::Agent = ureq::Agent::config_builder()
                 .http_status_as_error(false)
                 .build()
                 .into();
 
-            let result = agent.post(SERVER_URL)
-                .header("Content-Type", "application/json")
-                .header("X-Moderator-Key", &config.mod_key)
-                .send(&body_for_thread);
+            // Network errors and gateway failures are retried, the server ignores duplicates by idempotency key
+            let mut attempt = 0;
+            let result = loop {
+                let result = agent.post(SERVER_URL)
+                    .header("Content-Type", "application/json")
+                    .header("X-Moderator-Key", &config.mod_key)
+                    .header("X-Idempotency-Key", &submission_key)
+                    .send(&body_for_thread);
+
+                let retryable = match &result {
+                    Ok(response) => matches!(response.status().as_u16(), 409 | 502 | 503 | 504),
+                    Err(_) => true,
+                };
+                if !retryable || attempt >= SUBMIT_MAX_RETRIES {
+                    break result;
+                }
+
+                attempt += 1;
+                log::warn!("Survey submission attempt {} failed, retrying...", attempt);
+                thread::sleep(Duration::from_secs(1 << attempt));
+            };
 
             match result {
                 Ok(response) => {
@@ -295,10 +467,23 @@ impl WidgetForm {
                         toasts::success("Survey submitted successfully", 500);
                     } else {
                         set_request_status(format!("error: HTTP {}", code));
+                        let retry_after = response.headers()
+                            .get("Retry-After")
+                            .and_then(|h| h.to_str().ok())
+                            .and_then(|s| s.parse::<u64>().ok());
                         let response_text = response.into_body().read_to_string().unwrap_or_default();
 
                         let user_message = match code {
                             401 | 403 => "Survey submission failed: Invalid Moderator Key.".to_string(),
+                            410 => "Survey submission failed: The Moderator Key was revoked. Ask the mod team for a new one.".to_string(),
+                            429 => match retry_after {
+                                Some(secs) => format!("Survey submission failed: Too many reports were sent for this mod. Please try again in {}.", format_wait(secs)),
+                                None => "Survey submission failed: Too many reports were sent for this mod. Please try again later.".to_string(),
+                            },
+                            422 => match first_validation_error(&response_text) {
+                                Some(error) => format!("Survey submission was rejected by the server: {}.", error),
+                                None => "Survey submission was rejected by the server. Please report this.".to_string(),
+                            },
                             502 => "Survey submission failed: The server is temporarily unavailable (Bad Gateway).".to_string(),
                             500..=599 => format!("Survey submission failed: The server encountered an internal error (Code: {}).", code),
                             400..=499 => format!("Survey submission failed: There was a problem with the request (Code: {}). Please report this.", code),
@@ -513,3 +698,28 @@ impl WidgetForm {
         action
     }
 }
+
+/// File name of the survey config without extension, e.g. `feedback` for `surveys/feedback.json`
+fn config_stem_of(config_path: &str) -> String {
+    PathBuf::from(config_path)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("unknown_config")
+        .to_string()
+}
+
+/// Human readable wait time for `Retry-After` values
+fn format_wait(secs: u64) -> String {
+    match secs {
+        0..=59 => format!("{} seconds", secs.max(1)),
+        60..=3599 => format!("{} minutes", secs.div_ceil(60)),
+        _ => format!("{} hours", secs.div_ceil(3600)),
+    }
+}
+
+/// First entry of the server's 422 error list, as "field message"
+fn first_validation_error(response_text: &str) -> Option<String> {
+    let body: serde_json::Value = serde_json::from_str(response_text).ok()?;
+    let error = body.get("errors")?.as_array()?.first()?;
+    Some(format!("{} {}", error.get("field")?.as_str()?, error.get("message")?.as_str()?))
+}
@@ -15,7 +15,8 @@ pub use bug_report::BugReportWin;
 
 const DEFAULT_SURVEY: &str = "default.json";
 const SERVER_URL: &str = "https://lab.lavashik.dev/p2_survey/submit";
-const SERVER_URL_FILE: &str = "https://lab.lavashik.dev/p2_survey/upload";
+const SERVER_URL_UPLOAD_SESSIONS: &str = "https://lab.lavashik.dev/p2_survey/upload/sessions";
+const SERVER_URL_SURVEYS: &str = "https://lab.lavashik.dev/p2_survey/surveys";
 // Global, write-once container for the moderator key, loaded from config.json.
 pub static GLOBAL_SURVEY_CONFIG: OnceLock<ClientConfig> = OnceLock::new();
 // Global, thread-safe, mutable string to hold the current status of the network request.
@@ -2,7 +2,7 @@ use serde::{Deserialize, Serialize};
 use indexmap::IndexMap;
 
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct OneToTenConfig {
     pub text: String,
     pub label_at_one: String,
@@ -10,32 +10,32 @@ pub struct OneToTenConfig {
     pub required: bool,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct EssayConfig {
     pub text: String,
     pub required: bool,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct RadioChoicesConfig {
     pub text: String,
     pub choices: Vec<String>,
     pub required: bool,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct TextBlockConfig {
     pub text: String,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct CheckboxesConfig {
     pub text: String,
     pub choices: Vec<String>,
     pub required: bool,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(tag = "type")]
 pub enum WidgetConfig {
     OneToTen(OneToTenConfig),
@@ -71,7 +71,7 @@ impl WidgetConfig {
     }
 }
 
-#[derive(Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub struct FormConfig {
     pub title: String,
     pub embed_color: Option<String>, // format: "R G B"
@@ -134,6 +134,8 @@ pub struct FormSubmission {
     pub answers: IndexMap<String, String>,
     pub custom_embed_color: Option<i32>,
     pub files: Vec<(String, String)>, // (file_id, file_name)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub survey_hash: Option<String>, // SHA-256 of the survey config registered on the server
 
     #[serde(flatten)]
     pub extra_data: IndexMap<String, serde_json::Value>,
@@ -0,0 +1,334 @@
+use crate::bug_status::BugState;
+use crate::duplicates::DuplicateMatch;
+use crate::export_jobs::{self, ExportFilters, ExportJob, ExportStatus, StartError};
+use crate::exporter::ExportFormat;
+use crate::file_manager::FileMetadata;
+use crate::http_server::{authorize, serve_stored};
+use crate::links::LinkSigner;
+use crate::models::FormSubmission;
+use crate::previews::PreviewKind;
+use crate::state::ServerState;
+use crate::submission_store::{StoredSubmission, SubmissionFilter};
+use crate::surveys::StoredDefinition;
+use axum::{
+    body::Body,
+    extract::{Json, Path, Query, State},
+    http::{header, HeaderMap, HeaderValue, Request, StatusCode},
+    response::Response,
+    routing::{get, post},
+    Router,
+};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use tracing::error;
+use uuid::Uuid;
+
+const DEFAULT_PAGE_SIZE: u32 = 50;
+const MAX_PAGE_SIZE: u32 = 500;
+
+/// Read-only query API for internal tools, authenticated with `X-Moderator-Key`
+pub fn router() -> Router<ServerState> {
+    Router::new()
+        .route("/api/submissions", get(list_submissions))
+        .route("/api/submissions/:id", get(get_submission))
+        .route("/api/export", get(export_submissions))
+        .route("/api/exports", post(start_export).get(list_exports))
+        .route("/api/exports/:id", get(get_export))
+        .route("/api/surveys", get(list_surveys))
+}
+
+#[derive(Deserialize, Debug)]
+struct SubmissionQuery {
+    survey_id: Option<String>,
+    map_name: Option<String>,
+    user_xuid: Option<String>,
+    from: Option<u64>, // unix timestamp, inclusive
+    to: Option<u64>,   // unix timestamp, inclusive
+    limit: Option<u32>,
+    offset: Option<u32>,
+}
+
+impl SubmissionQuery {
+    fn filter(&self) -> Result<SubmissionFilter, StatusCode> {
+        checked_filter(SubmissionFilter {
+            survey_id: self.survey_id.clone(),
+            map_name: self.map_name.clone(),
+            user_xuid: self.user_xuid.clone(),
+            from_timestamp: self.from,
+            to_timestamp: self.to,
+            after_seq: None,
+        })
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct ExportQuery {
+    #[serde(default)]
+    format: ExportFormat,
+    survey_id: Option<String>,
+    map_name: Option<String>,
+    user_xuid: Option<String>,
+    from: Option<u64>,
+    to: Option<u64>,
+    #[serde(default)]
+    since_last_export: bool,
+}
+
+impl ExportQuery {
+    fn filters(&self) -> Result<ExportFilters, StatusCode> {
+        checked_export_filters(ExportFilters {
+            survey_id: self.survey_id.clone(),
+            map_name: self.map_name.clone(),
+            user_xuid: self.user_xuid.clone(),
+            from: self.from,
+            to: self.to,
+            since_last_export: self.since_last_export,
+        })
+    }
+}
+
+/// Body of `POST /api/exports`
+#[derive(Deserialize, Debug)]
+struct ExportRequest {
+    #[serde(default)]
+    format: ExportFormat,
+    #[serde(flatten)]
+    filters: ExportFilters,
+}
+
+/// Rejects filters whose date range is inverted
+fn checked_filter(filter: SubmissionFilter) -> Result<SubmissionFilter, StatusCode> {
+    match (filter.from_timestamp, filter.to_timestamp) {
+        (Some(from), Some(to)) if from > to => Err(StatusCode::BAD_REQUEST),
+        _ => Ok(filter),
+    }
+}
+
+fn checked_export_filters(filters: ExportFilters) -> Result<ExportFilters, StatusCode> {
+    match (filters.from, filters.to) {
+        (Some(from), Some(to)) if from > to => Err(StatusCode::BAD_REQUEST),
+        _ => Ok(filters),
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub(crate) struct AttachedFile {
+    #[serde(flatten)]
+    metadata: FileMetadata,
+    url: String,
+    #[serde(skip_serializing_if = "IndexMap::is_empty")]
+    preview_urls: IndexMap<PreviewKind, String>,
+}
+
+impl AttachedFile {
+    /// The URLs are signed, they expire together with the other links of the response
+    pub(crate) fn new(metadata: FileMetadata, links: &LinkSigner) -> Self {
+        Self {
+            url: links.data_link(&metadata.id).url,
+            preview_urls: metadata.previews.iter()
+                .map(|preview| (preview.kind, links.data_link(&preview.file_id).url))
+                .collect(),
+            metadata,
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct SubmissionResponse {
+    id: Uuid,
+    received_at: u64,
+    json_url: String,
+    links_expire_at: u64,
+    survey_version: Option<u32>,
+    submission: FormSubmission,
+    files: Vec<AttachedFile>,
+    possible_duplicates: Vec<DuplicateMatch>, // earlier bug reports, see `GET /api/submissions/:id`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bug: Option<BugState>, // status and assignee, only for bug reports
+}
+
+/// An export job, with a signed download link once it is completed
+#[derive(Serialize, Debug)]
+struct ExportJobResponse {
+    #[serde(flatten)]
+    job: ExportJob,
+    download_url: Option<String>,
+    links_expire_at: Option<u64>,
+}
+
+impl ExportJobResponse {
+    fn new(job: ExportJob, links: &LinkSigner) -> Self {
+        let link = match (&job.status, &job.filename) {
+            (ExportStatus::Completed, Some(filename)) => Some(links.export_link(filename)),
+            _ => None,
+        };
+        Self {
+            download_url: link.as_ref().map(|l| l.url.clone()),
+            links_expire_at: link.map(|l| l.expires_at),
+            job,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct SurveysQuery {
+    survey_id: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+struct SubmissionPage {
+    total: u64,
+    limit: u32,
+    offset: u32,
+    submissions: Vec<SubmissionResponse>,
+}
+
+async fn list_submissions(
+    State(state): State<ServerState>,
+    headers: HeaderMap,
+    Query(query): Query<SubmissionQuery>,
+) -> Result<Json<SubmissionPage>, StatusCode> {
+    let (key, _) = authorize(&state, &headers)?;
+    let filter = query.filter()?;
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    let offset = query.offset.unwrap_or(0);
+
+    let (total, records) = state.submissions.query_page(&key, &filter, limit, offset).map_err(|e| {
+        error!("Failed to query submissions: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(SubmissionPage {
+        total,
+        limit,
+        offset,
+        submissions: records.into_iter().map(|r| to_response(&state, r)).collect(),
+    }))
+}
+
+async fn get_submission(
+    State(state): State<ServerState>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<Json<SubmissionResponse>, StatusCode> {
+    let (key, _) = authorize(&state, &headers)?;
+
+    let record = state.submissions.get(&key, &id).map_err(|e| {
+        error!("Failed to load submission {}: {}", id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    match record {
+        Some(record) => Ok(Json(to_response(&state, record))),
+        None => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+fn start_error_status(error: StartError) -> StatusCode {
+    match error {
+        StartError::NoSubmissions | StartError::NothingNew { .. } => StatusCode::NOT_FOUND,
+        StartError::Index(e) => {
+            error!("Failed to start export: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+/// Builds an export of the matching submissions and sends it back as a download, once it is finished
+async fn export_submissions(
+    State(state): State<ServerState>,
+    headers: HeaderMap,
+    Query(query): Query<ExportQuery>,
+    request: Request<Body>,
+) -> Result<Response, StatusCode> {
+    let (key, _) = authorize(&state, &headers)?;
+    let filters = query.filters()?;
+
+    let (_, handle) = export_jobs::start_export(&state, &key, query.format, filters).map_err(start_error_status)?;
+    let job = handle.await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let (ExportStatus::Completed, Some(filename)) = (&job.status, job.filename) else {
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    };
+
+    let served = state.file_manager.storage.serve(&export_jobs::storage_key(&filename), &filename);
+    let mut response = serve_stored(served, request).await;
+    if let Ok(value) = HeaderValue::from_str(&format!("attachment; filename=\"{}\"", filename)) {
+        response.headers_mut().insert(header::CONTENT_DISPOSITION, value);
+    }
+    Ok(response)
+}
+
+/// Starts an export job in the background. Poll `GET /api/exports/:id` for its progress and download link.
+async fn start_export(
+    State(state): State<ServerState>,
+    headers: HeaderMap,
+    Json(request): Json<ExportRequest>,
+) -> Result<(StatusCode, Json<ExportJobResponse>), StatusCode> {
+    let (key, _) = authorize(&state, &headers)?;
+    let filters = checked_export_filters(request.filters)?;
+
+    let (job, _) = export_jobs::start_export(&state, &key, request.format, filters).map_err(start_error_status)?;
+    Ok((StatusCode::ACCEPTED, Json(ExportJobResponse::new(job, &state.links))))
+}
+
+/// Lists the key's export jobs, newest first
+async fn list_exports(
+    State(state): State<ServerState>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<ExportJobResponse>>, StatusCode> {
+    let (key, _) = authorize(&state, &headers)?;
+
+    let jobs = state.file_manager.exports.for_key(&key).into_iter()
+        .map(|job| ExportJobResponse::new(job, &state.links))
+        .collect();
+    Ok(Json(jobs))
+}
+
+async fn get_export(
+    State(state): State<ServerState>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<Json<ExportJobResponse>, StatusCode> {
+    let (key, _) = authorize(&state, &headers)?;
+
+    match state.file_manager.exports.get(&id) {
+        Some(job) if job.mod_key == key => Ok(Json(ExportJobResponse::new(job, &state.links))),
+        _ => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+/// Lists the registered survey definitions of the key, every version with its questions
+async fn list_surveys(
+    State(state): State<ServerState>,
+    headers: HeaderMap,
+    Query(query): Query<SurveysQuery>,
+) -> Result<Json<Vec<StoredDefinition>>, StatusCode> {
+    let (key, _) = authorize(&state, &headers)?;
+
+    state.submissions.definitions(&key, query.survey_id.as_deref())
+        .map(Json)
+        .map_err(|e| {
+            error!("Failed to load survey definitions: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+fn to_response(state: &ServerState, record: StoredSubmission) -> SubmissionResponse {
+    let files = record.file_ids.iter()
+        .filter_map(|id| state.file_manager.files.get(id).map(|m| m.clone()))
+        .map(|metadata| AttachedFile::new(metadata, &state.links))
+        .collect();
+    let json_link = state.links.data_link(&record.id);
+
+    SubmissionResponse {
+        id: record.id,
+        received_at: record.received_at,
+        json_url: json_link.url,
+        links_expire_at: json_link.expires_at,
+        survey_version: record.survey_version,
+        submission: record.submission,
+        files,
+        possible_duplicates: record.possible_duplicates,
+        bug: record.bug,
+    }
+}
@@ -0,0 +1,92 @@
+use crate::duplicates::POSITION_KEY;
+use crate::models::FormSubmission;
+use serde::{Deserialize, Serialize};
+
+/// Where a bug report is in its lifecycle, changed with the buttons under its notification
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BugStatus {
+    Open,
+    Triaged,
+    Fixed,
+    WontFix,
+    Duplicate,
+}
+
+impl BugStatus {
+    pub const ALL: [BugStatus; 5] = [BugStatus::Open, BugStatus::Triaged, BugStatus::Fixed, BugStatus::WontFix, BugStatus::Duplicate];
+
+    /// Value stored in the submission index and used in button ids
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BugStatus::Open => "open",
+            BugStatus::Triaged => "triaged",
+            BugStatus::Fixed => "fixed",
+            BugStatus::WontFix => "wont_fix",
+            BugStatus::Duplicate => "duplicate",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|status| status.as_str() == value)
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            BugStatus::Open => "Open",
+            BugStatus::Triaged => "Triaged",
+            BugStatus::Fixed => "Fixed",
+            BugStatus::WontFix => "Won't fix",
+            BugStatus::Duplicate => "Duplicate",
+        }
+    }
+
+    pub fn emoji(&self) -> char {
+        match self {
+            BugStatus::Open => '🐞',
+            BugStatus::Triaged => '🔍',
+            BugStatus::Fixed => '✅',
+            BugStatus::WontFix => '🚫',
+            BugStatus::Duplicate => '🔁',
+        }
+    }
+
+    /// Embed colour of a report with this status, `None` keeps the submission's own colour
+    pub fn colour(&self) -> Option<u32> {
+        match self {
+            BugStatus::Open => None,
+            BugStatus::Triaged => Some(0xF1C40F),
+            BugStatus::Fixed => Some(0x2ECC71),
+            BugStatus::WontFix => Some(0x95A5A6),
+            BugStatus::Duplicate => Some(0x9B59B6),
+        }
+    }
+
+    /// Resolved reports are no longer listed by `/open_bugs`
+    pub fn is_resolved(&self) -> bool {
+        !matches!(self, BugStatus::Open | BugStatus::Triaged)
+    }
+}
+
+/// Status and assignee of a bug report, as recorded in the submission index
+#[derive(Serialize, Debug, Clone)]
+pub struct BugState {
+    pub status: BugStatus,
+    pub assignee_id: Option<String>, // Discord user ID
+    pub assignee_name: Option<String>,
+    pub updated_by: Option<String>, // Discord user ID of the last change, None while untouched
+    pub updated_at: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<(String, String)>, // (channel_id, message_id) of the Discord notification
+}
+
+impl Default for BugState {
+    fn default() -> Self {
+        Self { status: BugStatus::Open, assignee_id: None, assignee_name: None, updated_by: None, updated_at: None, message: None }
+    }
+}
+
+/// Only the bug report window attaches the player's position
+pub fn is_bug_report(submission: &FormSubmission) -> bool {
+    submission.extra_data.contains_key(POSITION_KEY)
+}
@@ -0,0 +1,476 @@
+use crate::file_manager::{FileMetadata, FileStatus};
+use crate::http_server::resolve_key;
+use crate::models::ModeratorKeyData;
+use crate::state::ServerState;
+use crate::submission_store::{FilterOptions, MapSummary, StoredSubmission, SubmissionFilter};
+use axum::{
+    extract::{Form, Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Redirect, Response},
+    routing::{get, post},
+    Router,
+};
+use dashmap::DashMap;
+use serde::Deserialize;
+use std::fmt::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{error, info};
+use uuid::Uuid;
+
+const SESSION_COOKIE: &str = "dashboard_session";
+const SESSION_TTL_SECS: u64 = 12 * 60 * 60;
+const LOGIN_TOKEN_TTL_SECS: u64 = 15 * 60;
+const PAGE_SIZE: u32 = 50;
+
+const STYLE: &str = "
+    body { font-family: system-ui, sans-serif; margin: 0 auto; max-width: 1200px; padding: 1em; color: #222; }
+    table { border-collapse: collapse; width: 100%; margin-bottom: 1.5em; }
+    th, td { border-bottom: 1px solid #ddd; padding: 0.35em 0.6em; text-align: left; vertical-align: top; }
+    th { background: #f3f3f3; }
+    form.filters { display: flex; gap: 0.6em; flex-wrap: wrap; align-items: end; margin-bottom: 1.5em; }
+    header { display: flex; justify-content: space-between; align-items: center; }
+    .muted { color: #777; }
+    .active { color: #1a7f37; }
+    .expired { color: #b42318; }
+    .error { color: #b42318; }
+    pre { white-space: pre-wrap; margin: 0; font-family: inherit; }
+";
+
+/// Browser sessions of the dashboard, and one-time login links issued by `/dashboard_link`
+#[derive(Default)]
+pub struct DashboardSessions {
+    sessions: DashMap<String, (String, u64)>,     // session id -> (mod key, expires at)
+    login_tokens: DashMap<String, (String, u64)>, // login token -> (mod key, expires at)
+}
+
+impl DashboardSessions {
+    /// Issues a login token that can be exchanged for a session once
+    pub fn create_login_token(&self, mod_key: &str) -> String {
+        let token = Uuid::new_v4().simple().to_string();
+        self.login_tokens.insert(token.clone(), (mod_key.to_string(), now_secs() + LOGIN_TOKEN_TTL_SECS));
+        token
+    }
+
+    fn redeem_login_token(&self, token: &str) -> Option<String> {
+        self.login_tokens.remove(token)
+            .filter(|(_, (_, expires_at))| *expires_at > now_secs())
+            .map(|(_, (mod_key, _))| mod_key)
+    }
+
+    fn create_session(&self, mod_key: &str) -> String {
+        let id = Uuid::new_v4().simple().to_string();
+        self.sessions.insert(id.clone(), (mod_key.to_string(), now_secs() + SESSION_TTL_SECS));
+        id
+    }
+
+    fn session_key(&self, id: &str) -> Option<String> {
+        self.sessions.get(id)
+            .filter(|entry| entry.1 > now_secs())
+            .map(|entry| entry.0.clone())
+    }
+
+    fn end_session(&self, id: &str) {
+        self.sessions.remove(id);
+    }
+
+    pub fn purge_expired(&self) {
+        let now = now_secs();
+        self.sessions.retain(|_, (_, expires_at)| *expires_at > now);
+        self.login_tokens.retain(|_, (_, expires_at)| *expires_at > now);
+    }
+}
+
+/// Server-rendered HTML pages for browsing the submissions of a key
+pub fn router() -> Router<ServerState> {
+    Router::new()
+        .route("/dashboard", get(list_page))
+        .route("/dashboard/submissions/:id", get(submission_page))
+        .route("/dashboard/login", get(login_page).post(login))
+        .route("/dashboard/logout", post(logout))
+}
+
+#[derive(Deserialize, Debug)]
+struct LoginQuery {
+    token: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct LoginForm {
+    mod_key: String,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct ListQuery {
+    survey_id: Option<String>,
+    map_name: Option<String>,
+    user_xuid: Option<String>,
+    offset: Option<u32>,
+}
+
+impl ListQuery {
+    fn filter(&self) -> SubmissionFilter {
+        // Empty select options mean "any"
+        let non_empty = |v: &Option<String>| v.clone().filter(|v| !v.is_empty());
+        SubmissionFilter {
+            survey_id: non_empty(&self.survey_id),
+            map_name: non_empty(&self.map_name),
+            user_xuid: non_empty(&self.user_xuid),
+            ..Default::default()
+        }
+    }
+
+    fn clone_filters(&self) -> ListQuery {
+        ListQuery {
+            survey_id: self.survey_id.clone(),
+            map_name: self.map_name.clone(),
+            user_xuid: self.user_xuid.clone(),
+            offset: None,
+        }
+    }
+
+    /// Query string for the same filters at another offset
+    fn with_offset(&self, offset: u32) -> String {
+        let filter = self.filter();
+        let mut params = vec![format!("offset={}", offset)];
+        for (name, value) in [("survey_id", filter.survey_id), ("map_name", filter.map_name), ("user_xuid", filter.user_xuid)] {
+            if let Some(value) = value {
+                params.push(format!("{}={}", name, url_encode(&value)));
+            }
+        }
+        params.join("&")
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+fn base_url() -> String {
+    std::env::var("BASE_URL").unwrap_or_default()
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+fn url_encode(text: &str) -> String {
+    let mut encoded = String::new();
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => encoded.push(byte as char),
+            _ => { let _ = write!(encoded, "%{:02X}", byte); }
+        }
+    }
+    encoded
+}
+
+/// Formats a unix timestamp as `YYYY-MM-DD HH:MM UTC`
+fn format_time(timestamp: u64) -> String {
+    let (year, month, day) = crate::dates::civil_date(timestamp);
+    let secs_of_day = timestamp % 86400;
+    format!("{:04}-{:02}-{:02} {:02}:{:02} UTC", year, month, day, secs_of_day / 3600, secs_of_day % 3600 / 60)
+}
+
+fn format_size(bytes: u64) -> String {
+    match bytes {
+        b if b >= 1024 * 1024 => format!("{:.1} MB", b as f64 / (1024.0 * 1024.0)),
+        b if b >= 1024 => format!("{:.1} KB", b as f64 / 1024.0),
+        b => format!("{} B", b),
+    }
+}
+
+fn page(title: &str, body: &str) -> Html<String> {
+    Html(format!(
+        "<!DOCTYPE html>\n<html lang=\"en\"><head><meta charset=\"utf-8\">\
+        <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\
+        <title>{} - Playtest Feedback</title><style>{}</style></head><body>{}</body></html>",
+        escape_html(title), STYLE, body
+    ))
+}
+
+fn session_cookie(headers: &HeaderMap) -> Option<String> {
+    headers.get_all(header::COOKIE).iter()
+        .filter_map(|h| h.to_str().ok())
+        .flat_map(|h| h.split(';'))
+        .find_map(|pair| pair.trim().strip_prefix(SESSION_COOKIE)?.strip_prefix('=').map(str::to_string))
+}
+
+fn set_session_cookie(value: &str, max_age: u64) -> String {
+    // Only mark the cookie Secure when the dashboard is served over https, so it also works locally
+    let secure = if base_url().starts_with("https://") { "; Secure" } else { "" };
+    format!("{}={}; Path=/; HttpOnly; SameSite=Lax; Max-Age={}{}", SESSION_COOKIE, value, max_age, secure)
+}
+
+fn redirect_to_login() -> Response {
+    Redirect::to(&format!("{}/dashboard/login", base_url())).into_response()
+}
+
+/// Resolves the session cookie to the key it was opened with.
+/// Sessions of revoked keys end, and rotated keys resolve to their replacement like in `authorize`.
+fn session_key(state: &ServerState, headers: &HeaderMap) -> Option<(String, ModeratorKeyData)> {
+    let mod_key = session_cookie(headers).and_then(|id| state.dashboard.session_key(&id))?;
+    resolve_key(state, &mod_key).ok()
+}
+
+fn login_form(message: Option<&str>) -> Html<String> {
+    let mut body = String::from("<h1>Playtest Feedback</h1>");
+    if let Some(message) = message {
+        let _ = write!(body, "<p class=\"error\">{}</p>", escape_html(message));
+    }
+    let _ = write!(
+        body,
+        "<form method=\"post\" action=\"{}/dashboard/login\">\
+        <p><label>Moderator key<br><input type=\"password\" name=\"mod_key\" size=\"40\" autocomplete=\"off\" required></label></p>\
+        <p><button type=\"submit\">Log in</button></p></form>\
+        <p class=\"muted\">Or use <code>/dashboard_link</code> in the Discord channel of your key to get a login link.</p>",
+        base_url()
+    );
+    page("Log in", &body)
+}
+
+fn start_session(state: &ServerState, mod_key: &str) -> Response {
+    let session = state.dashboard.create_session(mod_key);
+    (
+        [(header::SET_COOKIE, set_session_cookie(&session, SESSION_TTL_SECS))],
+        Redirect::to(&format!("{}/dashboard", base_url())),
+    ).into_response()
+}
+
+async fn login_page(State(state): State<ServerState>, Query(query): Query<LoginQuery>) -> Response {
+    let Some(token) = query.token else {
+        return login_form(None).into_response();
+    };
+
+    match state.dashboard.redeem_login_token(&token) {
+        Some(mod_key) => match resolve_key(&state, &mod_key) {
+            Ok((key, _)) => start_session(&state, &key),
+            Err(_) => (StatusCode::GONE, login_form(Some("The key of this login link was revoked."))).into_response(),
+        },
+        None => (StatusCode::UNAUTHORIZED, login_form(Some("This login link has expired or was already used."))).into_response(),
+    }
+}
+
+async fn login(State(state): State<ServerState>, Form(form): Form<LoginForm>) -> Response {
+    match resolve_key(&state, form.mod_key.trim()) {
+        Ok((key, data)) => {
+            info!("Dashboard login for key of server '{}'", data.server_name);
+            start_session(&state, &key)
+        }
+        Err(StatusCode::GONE) => (StatusCode::GONE, login_form(Some("This moderator key was revoked."))).into_response(),
+        Err(status) => (status, login_form(Some("Unknown moderator key."))).into_response(),
+    }
+}
+
+async fn logout(State(state): State<ServerState>, headers: HeaderMap) -> Response {
+    if let Some(id) = session_cookie(&headers) {
+        state.dashboard.end_session(&id);
+    }
+    (
+        [(header::SET_COOKIE, set_session_cookie("", 0))],
+        Redirect::to(&format!("{}/dashboard/login", base_url())),
+    ).into_response()
+}
+
+fn page_header(data: &ModeratorKeyData) -> String {
+    format!(
+        "<header><h1>{}{}</h1>\
+        <form method=\"post\" action=\"{}/dashboard/logout\"><button type=\"submit\">Log out</button></form></header>",
+        escape_html(&data.server_name),
+        data.label.as_deref().map(|l| format!(" <span class=\"muted\">({})</span>", escape_html(l))).unwrap_or_default(),
+        base_url()
+    )
+}
+
+fn select(name: &str, label: &str, selected: Option<&str>, options: &[(String, String)]) -> String {
+    let mut html = format!("<label>{}<br><select name=\"{}\"><option value=\"\">Any</option>", label, name);
+    for (value, text) in options {
+        let is_selected = if selected == Some(value.as_str()) { " selected" } else { "" };
+        let _ = write!(html, "<option value=\"{}\"{}>{}</option>", escape_html(value), is_selected, escape_html(text));
+    }
+    html.push_str("</select></label>");
+    html
+}
+
+fn filter_form(filter: &SubmissionFilter, options: &FilterOptions) -> String {
+    let as_options = |values: &[String]| values.iter().map(|v| (v.clone(), v.clone())).collect::<Vec<_>>();
+    let testers: Vec<(String, String)> = options.testers.iter()
+        .map(|(xuid, name)| (xuid.clone(), format!("{} ({})", name, xuid)))
+        .collect();
+
+    format!(
+        "<form class=\"filters\" method=\"get\" action=\"{}/dashboard\">{}{}{}\
+        <button type=\"submit\">Filter</button> <a href=\"{}/dashboard\">Reset</a></form>",
+        base_url(),
+        select("survey_id", "Survey", filter.survey_id.as_deref(), &as_options(&options.survey_ids)),
+        select("map_name", "Map", filter.map_name.as_deref(), &as_options(&options.map_names)),
+        select("user_xuid", "Tester", filter.user_xuid.as_deref(), &testers),
+        base_url()
+    )
+}
+
+fn map_table(summaries: &[MapSummary], query: &ListQuery) -> String {
+    let mut html = String::from(
+        "<h2>Maps</h2><table><tr><th>Map</th><th>Submissions</th><th>Testers</th><th>Attachments</th><th>Last submission</th></tr>",
+    );
+    for summary in summaries {
+        let link = ListQuery { map_name: Some(summary.map_name.clone()), ..query.clone_filters() };
+        let _ = write!(
+            html,
+            "<tr><td><a href=\"{}/dashboard?{}\">{}</a></td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            base_url(), escape_html(&link.with_offset(0)), escape_html(&summary.map_name),
+            summary.submissions, summary.testers, summary.attachments, format_time(summary.last_submission)
+        );
+    }
+    html.push_str("</table>");
+    html
+}
+
+/// Short status of a set of attachments, e.g. "2 active, 1 expired"
+fn attachment_summary(files: &[FileMetadata]) -> String {
+    if files.is_empty() {
+        return "<span class=\"muted\">none</span>".to_string();
+    }
+    let active = files.iter().filter(|f| matches!(f.status, FileStatus::Active)).count();
+    let expired = files.len() - active;
+    match (active, expired) {
+        (active, 0) => format!("<span class=\"active\">{} active</span>", active),
+        (0, expired) => format!("<span class=\"expired\">{} expired</span>", expired),
+        (active, expired) => format!("<span class=\"active\">{} active</span>, <span class=\"expired\">{} expired</span>", active, expired),
+    }
+}
+
+fn attached_files(state: &ServerState, record: &StoredSubmission) -> Vec<FileMetadata> {
+    record.file_ids.iter()
+        .filter_map(|id| state.file_manager.files.get(id).map(|m| m.clone()))
+        .collect()
+}
+
+async fn list_page(State(state): State<ServerState>, headers: HeaderMap, Query(query): Query<ListQuery>) -> Response {
+    let Some((key, data)) = session_key(&state, &headers) else {
+        return redirect_to_login();
+    };
+    let filter = query.filter();
+    let offset = query.offset.unwrap_or(0);
+
+    let loaded = state.submissions.filter_options(&key)
+        .and_then(|options| Ok((options, state.submissions.map_summaries(&key, &filter)?)))
+        .and_then(|(options, summaries)| Ok((options, summaries, state.submissions.query_page(&key, &filter, PAGE_SIZE, offset)?)));
+    let (options, summaries, (total, records)) = match loaded {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            error!("Failed to load dashboard data: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let mut body = page_header(&data);
+    body.push_str(&filter_form(&filter, &options));
+    body.push_str(&map_table(&summaries, &query));
+
+    let _ = write!(body, "<h2>Submissions <span class=\"muted\">({})</span></h2>", total);
+    body.push_str("<table><tr><th>Submitted</th><th>Survey</th><th>Map</th><th>Tester</th><th>Answers</th><th>Attachments</th></tr>");
+    for record in &records {
+        let submission = &record.submission;
+        let version = record.survey_version.map(|v| format!(" <span class=\"muted\">v{}</span>", v)).unwrap_or_default();
+        let _ = write!(
+            body,
+            "<tr><td><a href=\"{}/dashboard/submissions/{}\">{}</a></td><td>{}{}</td><td>{}</td><td>{} <span class=\"muted\">{}</span></td><td>{}</td><td>{}</td></tr>",
+            base_url(), record.id, format_time(submission.submission_timestamp),
+            escape_html(&submission.survey_id), version,
+            escape_html(&submission.map_name),
+            escape_html(&submission.user_name), escape_html(&submission.user_xuid),
+            submission.answers.len(),
+            attachment_summary(&attached_files(&state, record)),
+        );
+    }
+    body.push_str("</table>");
+
+    // Pagination
+    let mut links = Vec::new();
+    if offset > 0 {
+        links.push(format!("<a href=\"{}/dashboard?{}\">&larr; Newer</a>", base_url(), escape_html(&query.with_offset(offset.saturating_sub(PAGE_SIZE)))));
+    }
+    if ((offset + PAGE_SIZE) as u64) < total {
+        links.push(format!("<a href=\"{}/dashboard?{}\">Older &rarr;</a>", base_url(), escape_html(&query.with_offset(offset + PAGE_SIZE))));
+    }
+    let _ = write!(body, "<p>{}</p>", links.join(" | "));
+
+    page("Submissions", &body).into_response()
+}
+
+fn file_status(file: &FileMetadata) -> String {
+    match &file.status {
+        FileStatus::Expired { deleted_at } => format!("<span class=\"expired\">Expired, deleted {}</span>", format_time(*deleted_at)),
+        FileStatus::Active if file.is_priority => "<span class=\"active\">Active, priority</span>".to_string(),
+        FileStatus::Active => match file.expires_at {
+            Some(expires_at) => format!("<span class=\"active\">Active until {}</span>", format_time(expires_at)),
+            None => "<span class=\"active\">Active</span>".to_string(),
+        },
+    }
+}
+
+async fn submission_page(State(state): State<ServerState>, headers: HeaderMap, Path(id): Path<Uuid>) -> Response {
+    let Some((key, data)) = session_key(&state, &headers) else {
+        return redirect_to_login();
+    };
+
+    let record = match state.submissions.get(&key, &id) {
+        Ok(Some(record)) => record,
+        Ok(None) => return (StatusCode::NOT_FOUND, page("Not found", "<p>This submission does not exist.</p>")).into_response(),
+        Err(e) => {
+            error!("Failed to load submission {} for the dashboard: {}", id, e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    let submission = &record.submission;
+
+    let mut body = page_header(&data);
+    let _ = write!(body, "<p><a href=\"{}/dashboard\">&larr; All submissions</a></p>", base_url());
+    let _ = write!(
+        body,
+        "<h2>{} on {}</h2><table>\
+        <tr><th>Tester</th><td>{} <span class=\"muted\">{}</span></td></tr>\
+        <tr><th>Survey</th><td>{}{}</td></tr>\
+        <tr><th>Submitted</th><td>{}</td></tr>\
+        <tr><th>Game time</th><td>{:.1}s</td></tr>\
+        <tr><th>Raw JSON</th><td><a href=\"{}\">{}.json</a></td></tr></table>",
+        escape_html(&submission.user_name), escape_html(&submission.map_name),
+        escape_html(&submission.user_name), escape_html(&submission.user_xuid),
+        escape_html(&submission.survey_id),
+        record.survey_version.map(|v| format!(" <span class=\"muted\">v{}</span>", v)).unwrap_or_default(),
+        format_time(submission.submission_timestamp),
+        submission.game_timestamp,
+        escape_html(&state.links.data_link(&record.id).url), record.id,
+    );
+
+    body.push_str("<h2>Answers</h2><table><tr><th>Question</th><th>Answer</th></tr>");
+    for (question, answer) in &submission.answers {
+        let _ = write!(body, "<tr><td>{}</td><td><pre>{}</pre></td></tr>", escape_html(question), escape_html(answer));
+    }
+    body.push_str("</table>");
+
+    let files = attached_files(&state, &record);
+    body.push_str("<h2>Attachments</h2>");
+    if files.is_empty() {
+        body.push_str("<p class=\"muted\">No attachments.</p>");
+    } else {
+        body.push_str("<table><tr><th>File</th><th>Size</th><th>Status</th></tr>");
+        for file in &files {
+            let name = match file.status {
+                FileStatus::Active => format!(
+                    "<a href=\"{}\">{}</a>",
+                    escape_html(&state.links.data_link(&file.id).url), escape_html(&file.original_name)
+                ),
+                FileStatus::Expired { .. } => escape_html(&file.original_name),
+            };
+            let _ = write!(body, "<tr><td>{}</td><td>{}</td><td>{}</td></tr>", name, format_size(file.size_bytes), file_status(file));
+        }
+        body.push_str("</table>");
+    }
+
+    page(&format!("{} on {}", submission.user_name, submission.map_name), &body).into_response()
+}
@@ -0,0 +1,270 @@
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use zip::ZipArchive;
+
+const DEMO_MAGIC: &[u8; 8] = b"HL2DEMO\0";
+const PATH_LEN: usize = 260; // MAX_OSPATH, the size of each string field
+// Signature, demo and network protocol, server/client/map/game dir, playback time, ticks, frames, signon length
+const HEADER_LEN: usize = 8 + 4 + 4 + 4 * PATH_LEN + 4 + 4 + 4 + 4;
+const MAX_ARCHIVE_ENTRIES: usize = 256; // pack_demos writes one entry per demo of a session
+const MAX_LISTED_DEMOS: usize = 64;
+
+/// The header every Source engine demo starts with
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DemoHeader {
+    pub file_name: String,
+    pub demo_protocol: i32,
+    pub network_protocol: i32,
+    pub server_name: String,
+    pub client_name: String,
+    pub map_name: String,
+    pub game_dir: String,
+    pub playback_secs: f32,
+    pub ticks: i32,
+    pub frames: i32,
+}
+
+impl DemoHeader {
+    /// Parses the first `HEADER_LEN` bytes of a `.dem` file
+    pub fn parse(file_name: &str, bytes: &[u8]) -> Result<Self, String> {
+        if bytes.len() < HEADER_LEN {
+            return Err(format!("{} bytes is too short for a demo header", bytes.len()));
+        }
+        if &bytes[..8] != DEMO_MAGIC {
+            return Err("not a Source demo (missing HL2DEMO signature)".to_string());
+        }
+
+        let word = |offset: usize| -> [u8; 4] { bytes[offset..offset + 4].try_into().unwrap() };
+        let string = |index: usize| {
+            let field = &bytes[16 + index * PATH_LEN..16 + (index + 1) * PATH_LEN];
+            let end = field.iter().position(|&b| b == 0).unwrap_or(PATH_LEN);
+            String::from_utf8_lossy(&field[..end]).into_owned()
+        };
+        let tail = 16 + 4 * PATH_LEN;
+        let playback_secs = f32::from_le_bytes(word(tail));
+        let ticks = i32::from_le_bytes(word(tail + 4));
+        let frames = i32::from_le_bytes(word(tail + 8));
+
+        // A demo the game stopped writing mid-way still has a usable header, a nonsensical one does not
+        if !playback_secs.is_finite() || playback_secs < 0.0 || ticks < 0 || frames < 0 {
+            return Err("demo header has invalid playback time or tick count".to_string());
+        }
+
+        Ok(Self {
+            file_name: file_name.to_string(),
+            demo_protocol: i32::from_le_bytes(word(8)),
+            network_protocol: i32::from_le_bytes(word(12)),
+            server_name: string(0),
+            client_name: string(1),
+            map_name: string(2),
+            game_dir: string(3),
+            playback_secs,
+            ticks,
+            frames,
+        })
+    }
+}
+
+/// What was found in an uploaded demo or demo archive, stored with its `FileMetadata`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum DemoInfo {
+    Parsed {
+        demos: Vec<DemoHeader>,
+        #[serde(default)]
+        invalid: Vec<String>, // `.dem` entries whose header could not be read
+    },
+    Rejected { reason: String },
+}
+
+/// Reads the demo headers of an uploaded `.dem` or `.zip`. Returns `None` for ZIPs without demos.
+///
+/// Only the central directory and the first bytes of each `.dem` entry are read, so large or
+/// highly compressed archives cost no more than small ones. Archives over `max_bytes`, with too many
+/// entries or that are not valid ZIPs are rejected without being inspected further.
+pub fn inspect(path: &Path, original_name: &str, max_bytes: u64) -> Option<DemoInfo> {
+    let extension = Path::new(original_name).extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase());
+    let result = match extension.as_deref() {
+        Some("dem") => read_header(File::open(path), original_name).map(|header| DemoInfo::Parsed { demos: vec![header], invalid: Vec::new() }),
+        Some("zip") => inspect_archive(path, max_bytes),
+        _ => return None,
+    };
+
+    match result {
+        Ok(DemoInfo::Parsed { demos, invalid }) if demos.is_empty() && invalid.is_empty() => None,
+        Ok(info) => Some(info),
+        Err(reason) => Some(DemoInfo::Rejected { reason }),
+    }
+}
+
+fn read_header(reader: io::Result<impl Read>, file_name: &str) -> Result<DemoHeader, String> {
+    let mut bytes = Vec::with_capacity(HEADER_LEN);
+    reader.map_err(|e| e.to_string())?
+        .take(HEADER_LEN as u64)
+        .read_to_end(&mut bytes)
+        .map_err(|e| e.to_string())?;
+    DemoHeader::parse(file_name, &bytes)
+}
+
+fn inspect_archive(path: &Path, max_bytes: u64) -> Result<DemoInfo, String> {
+    let size = std::fs::metadata(path).map_err(|e| e.to_string())?.len();
+    if size > max_bytes {
+        return Err(format!("archive is {} MB, demo headers are only read up to {} MB", size / (1024 * 1024), max_bytes / (1024 * 1024)));
+    }
+
+    let mut archive = ZipArchive::new(File::open(path).map_err(|e| e.to_string())?)
+        .map_err(|e| format!("not a valid ZIP archive: {}", e))?;
+    if archive.len() > MAX_ARCHIVE_ENTRIES {
+        return Err(format!("archive has {} entries, at most {} are inspected", archive.len(), MAX_ARCHIVE_ENTRIES));
+    }
+
+    let mut demos = Vec::new();
+    let mut invalid = Vec::new();
+    for index in 0..archive.len() {
+        let entry = archive.by_index(index);
+        let name = match &entry {
+            Ok(entry) if entry.is_dir() => continue,
+            Ok(entry) => entry.name().to_string(),
+            Err(e) => {
+                invalid.push(format!("entry {}: {}", index, e));
+                continue;
+            }
+        };
+        if !name.to_ascii_lowercase().ends_with(".dem") {
+            continue;
+        }
+        if demos.len() >= MAX_LISTED_DEMOS {
+            invalid.push(format!("{}: more than {} demos in the archive", name, MAX_LISTED_DEMOS));
+            continue;
+        }
+
+        let file_name = Path::new(&name).file_name().and_then(|n| n.to_str()).unwrap_or(&name).to_string();
+        match read_header(entry.map_err(io::Error::other), &file_name) {
+            Ok(header) => demos.push(header),
+            Err(e) => invalid.push(format!("{}: {}", file_name, e)),
+        }
+    }
+
+    Ok(DemoInfo::Parsed { demos, invalid })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+
+    fn header(map_name: &str, playback_secs: f32, ticks: i32) -> Vec<u8> {
+        let mut bytes = DEMO_MAGIC.to_vec();
+        bytes.extend(4i32.to_le_bytes());
+        bytes.extend(24i32.to_le_bytes());
+        for field in ["Playtest Server", "Tester", map_name, "portal2"] {
+            let mut padded = field.as_bytes().to_vec();
+            padded.resize(PATH_LEN, 0);
+            bytes.extend(padded);
+        }
+        bytes.extend(playback_secs.to_le_bytes());
+        bytes.extend(ticks.to_le_bytes());
+        bytes.extend(ticks.max(0).to_le_bytes());
+        bytes.extend(0i32.to_le_bytes());
+        bytes
+    }
+
+    fn zip_of(entries: &[(&str, Vec<u8>)]) -> Vec<u8> {
+        let mut zip = ZipWriter::new(io::Cursor::new(Vec::new()));
+        for (name, content) in entries {
+            zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(content).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn parse_headers() {
+        let valid = header("sp_a1_intro1", 12.5, 750);
+        let mut bad_magic = valid.clone();
+        bad_magic[..8].copy_from_slice(b"NOTADEMO");
+
+        let cases: [(&str, Vec<u8>, Result<&str, &str>); 5] = [
+            ("valid", valid.clone(), Ok("sp_a1_intro1")),
+            ("truncated", valid[..HEADER_LEN - 1].to_vec(), Err("too short")),
+            ("bad magic", bad_magic, Err("missing HL2DEMO signature")),
+            ("negative ticks", header("sp_a1_intro1", 12.5, -1), Err("invalid playback time")),
+            ("NaN playback time", header("sp_a1_intro1", f32::NAN, 750), Err("invalid playback time")),
+        ];
+        for (case, bytes, expected) in cases {
+            match (DemoHeader::parse("a.dem", &bytes), expected) {
+                (Ok(parsed), Ok(map_name)) => {
+                    assert_eq!(parsed.map_name, map_name, "{}", case);
+                    assert_eq!((parsed.server_name.as_str(), parsed.game_dir.as_str()), ("Playtest Server", "portal2"), "{}", case);
+                    assert_eq!((parsed.demo_protocol, parsed.network_protocol), (4, 24), "{}", case);
+                    assert_eq!((parsed.playback_secs, parsed.ticks, parsed.frames), (12.5, 750, 750), "{}", case);
+                }
+                (Err(e), Err(reason)) => assert!(e.contains(reason), "{}: {}", case, e),
+                (result, _) => panic!("{}: unexpected {:?}", case, result),
+            }
+        }
+    }
+
+    /// What `inspect` should make of a file: its parsed demos and invalid entries, a rejection, or nothing
+    #[derive(Debug)]
+    enum Expected {
+        Parsed(&'static [&'static str], usize),
+        Rejected(&'static str),
+        Ignored,
+    }
+
+    #[test]
+    fn inspect_uploads() {
+        let many_entries: Vec<(String, Vec<u8>)> = (0..=MAX_ARCHIVE_ENTRIES).map(|i| (format!("{}.txt", i), Vec::new())).collect();
+        let many_entries: Vec<(&str, Vec<u8>)> = many_entries.iter().map(|(name, content)| (name.as_str(), content.clone())).collect();
+        let cases: Vec<(&str, &str, Vec<u8>, u64, Expected)> = vec![
+            ("single demo", "a.dem", header("sp_a1_intro1", 1.0, 60), 1024, Expected::Parsed(&["sp_a1_intro1"], 0)),
+            ("broken demo", "a.DEM", b"HL2DEMO\0".to_vec(), 1024, Expected::Rejected("too short")),
+            (
+                "archive",
+                "session.zip",
+                zip_of(&[
+                    ("demos/1.dem", header("sp_a1_intro1", 1.0, 60)),
+                    ("demos/2.dem", header("sp_a1_intro2", 1.0, 60)),
+                    ("demos/3.dem", b"junk".to_vec()),
+                    ("console.log", b"log".to_vec()),
+                ]),
+                u64::MAX,
+                Expected::Parsed(&["sp_a1_intro1", "sp_a1_intro2"], 1),
+            ),
+            ("archive without demos", "logs.zip", zip_of(&[("console.log", b"log".to_vec())]), u64::MAX, Expected::Ignored),
+            (
+                "archive over max_bytes",
+                "big.zip",
+                zip_of(&[("1.dem", header("sp_a1_intro1", 1.0, 60))]),
+                16,
+                Expected::Rejected("demo headers are only read up to"),
+            ),
+            ("too many entries", "many.zip", zip_of(&many_entries), u64::MAX, Expected::Rejected("entries, at most")),
+            ("not a ZIP", "fake.zip", b"just text".to_vec(), u64::MAX, Expected::Rejected("not a valid ZIP archive")),
+            ("other file", "clip.mp4", b"video".to_vec(), u64::MAX, Expected::Ignored),
+        ];
+
+        let dir = test_support::temp_dir();
+        for (case, name, content, max_bytes, expected) in cases {
+            let path = dir.join(name);
+            std::fs::write(&path, content).unwrap();
+            match (inspect(&path, name, max_bytes), &expected) {
+                (Some(DemoInfo::Parsed { demos, invalid }), Expected::Parsed(maps, invalid_count)) => {
+                    assert_eq!(demos.iter().map(|d| d.map_name.as_str()).collect::<Vec<_>>(), *maps, "{}", case);
+                    assert_eq!(invalid.len(), *invalid_count, "{}: {:?}", case, invalid);
+                }
+                (Some(DemoInfo::Rejected { reason }), Expected::Rejected(expected)) => {
+                    assert!(reason.contains(expected), "{}: {}", case, reason);
+                }
+                (None, Expected::Ignored) => {}
+                (result, _) => panic!("{}: expected {:?}, got {:?}", case, expected, result),
+            }
+        }
+    }
+}
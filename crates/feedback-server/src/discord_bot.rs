@@ -1,6 +1,18 @@
-use crate::models::{ModeratorKeyData, SubmissionEvent};
+use crate::bug_status::{self, BugState, BugStatus};
+use crate::duplicates::DuplicateMatch;
+use crate::demos::DemoInfo;
+use crate::file_manager::{FileMetadata, FileStatus};
+use crate::links::LinkSigner;
+use crate::log_triage::LogFindings;
+use crate::models::{FormSubmission, ModeratorKeyData, SubmissionEvent, WebhookConfig};
+use crate::notifier::Notifier;
+use crate::previews::PreviewKind;
 use crate::state::ServerState;
-use serenity::all::{Colour, Command, CommandDataOptionValue, CommandOptionType, CreateCommand, CreateCommandOption, CreateMessage, Interaction, Permissions, ResolvedOption, ResolvedValue};
+use crate::export_jobs::{self, ExportFilters, ExportJob, ExportStatus, StartError};
+use crate::exporter::ExportFormat;
+use crate::submission_store::{StoredSubmission, SubmissionFilter, SubmissionStore};
+use crate::surveys::{self, Question, StoredDefinition};
+use serenity::all::{ButtonStyle, Channel, ChannelId, ChannelType, Colour, Command, CommandDataOptionValue, CommandOptionType, ComponentInteraction, ComponentInteractionDataKind, CreateActionRow, CreateButton, CreateCommand, CreateCommandOption, CreateForumPost, CreateMessage, CreateSelectMenu, CreateSelectMenuKind, CreateThread, Embed, EmbedField, ForumTag, ForumTagId, Interaction, MessageId, PartialChannel, Permissions, ResolvedOption, RoleId, ResolvedValue};
 use serenity::async_trait;
 use serenity::builder::{CreateEmbed, CreateInteractionResponse, CreateInteractionResponseMessage};
 use serenity::http::Http;
@@ -8,7 +20,7 @@ use serenity::model::gateway::Ready;
 use serenity::prelude::*;
 use zip::write::FileOptions;
 use std::sync::Arc;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use std::collections::{HashMap, HashSet};
 use tracing::{error, info, warn};
 use uuid::Uuid;
@@ -35,8 +47,79 @@ impl EventHandler for BotHandler {
                         error!("Failed to handle stats command: {}", e);
                     }
                 }
+                "revoke_key" => {
+                    if let Err(e) = handle_revoke_key(&ctx, &command).await {
+                        error!("Failed to handle revoke_key command: {}", e);
+                    }
+                }
+                "rotate_key" => {
+                    if let Err(e) = handle_rotate_key(&ctx, &command).await {
+                        error!("Failed to handle rotate_key command: {}", e);
+                    }
+                }
+                "list_keys" => {
+                    if let Err(e) = handle_list_keys(&ctx, &command).await {
+                        error!("Failed to handle list_keys command: {}", e);
+                    }
+                }
+                "add_webhook" => {
+                    if let Err(e) = handle_add_webhook(&ctx, &command).await {
+                        error!("Failed to handle add_webhook command: {}", e);
+                    }
+                }
+                "remove_webhook" => {
+                    if let Err(e) = handle_remove_webhook(&ctx, &command).await {
+                        error!("Failed to handle remove_webhook command: {}", e);
+                    }
+                }
+                "list_webhooks" => {
+                    if let Err(e) = handle_list_webhooks(&ctx, &command).await {
+                        error!("Failed to handle list_webhooks command: {}", e);
+                    }
+                }
+                "dashboard_link" => {
+                    if let Err(e) = handle_dashboard_link(&ctx, &command).await {
+                        error!("Failed to handle dashboard_link command: {}", e);
+                    }
+                }
+                "set_forum" => {
+                    if let Err(e) = handle_set_forum(&ctx, &command).await {
+                        error!("Failed to handle set_forum command: {}", e);
+                    }
+                }
+                "auto_threads" => {
+                    if let Err(e) = handle_auto_threads(&ctx, &command).await {
+                        error!("Failed to handle auto_threads command: {}", e);
+                    }
+                }
+                "triager_role" => {
+                    if let Err(e) = handle_triager_role(&ctx, &command).await {
+                        error!("Failed to handle triager_role command: {}", e);
+                    }
+                }
+                "open_bugs" => {
+                    if let Err(e) = handle_open_bugs(&ctx, &command).await {
+                        error!("Failed to handle open_bugs command: {}", e);
+                    }
+                }
                 _ => {}
             }
+        } else if let Interaction::Component(component) = interaction {
+            let custom_id = component.data.custom_id.as_str();
+            let result = if let Some(id) = custom_id.strip_prefix(REFRESH_LINKS_PREFIX) {
+                handle_refresh_links(&ctx, &component, id).await
+            } else if let Some(filename) = custom_id.strip_prefix(REFRESH_EXPORT_PREFIX) {
+                handle_refresh_export(&ctx, &component, filename).await
+            } else if let Some(rest) = custom_id.strip_prefix(BUG_STATUS_PREFIX) {
+                handle_bug_status(&ctx, &component, rest).await
+            } else if let Some(id) = custom_id.strip_prefix(BUG_ASSIGN_PREFIX) {
+                handle_bug_assign(&ctx, &component, id).await
+            } else {
+                Ok(())
+            };
+            if let Err(e) = result {
+                error!("Failed to handle button {}: {}", custom_id, e);
+            }
         }
     }
 
@@ -45,9 +128,131 @@ impl EventHandler for BotHandler {
 
         let commands = vec![
             CreateCommand::new("generate_key")
-                .description("Generates a new moderator key for this server and channel."),
+                .description("Generates a new moderator key for this server and channel.")
+                .add_option(CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "label",
+                    "A name for the key, shown in /list_keys (e.g., the mod's name)"
+                ).required(false)),
+            CreateCommand::new("revoke_key")
+                .description("Revokes a moderator key immediately. Clients using it can no longer submit.")
+                .default_member_permissions(Permissions::MANAGE_GUILD)
+                .add_option(CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "key",
+                    "The key or its first 8 characters (default: the key of this channel)"
+                ).required(false)),
+            CreateCommand::new("rotate_key")
+                .description("Issues a new key for this channel. The old key keeps working for a grace period.")
+                .default_member_permissions(Permissions::MANAGE_GUILD)
+                .add_option(CreateCommandOption::new(
+                    CommandOptionType::Integer,
+                    "grace_hours",
+                    "How long the old key stays valid (default: 24, 0 revokes it right away)"
+                ).min_int_value(0).max_int_value(MAX_ROTATION_GRACE_HOURS as u64).required(false))
+                .add_option(CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "label",
+                    "A new label for the key (default: keep the current one)"
+                ).required(false)),
+            CreateCommand::new("list_keys")
+                .description("Lists the moderator keys of this server with their status.")
+                .default_member_permissions(Permissions::MANAGE_GUILD),
+            CreateCommand::new("add_webhook")
+                .description("Sends every new submission of this channel's key to a URL as a JSON POST.")
+                .default_member_permissions(Permissions::MANAGE_GUILD)
+                .add_option(CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "url",
+                    "The http(s) URL to POST submissions to"
+                ).required(true))
+                .add_option(CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "secret",
+                    "Signs requests with HMAC-SHA256 in the X-Webhook-Signature header"
+                ).required(false)),
+            CreateCommand::new("remove_webhook")
+                .description("Stops sending submissions to a webhook.")
+                .default_member_permissions(Permissions::MANAGE_GUILD)
+                .add_option(CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "id",
+                    "The webhook ID or its first 8 characters, see /list_webhooks"
+                ).required(true)),
+            CreateCommand::new("list_webhooks")
+                .description("Lists the webhooks of this channel's key and their recent deliveries.")
+                .default_member_permissions(Permissions::MANAGE_GUILD),
+            CreateCommand::new("dashboard_link")
+                .description("Sends you a one-time login link to the web dashboard of this channel's key.")
+                .default_member_permissions(Permissions::MANAGE_GUILD),
+            CreateCommand::new("set_forum")
+                .description("Posts this channel's submissions to a forum, one post each. Leave out the forum to stop.")
+                .default_member_permissions(Permissions::MANAGE_GUILD)
+                .add_option(CreateCommandOption::new(
+                    CommandOptionType::Channel,
+                    "forum",
+                    "The forum channel to create posts in (default: post to this channel again)"
+                ).channel_types(vec![ChannelType::Forum]).required(false))
+                .add_option(CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "tag_questions",
+                    "Comma-separated questions whose answers pick the post's tags (e.g., Category, Impact)"
+                ).required(false)),
+            CreateCommand::new("auto_threads")
+                .description("Starts a thread on every submission posted to this channel.")
+                .default_member_permissions(Permissions::MANAGE_GUILD)
+                .add_option(CreateCommandOption::new(
+                    CommandOptionType::Boolean,
+                    "enabled",
+                    "Whether to start a thread per submission"
+                ).required(true)),
+            CreateCommand::new("triager_role")
+                .description("Lets a role change the status and assignee of bug reports. Leave out the role to stop.")
+                .default_member_permissions(Permissions::MANAGE_GUILD)
+                .add_option(CreateCommandOption::new(
+                    CommandOptionType::Role,
+                    "role",
+                    "The role of your triagers (default: only members with Manage Messages)"
+                ).required(false)),
+            CreateCommand::new("open_bugs")
+                .description("Lists the bug reports of this channel that are not fixed, rejected or duplicates yet.")
+                .add_option(CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "map_name",
+                    "Only list bug reports from this map"
+                ).required(false)),
             CreateCommand::new("export_data")
-                .description("Exports all survey data and files for this channel into a ZIP archive."),
+                .description("Exports survey data for this channel as a ZIP archive, CSV or NDJSON.")
+                .add_option(CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "format",
+                    "Export format (default: ZIP with raw JSONs and files). CSV/NDJSON are split per survey."
+                ).add_string_choice("ZIP", "zip").add_string_choice("CSV", "csv").add_string_choice("NDJSON", "ndjson").required(false))
+                .add_option(CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "survey_id",
+                    "Only export this survey (e.g., default.json)"
+                ).required(false))
+                .add_option(CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "map_name",
+                    "Only export submissions from this map"
+                ).required(false))
+                .add_option(CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "from",
+                    "Only export submissions made on or after this date (YYYY-MM-DD, UTC)"
+                ).required(false))
+                .add_option(CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "to",
+                    "Only export submissions made on or before this date (YYYY-MM-DD, UTC)"
+                ).required(false))
+                .add_option(CreateCommandOption::new(
+                    CommandOptionType::Boolean,
+                    "since_last_export",
+                    "Only export submissions received since the last export with the same survey and map"
+                ).required(false)),
             CreateCommand::new("stats")
                 .description("Analyzes numerical answers for a specific survey.")
                 .add_option(CreateCommandOption::new(
@@ -94,18 +299,15 @@ async fn handle_generate_key(ctx: &Context, command: &serenity::all::CommandInte
         }
     };
 
-    let channel_id_str = command.channel_id.to_string();
-
-    let mut existing_key = None;
-    for entry in state.key_store.iter() {
-        if entry.value().channel_id == channel_id_str {
-            existing_key = Some(entry.key().clone());
-            break;
+    let mut label = None;
+    for opt in &command.data.options {
+        if let ("label", CommandDataOptionValue::String(s)) = (opt.name.as_str(), &opt.value) {
+            label = Some(s.trim().to_string()).filter(|s| !s.is_empty());
         }
     }
 
     // Already have a key for this channel
-    if let Some(key) = existing_key {
+    if let Some((key, _)) = state.key_for_channel(&command.channel_id.to_string()) {
         let response_content = format!(
             "⚠️ **You already have a key for this channel!**\n\n\
             Your mod-key:\n```\n{}\n```",
@@ -160,7 +362,9 @@ async fn handle_generate_key(ctx: &Context, command: &serenity::all::CommandInte
         guild_id: guild_id.to_string(),
         channel_id: command.channel_id.to_string(),
         server_name: guild_name.clone(),
-        is_priority: false,
+        label,
+        created_at: Some(now_secs()),
+        ..Default::default()
     };
 
     state.key_store.insert(new_key.clone(), key_data);
@@ -186,6 +390,22 @@ async fn handle_generate_key(ctx: &Context, command: &serenity::all::CommandInte
     Ok(())
 }
 
+/// Parses a `YYYY-MM-DD` date as the unix timestamp of its start (UTC)
+fn parse_date(date: &str) -> Option<u64> {
+    let mut parts = date.trim().splitn(3, '-').map(|p| p.parse::<i64>().ok());
+    let (year, month, day) = (parts.next()??, parts.next()??, parts.next()??);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    crate::dates::date_start(year, month, day)
+}
+
+fn export_progress_message(job: &ExportJob) -> String {
+    let percent = (job.processed * 100).checked_div(job.total).unwrap_or(100);
+    format!("⏳ Exporting {} submissions as {}... {}/{} ({}%)", job.total, job.format.label(), job.processed, job.total, percent)
+}
+
 async fn handle_export_data(ctx: &Context, command: &serenity::all::CommandInteraction) -> Result<(), serenity::Error> {
     // We acknowledge the command immediately because zipping takes time
     let defer_builder = CreateInteractionResponse::Defer(CreateInteractionResponseMessage::new().ephemeral(false));
@@ -196,76 +416,112 @@ async fn handle_export_data(ctx: &Context, command: &serenity::all::CommandInter
         data.get::<ServerState>().cloned().expect("ServerState not found in TypeMap")
     };
 
-    // Find the mod_key associated with this channel
-    let mut target_key = None;
-    for entry in state.key_store.iter() {
-        if entry.value().channel_id == command.channel_id.to_string() {
-            target_key = Some(entry.key().clone());
-            break;
+    let mut format = ExportFormat::default();
+    let mut filters = ExportFilters::default();
+    for opt in &command.data.options {
+        match (opt.name.as_str(), &opt.value) {
+            ("format", CommandDataOptionValue::String(s)) => format = ExportFormat::parse(s).unwrap_or_default(),
+            ("survey_id", CommandDataOptionValue::String(s)) => filters.survey_id = Some(s.to_string()),
+            ("map_name", CommandDataOptionValue::String(s)) => filters.map_name = Some(s.to_string()),
+            ("since_last_export", CommandDataOptionValue::Boolean(b)) => filters.since_last_export = *b,
+            ("from" | "to", CommandDataOptionValue::String(s)) => {
+                let Some(day_start) = parse_date(s) else {
+                    let message = format!("❌ `{}` is not a valid date, use YYYY-MM-DD.", s);
+                    command.edit_response(&ctx.http, serenity::builder::EditInteractionResponse::new().content(message)).await?;
+                    return Ok(());
+                };
+                // Both ends are inclusive, `to` covers its whole day
+                if opt.name == "from" {
+                    filters.from = Some(day_start);
+                } else {
+                    filters.to = Some(day_start + 86399);
+                }
+            }
+            _ => {}
         }
     }
+    if let (Some(from), Some(to)) = (filters.from, filters.to)
+        && from > to {
+        command.edit_response(&ctx.http, serenity::builder::EditInteractionResponse::new().content("❌ `from` must not be after `to`.")).await?;
+        return Ok(());
+    }
 
-    let mod_key = match target_key {
-        Some(k) => k,
+    // Find the mod_key associated with this channel
+    let mod_key = match state.key_for_channel(&command.channel_id.to_string()) {
+        Some((k, _)) => k,
         None => {
             command.edit_response(&ctx.http, serenity::builder::EditInteractionResponse::new().content("❌ No moderator key is bound to this channel.")).await?;
             return Ok(());
         }
     };
 
-    let base_dir = state.file_manager.base_dir.clone();
-    let export_dir = base_dir.join("EXPORTS");
-    
-    let export_id = Uuid::new_v4();
-    let zip_filename = format!("{}.zip", export_id);
-    let zip_path = export_dir.join(&zip_filename);
-    let answers_dir = base_dir.join("ANSWERS").join(&mod_key);
-
-    if !answers_dir.exists() {
-        command.edit_response(&ctx.http, serenity::builder::EditInteractionResponse::new().content("❌ No data has been collected for this key yet.")).await?;
-        return Ok(());
-    }
-
-    // Move heavy zip operation to a blocking thread to avoid freezing the async runtime
-    let zip_result = tokio::task::spawn_blocking(move || -> Result<(), String> {
-        let file = std::fs::File::create(&zip_path).map_err(|e| e.to_string())?;
-        let mut zip = zip::ZipWriter::new(file);
-        let options = zip::write::SimpleFileOptions::default();
-
-        let walker = walkdir::WalkDir::new(&answers_dir);
-        for entry in walker.into_iter().filter_map(|e| e.ok()) {
-            let path = entry.path();
-            let name = path.strip_prefix(&answers_dir).unwrap().to_str().unwrap();
-
-            if path.is_file() {
-                zip.start_file(name, options).map_err(|e| e.to_string())?;
-                let mut f = std::fs::File::open(path).map_err(|e| e.to_string())?;
-                std::io::copy(&mut f, &mut zip).map_err(|e| e.to_string())?;
-            } else if !name.is_empty() {
-                zip.add_directory(name, options).map_err(|e| e.to_string())?;
+    let (job, mut handle) = match export_jobs::start_export(&state, &mod_key, format, filters) {
+        Ok(started) => started,
+        Err(e) => {
+            let message = match e {
+                StartError::NoSubmissions => "❌ No submissions match these filters.".to_string(),
+                StartError::NothingNew { last_export_at } => format!("✅ Nothing new since the last export <t:{}:R>.", last_export_at),
+                StartError::Index(e) => {
+                    error!("Failed to start export: {}", e);
+                    "❌ Failed to read the submission index.".to_string()
+                }
+            };
+            command.edit_response(&ctx.http, serenity::builder::EditInteractionResponse::new().content(message)).await?;
+            return Ok(());
+        }
+    };
+    command.edit_response(&ctx.http, serenity::builder::EditInteractionResponse::new().content(export_progress_message(&job))).await?;
+
+    // Report progress while the export runs, Discord rate limits edits so a few seconds apart is enough
+    let mut ticker = tokio::time::interval(Duration::from_secs(3));
+    ticker.tick().await;
+    let mut last_processed = job.processed;
+    let job = loop {
+        tokio::select! {
+            finished = &mut handle => break finished.ok(),
+            _ = ticker.tick() => {
+                if let Some(progress) = state.file_manager.exports.get(&job.id)
+                    && progress.processed != last_processed {
+                    last_processed = progress.processed;
+                    command.edit_response(&ctx.http, serenity::builder::EditInteractionResponse::new().content(export_progress_message(&progress))).await?;
+                }
             }
         }
-        zip.finish().map_err(|e| e.to_string())?;
-        Ok(())
-    }).await.unwrap_or_else(|e| Err(format!("Task panicked: {}", e)));
+    };
 
-    match zip_result {
-        Ok(_) => {
-            let base_url = std::env::var("BASE_URL").expect("Expected BASE_URL in the environment");
-            // Since EXPORTS is served directly or via a special endpoint, we just construct the URL
-            let download_url = format!("{}/exports/{}", base_url, zip_filename);
+    match job {
+        Some(ExportJob { status: ExportStatus::Completed, filename: Some(export_filename), total, expires_at, since_seq, .. }) => {
+            let download_link = state.links.export_link(&export_filename);
+            let archive_kind = if export_filename.ends_with(".zip") { "ZIP" } else { format.label() };
+            let scope = if since_seq.is_some() { "new submissions since the last export" } else { "submissions" };
+
+            let mut link_text = format!("[Click here to download {}]({})\nLink expires <t:{}:R>", archive_kind, download_link.url, download_link.expires_at);
+            if let Some(expires_at) = expires_at {
+                link_text.push_str(&format!(", the export is deleted <t:{}:R>", expires_at));
+            }
 
             let embed = CreateEmbed::new()
                 .title("📦 Data Export Complete")
                 .color(0x00FF00)
-                .description("Your data has been successfully packaged.")
-                .field("Download Link", format!("[Click here to download ZIP]({})", download_url), false)
-                .footer(serenity::builder::CreateEmbedFooter::new("This link will expire in 14 days."));
-
-            command.edit_response(&ctx.http, serenity::builder::EditInteractionResponse::new().add_embed(embed)).await?;
+                .description(format!("Your data has been successfully packaged: {} {}.", total, scope))
+                .field("Download Link", link_text, false)
+                .footer(serenity::builder::CreateEmbedFooter::new("Use \"Refresh link\" to get a new download link."));
+
+            let button = CreateButton::new(format!("{}{}", REFRESH_EXPORT_PREFIX, export_filename))
+                .label("Refresh link")
+                .emoji('🔄')
+                .style(ButtonStyle::Secondary);
+            let response = serenity::builder::EditInteractionResponse::new()
+                .content("")
+                .add_embed(embed)
+                .components(vec![CreateActionRow::Buttons(vec![button])]);
+            command.edit_response(&ctx.http, response).await?;
         }
-        Err(e) => {
-            command.edit_response(&ctx.http, serenity::builder::EditInteractionResponse::new().content(format!("❌ Failed to create zip: {}", e))).await?;
+        Some(ExportJob { status: ExportStatus::Failed { error }, .. }) => {
+            command.edit_response(&ctx.http, serenity::builder::EditInteractionResponse::new().content(format!("❌ Failed to create export: {}", error))).await?;
+        }
+        _ => {
+            command.edit_response(&ctx.http, serenity::builder::EditInteractionResponse::new().content("❌ Failed to create export.")).await?;
         }
     }
     Ok(())
@@ -308,16 +564,8 @@ async fn handle_stats(ctx: &Context, command: &serenity::all::CommandInteraction
         }
     }
 
-    let mut target_key = None;
-    for entry in state.key_store.iter() {
-        if entry.value().channel_id == command.channel_id.to_string() {
-            target_key = Some(entry.key().clone());
-            break;
-        }
-    }
-
-    let mod_key = match target_key {
-        Some(k) => k,
+    let mod_key = match state.key_for_channel(&command.channel_id.to_string()) {
+        Some((k, _)) => k,
         None => {
             let builder = CreateInteractionResponse::Message(CreateInteractionResponseMessage::new().content("❌ No moderator key is bound to this channel.").ephemeral(true));
             command.create_response(&ctx.http, builder).await?;
@@ -325,63 +573,71 @@ async fn handle_stats(ctx: &Context, command: &serenity::all::CommandInteraction
         }
     };
 
-    let answers_dir = state.file_manager.base_dir.join("ANSWERS").join(&mod_key);
-    let mut total_surveys = 0;
+    let filter = SubmissionFilter {
+        survey_id: Some(survey_id.clone()),
+        map_name: target_map.clone(),
+        user_xuid: target_user.clone(),
+        ..Default::default()
+    };
+    let records = match state.submissions.query(&mod_key, &filter) {
+        Ok(records) => records,
+        Err(e) => {
+            error!("Failed to query submissions for stats: {}", e);
+            Vec::new()
+        }
+    };
+    let total_surveys = records.len();
+
+    // Question types come from the registered definitions the submissions answered
+    let definitions = state.submissions.definitions(&mod_key, Some(&survey_id)).unwrap_or_else(|e| {
+        error!("Failed to load survey definitions for stats: {}", e);
+        Vec::new()
+    });
+    let answered_versions: HashSet<u32> = records.iter().filter_map(|r| r.survey_version).collect();
+    let answered_definitions: Vec<&StoredDefinition> = definitions.iter()
+        .filter(|d| answered_versions.contains(&d.version))
+        .collect();
+    let questions = surveys::question_types(&answered_definitions);
 
     // Question -> Group -> Values
     let mut num_stats: HashMap< String, HashMap<String, Vec<f64>> > = HashMap::new();
-    let mut unique_maps = HashSet::new();
+    // Question -> Group -> Choice -> Count
+    let mut choice_stats: HashMap< String, HashMap<String, HashMap<String, usize>> > = HashMap::new();
     let mut group_totals: HashMap<String, usize> = HashMap::new();
 
-    // yeah, call me "the bullshit"
-    if answers_dir.exists() {
-        let walker = walkdir::WalkDir::new(&answers_dir);
-        for entry in walker.into_iter().filter_map(|e| e.ok()) {
-            let path = entry.path();
-            if path.is_file() && path.extension().and_then(|s| s.to_str()) == Some("json") {
-                if let Ok(content) = std::fs::read_to_string(path) {
-                    if let Ok(json) = serde_json::from_str::<serde_json::Value>(&content) {
-                        if json["survey_id"].as_str() != Some(&survey_id) {
-                            continue;
-                        }
-
-                        let map_str = json["map_name"].as_str().unwrap_or("unknown").to_string();
-                        let user_str = json["user_name"].as_str().unwrap_or("unknown").to_string();
-
-                        // Apply filters
-                        if let Some(ref m) = target_map {
-                            if &map_str != m { continue; }
-                        }
-                        if let Some(ref u) = target_user {
-                            if &user_str != u { continue; }
-                        }
-
-                        total_surveys += 1;
-                        unique_maps.insert(map_str.clone());
-
-                        // collect stats by group key
-                        let group_key = match group_by.as_deref() {
-                            Some("map") => map_str,
-                            Some("user") => user_str,
-                            _ => "Overall".to_string(),
-                        };
-
-                        *group_totals.entry(group_key.clone()).or_insert(0) += 1;
-
-                        if let Some(answers) = json["answers"].as_object() {
-                            for (q, a) in answers {
-                                if let Some(val_str) = a.as_str() {
-                                    if let Ok(num) = val_str.parse::<f64>() {
-                                        num_stats.entry(q.clone())
-                                            .or_default()
-                                            .entry(group_key.clone())
-                                            .or_default()
-                                            .push(num);                                    }
-                                }
-                            }
-                        }
+    for record in &records {
+        let submission = &record.submission;
+
+        // collect stats by group key
+        let group_key = match group_by.as_deref() {
+            Some("map") => submission.map_name.clone(),
+            Some("user") => submission.user_name.clone(),
+            _ => "Overall".to_string(),
+        };
+
+        *group_totals.entry(group_key.clone()).or_insert(0) += 1;
+
+        for (q, a) in &submission.answers {
+            let selected: Vec<&str> = match questions.get(q) {
+                Some(Question::RadioChoices { .. }) if !a.is_empty() => vec![a.as_str()],
+                Some(Question::Checkboxes { choices, .. }) => surveys::selected_choices(a, choices),
+                Some(Question::OneToTen { .. }) | None => {
+                    // Without a definition, anything that parses as a number is treated as a rating
+                    if let Ok(num) = a.parse::<f64>() {
+                        num_stats.entry(q.clone())
+                            .or_default()
+                            .entry(group_key.clone())
+                            .or_default()
+                            .push(num);
                     }
+                    continue;
                 }
+                Some(_) => continue,
+            };
+
+            let counts = choice_stats.entry(q.clone()).or_default().entry(group_key.clone()).or_default();
+            for choice in selected {
+                *counts.entry(choice.to_string()).or_insert(0) += 1;
             }
         }
     }
@@ -408,6 +664,10 @@ async fn handle_stats(ctx: &Context, command: &serenity::all::CommandInteraction
     if let Some(ref u) = target_user {
         description.push_str(&format!("\n**Filtered by User:** `{}`", u));
     }
+    if !answered_definitions.is_empty() {
+        let versions: Vec<String> = answered_definitions.iter().map(|d| format!("v{}", d.version)).collect();
+        description.push_str(&format!("\n**Survey Versions:** {}", versions.join(", ")));
+    }
 
     let mut embed = CreateEmbed::new()
         .title(format!("📊 Statistics: {}", survey_id.split('/').last().unwrap_or(&survey_id)))
@@ -427,8 +687,8 @@ async fn handle_stats(ctx: &Context, command: &serenity::all::CommandInteraction
         for (group_name, values) in sorted_groups {
             let total_in_group = group_totals.get(group_name).unwrap_or(&0);
 
-            // Only show stats if more than 50% of the group has numerical data for this question
-            if values.len() as f64 > (*total_in_group as f64 * 0.5) {
+            // Without a definition, only show stats if more than 50% of the group has numerical data for this question
+            if questions.contains_key(q) || values.len() as f64 > (*total_in_group as f64 * 0.5) {
                 let sum: f64 = values.iter().sum();
                 let avg = sum / values.len() as f64;
                 let min = values.iter().fold(f64::INFINITY, |a, &b| a.min(b));
@@ -457,8 +717,47 @@ async fn handle_stats(ctx: &Context, command: &serenity::all::CommandInteraction
         }
     }
 
+    // Choice questions: how often each choice was picked, in the order of the definition
+    for (q, groups) in &choice_stats {
+        let Some(choices) = questions.get(q).and_then(|question| question.choices()) else { continue };
+
+        let mut sorted_groups: Vec<_> = groups.iter().collect();
+        sorted_groups.sort_by(|a, b| a.0.cmp(b.0));
+
+        let mut field_text = String::new();
+        for (group_name, counts) in sorted_groups {
+            let total_in_group = *group_totals.get(group_name).unwrap_or(&0);
+            let percent = |count: usize| count as f64 * 100.0 / total_in_group.max(1) as f64;
+
+            let line = if group_name == "Overall" {
+                choices.iter()
+                    .map(|c| {
+                        let count = counts.get(c).copied().unwrap_or(0);
+                        format!("`{}`: **{}** ({:.0}%)\n", c, count, percent(count))
+                    })
+                    .collect::<String>()
+            } else {
+                let picked: Vec<String> = choices.iter()
+                    .filter_map(|c| counts.get(c).map(|count| format!("{} {:.0}%", c, percent(*count))))
+                    .collect();
+                format!("🔹 `{}`: {}\n", group_name, picked.join(", "))
+            };
+
+            if field_text.len() + line.len() > 1000 {
+                field_text.push_str("...and more\n");
+                break;
+            }
+            field_text.push_str(&line);
+        }
+
+        if !field_text.is_empty() {
+            embed = embed.field(q, field_text, false);
+            added_fields += 1;
+        }
+    }
+
     if added_fields == 0 {
-        embed = embed.field("Notice", "No numerical answers found to analyze. All answers seem to be text.", false);
+        embed = embed.field("Notice", "No numerical or choice answers found to analyze. All answers seem to be text.", false);
     }
 
     let builder = CreateInteractionResponse::Message(CreateInteractionResponseMessage::new().add_embed(embed));
@@ -466,93 +765,1134 @@ async fn handle_stats(ctx: &Context, command: &serenity::all::CommandInteraction
     Ok(())
 }
 
-pub async fn notification_listener(state: ServerState, http: Arc<Http>) {
-    let mut receiver = state.submission_sender.subscribe();
-    info!("Notification listener started.");
+const DEFAULT_ROTATION_GRACE_HOURS: i64 = 24;
+const MAX_ROTATION_GRACE_HOURS: i64 = 24 * 30;
+const KEY_PREFIX_LEN: usize = 8;
 
-    while let Ok(event) = receiver.recv().await {
-        info!("Received event for guild {}", event.destination.guild_id);
-        let submission = &event.submission;
-        let base_url = std::env::var("BASE_URL").expect("Expected BASE_URL in the environment");
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
 
-        // --- METADATA ---
-        // time in game:
-        let game_seconds = submission.game_timestamp;
-        let formatted_game_time = format!("{:.0} min {:.2} sec", (game_seconds / 60.0).floor(), game_seconds % 60.0);
-        // survey name
-        let survey_filename = submission.survey_id.split('/').last().unwrap_or("Survey");
+/// Shows only the beginning of a key, enough to tell keys apart without leaking them
+fn mask_key(key: &str) -> String {
+    format!("{}…", &key[..key.len().min(KEY_PREFIX_LEN)])
+}
 
-        // color
-        let embed_color = match submission.custom_embed_color {
-            Some(color) => color as u32,
-            None => 0x00BFFF
-        };
+async fn respond_ephemeral(ctx: &Context, command: &serenity::all::CommandInteraction, content: impl Into<String>) -> Result<(), serenity::Error> {
+    let builder = CreateInteractionResponse::Message(CreateInteractionResponseMessage::new().content(content).ephemeral(true));
+    command.create_response(&ctx.http, builder).await
+}
 
-        // --- CREATE EMBED ---
-        let mut embed = CreateEmbed::new()
-            .title(format!("New Submission: {}", survey_filename))
-            .description(format!("From user **{}** (`{}`)", submission.user_name, submission.user_xuid))
-            .color(embed_color)
-            .field("Map", format!("`{}`", submission.map_name), true)
-            .field("Game Timestamp", formatted_game_time, true);
+async fn handle_revoke_key(ctx: &Context, command: &serenity::all::CommandInteraction) -> Result<(), serenity::Error> {
+    let state = {
+        let data = ctx.data.read().await;
+        data.get::<ServerState>().cloned().expect("ServerState not found in TypeMap")
+    };
 
-        // section 1: Metadata
-        if !submission.extra_data.is_empty() {
-            for (key, value) in &submission.extra_data {
-                // Format the value nicely, removing quotes from strings.
-                let value_str = format!("`{}`", value.to_string().trim_matches('"'));
-                embed = embed.field(key, value_str, true);
+    let Some(guild_id) = command.guild_id.map(|id| id.to_string()) else {
+        return respond_ephemeral(ctx, command, "This command can only be used in a server.").await;
+    };
+
+    let mut requested = None;
+    for opt in &command.data.options {
+        if let ("key", CommandDataOptionValue::String(s)) = (opt.name.as_str(), &opt.value) {
+            requested = Some(s.trim().to_string());
+        }
+    }
+
+    // Resolve the full key, only among the keys of this guild
+    let target = match requested {
+        Some(requested) => {
+            let matches: Vec<String> = state.key_store.iter()
+                .filter(|entry| entry.value().guild_id == guild_id)
+                .filter(|entry| *entry.key() == requested || (requested.len() >= KEY_PREFIX_LEN && entry.key().starts_with(&requested)))
+                .map(|entry| entry.key().clone())
+                .collect();
+            match matches.as_slice() {
+                [key] => key.clone(),
+                [] => return respond_ephemeral(ctx, command, "❌ No key of this server matches that value.").await,
+                _ => return respond_ephemeral(ctx, command, "❌ Several keys match, please give more characters of the key.").await,
             }
         }
+        None => match state.key_for_channel(&command.channel_id.to_string()) {
+            Some((key, _)) => key,
+            None => return respond_ephemeral(ctx, command, "❌ No moderator key is bound to this channel.").await,
+        },
+    };
 
-        // section 2: Survey Answers
-        embed = embed.field("\u{200B}", "**Survey Answers**", false)
-            .fields(submission.answers.iter().map(|(q, a)| (q.clone(), a.clone(), false)));
+    let revoked: Vec<String> = state.revoke_key(&target, now_secs()).iter().map(|key| mask_key(key)).collect();
 
-        // section 3: files
-        let mut files_text = String::new();
-        files_text.push_str(&format!("📄 [Raw JSON]({}/data/{})\n", base_url, event.submission_id));
-        for file in &event.attached_files {
-            files_text.push_str(&format!("📎 [{}]({}/data/{})\n", file.original_name, base_url, file.id));
+    if revoked.is_empty() {
+        return respond_ephemeral(ctx, command, format!("⚠️ Key `{}` was already revoked.", mask_key(&target))).await;
+    }
+    if let Err(e) = state.save_keys_to_disk() {
+        error!("Failed to save keys after revocation: {}", e);
+    }
+    info!("User {} revoked keys {:?} in guild {}", command.user.id, revoked, guild_id);
+
+    let content = format!(
+        "🔒 **Revoked:** {}\n\
+        Clients using it can no longer submit. Submissions and files collected with it stay with the revoked key, \
+        a key from `/generate_key` starts without them. To replace a key and keep its history, use `/rotate_key` instead.",
+        revoked.iter().map(|k| format!("`{}`", k)).collect::<Vec<_>>().join(", ")
+    );
+    respond_ephemeral(ctx, command, content).await
+}
+
+async fn handle_rotate_key(ctx: &Context, command: &serenity::all::CommandInteraction) -> Result<(), serenity::Error> {
+    let state = {
+        let data = ctx.data.read().await;
+        data.get::<ServerState>().cloned().expect("ServerState not found in TypeMap")
+    };
+
+    let mut grace_hours = DEFAULT_ROTATION_GRACE_HOURS;
+    let mut label = None;
+    for opt in &command.data.options {
+        match (opt.name.as_str(), &opt.value) {
+            ("grace_hours", CommandDataOptionValue::Integer(h)) => grace_hours = (*h).clamp(0, MAX_ROTATION_GRACE_HOURS),
+            ("label", CommandDataOptionValue::String(s)) => label = Some(s.trim().to_string()).filter(|s| !s.is_empty()),
+            _ => {}
         }
+    }
 
-        embed = embed.field("**Files:**", &files_text, false);
+    let Some((old_key, old_data)) = state.key_for_channel(&command.channel_id.to_string()) else {
+        return respond_ephemeral(ctx, command, "❌ No moderator key is bound to this channel.").await;
+    };
 
-        // --- SEND MESSAGE ---
-        let channel_id_u64 = event.destination.channel_id.parse::<u64>().unwrap_or(0);
-        let channel_id = serenity::model::id::ChannelId::new(channel_id_u64);
-        let builder = CreateMessage::new().embed(embed);
-
-        if let Err(why) = channel_id.send_message(&http, builder).await {
-            warn!("Failed to send notification embed to channel {}: {:?}", channel_id, why);
-
-            // If sending the embed fails (e.g., too large), send a fallback message.
-            let fallback_embed = CreateEmbed::new()
-                .title("📄 Submission Received (Manual View Required)")
-                .color(0x99AAB5)
-                .description(format!(
-                    "The full submission for `{}` was received successfully, but it is too large to be displayed as a summary here.",
-                    survey_filename
-                ))
-                .field(
-                    "Submitted By",
-                    format!("**{}** (`{}`)", submission.user_name, submission.user_xuid),
-                    false
-                )
-                .field("Links", files_text, false);
-
-
-            let fallback_builder = CreateMessage::new().embed(fallback_embed);
-            if let Err(fallback_why) = channel_id.send_message(&http, fallback_builder).await {
-                error!(
-                    "Failed to send fallback notification to channel {}: {:?}",
-                    channel_id, fallback_why
-                );
-            } else {
-                info!("Successfully sent fallback message to channel {}", channel_id);
+    let now = now_secs();
+    let new_key = Uuid::new_v4().to_string();
+    let new_data = ModeratorKeyData {
+        owner_id: command.user.id.to_string(),
+        label: label.or(old_data.label.clone()),
+        created_at: Some(now),
+        last_used_at: None,
+        revoked_at: None,
+        expires_at: None,
+        replaced_by: None,
+        ..old_data
+    };
+    let grace_end = now + grace_hours as u64 * 60 * 60;
+    state.rotate_key(&old_key, &new_key, new_data, grace_end);
+    info!("Rotated key {} to {}", mask_key(&old_key), mask_key(&new_key));
+
+    if let Err(e) = state.save_keys_to_disk() {
+        error!("Failed to save keys after rotation: {}", e);
+    }
+
+    let content = format!(
+        "🔄 **Key rotated!**\n\
+        The old key `{}` stays valid until <t:{}:f>.\n\n\
+        Your new mod-key is:\n\
+        ```\n{}\n```\n\
+        Update the survey's global config before the old key expires.",
+        mask_key(&old_key),
+        grace_end,
+        new_key
+    );
+    respond_ephemeral(ctx, command, content).await
+}
+
+async fn handle_list_keys(ctx: &Context, command: &serenity::all::CommandInteraction) -> Result<(), serenity::Error> {
+    let state = {
+        let data = ctx.data.read().await;
+        data.get::<ServerState>().cloned().expect("ServerState not found in TypeMap")
+    };
+
+    let Some(guild_id) = command.guild_id.map(|id| id.to_string()) else {
+        return respond_ephemeral(ctx, command, "This command can only be used in a server.").await;
+    };
+
+    let mut keys: Vec<(String, ModeratorKeyData)> = state.key_store.iter()
+        .filter(|entry| entry.value().guild_id == guild_id)
+        .map(|entry| (entry.key().clone(), entry.value().clone()))
+        .collect();
+    if keys.is_empty() {
+        return respond_ephemeral(ctx, command, "No moderator keys have been generated in this server yet.").await;
+    }
+    keys.sort_by_key(|(_, data)| std::cmp::Reverse(data.created_at));
+
+    let now = now_secs();
+    let timestamp = |t: Option<u64>| t.map(|t| format!("<t:{}:R>", t)).unwrap_or_else(|| "unknown".to_string());
+
+    // Discord embeds are limited to 25 fields
+    let mut embed = CreateEmbed::new()
+        .title("🔑 Moderator Keys")
+        .color(Colour::DARK_TEAL)
+        .description(format!("{} keys in this server.", keys.len()));
+    for (key, data) in keys.iter().take(25) {
+        let status = match (data.revoked_at, data.expires_at, &data.replaced_by) {
+            (Some(t), _, _) => format!("⛔ Revoked <t:{}:R>", t),
+            (None, Some(exp), Some(new)) if exp > now => format!("🔄 Rotated to `{}`, valid until <t:{}:f>", mask_key(new), exp),
+            (None, Some(exp), _) if exp <= now => format!("⌛ Expired <t:{}:R>", exp),
+            _ => "✅ Active".to_string(),
+        };
+        let value = format!(
+            "Channel: <#{}>\nCreated: {}\nLast used: {}\nStatus: {}",
+            data.channel_id, timestamp(data.created_at), timestamp(data.last_used_at), status
+        );
+        let name = format!("{} (`{}`)", data.label.as_deref().unwrap_or("Unlabeled"), mask_key(key));
+        embed = embed.field(name, value, false);
+    }
+
+    let builder = CreateInteractionResponse::Message(CreateInteractionResponseMessage::new().add_embed(embed).ephemeral(true));
+    command.create_response(&ctx.http, builder).await
+}
+
+async fn handle_dashboard_link(ctx: &Context, command: &serenity::all::CommandInteraction) -> Result<(), serenity::Error> {
+    let state = {
+        let data = ctx.data.read().await;
+        data.get::<ServerState>().cloned().expect("ServerState not found in TypeMap")
+    };
+
+    let Some((key, _)) = state.key_for_channel(&command.channel_id.to_string()) else {
+        return respond_ephemeral(ctx, command, "❌ No moderator key is bound to this channel.").await;
+    };
+
+    let Some(base_url) = std::env::var("BASE_URL").ok().filter(|url| !url.is_empty()) else {
+        warn!("Dashboard link requested but BASE_URL is not set");
+        return respond_ephemeral(ctx, command, "❌ The dashboard is not available, the server has no BASE_URL configured.").await;
+    };
+    let token = state.dashboard.create_login_token(&key);
+    info!("User {} requested a dashboard link for key {}", command.user.id, mask_key(&key));
+
+    let content = format!(
+        concat!(
+            "🔗 [Open the dashboard]({}/dashboard/login?token={})\n",
+            "The link works once and expires in 15 minutes. Do not share it, it grants access to all submissions of this channel.",
+        ),
+        base_url, token
+    );
+    respond_ephemeral(ctx, command, content).await
+}
+
+/// Binds the key of this channel to a forum, or back to the channel when no forum is given
+async fn handle_set_forum(ctx: &Context, command: &serenity::all::CommandInteraction) -> Result<(), serenity::Error> {
+    let state = {
+        let data = ctx.data.read().await;
+        data.get::<ServerState>().cloned().expect("ServerState not found in TypeMap")
+    };
+
+    let mut forum = None;
+    let mut tag_questions = Vec::new();
+    for opt in &command.data.options {
+        match (opt.name.as_str(), &opt.value) {
+            ("forum", CommandDataOptionValue::Channel(id)) => forum = Some(*id),
+            ("tag_questions", CommandDataOptionValue::String(s)) => {
+                tag_questions = s.split(',').map(|q| q.trim().to_string()).filter(|q| !q.is_empty()).collect();
             }
-        } else {
-            info!("Successfully sent message to channel {}", channel_id);
+            _ => {}
         }
     }
+
+    let Some((key, data)) = state.key_for_channel(&command.channel_id.to_string()) else {
+        return respond_ephemeral(ctx, command, "❌ No moderator key is bound to this channel.").await;
+    };
+    if data.channel_id != command.channel_id.to_string() {
+        return respond_ephemeral(ctx, command, format!("❌ Run this command in <#{}>, the channel the key is bound to.", data.channel_id)).await;
+    }
+
+    let Some(forum) = forum else {
+        if let Some(mut data) = state.key_store.get_mut(&key) {
+            data.forum_channel_id = None;
+            data.forum_tag_questions.clear();
+        }
+        if let Err(e) = state.save_keys_to_disk() {
+            error!("Failed to save keys after unbinding a forum: {}", e);
+        }
+        return respond_ephemeral(ctx, command, "✅ Submissions are posted to this channel again.").await;
+    };
+
+    let available_tags = match forum.to_channel(&ctx.http).await {
+        Ok(Channel::Guild(channel)) if channel.kind == ChannelType::Forum => channel.available_tags,
+        Ok(_) => return respond_ephemeral(ctx, command, "❌ That channel is not a forum.").await,
+        Err(e) => {
+            warn!("Failed to load forum {}: {}", forum, e);
+            return respond_ephemeral(ctx, command, "❌ I can't see that forum. Check my permissions there.").await;
+        }
+    };
+
+    if let Some(mut data) = state.key_store.get_mut(&key) {
+        data.forum_channel_id = Some(forum.to_string());
+        data.forum_tag_questions = tag_questions.clone();
+    }
+    if let Err(e) = state.save_keys_to_disk() {
+        error!("Failed to save keys after binding a forum: {}", e);
+    }
+    info!("User {} bound key {} to forum {}", command.user.id, mask_key(&key), forum);
+
+    let mut content = format!("✅ **Forum set!** New submissions become posts in <#{}>. Buttons and `/open_bugs` keep working there.", forum);
+    if tag_questions.is_empty() {
+        content.push_str("\nPosts are not tagged. Set `tag_questions` to tag them by answers.");
+    } else {
+        let tags: Vec<String> = available_tags.iter().map(|tag| format!("`{}`", tag.name)).collect();
+        content.push_str(&format!(
+            "\nPosts are tagged with the forum tags named like the answers to: {}.\nAvailable tags: {}",
+            tag_questions.iter().map(|q| format!("`{}`", q)).collect::<Vec<_>>().join(", "),
+            if tags.is_empty() { "none yet, create them in the forum settings".to_string() } else { tags.join(", ") },
+        ));
+    }
+    respond_ephemeral(ctx, command, content).await
+}
+
+/// Turns the thread per submission on or off for the key of this channel
+async fn handle_auto_threads(ctx: &Context, command: &serenity::all::CommandInteraction) -> Result<(), serenity::Error> {
+    let state = {
+        let data = ctx.data.read().await;
+        data.get::<ServerState>().cloned().expect("ServerState not found in TypeMap")
+    };
+
+    let mut enabled = false;
+    for opt in &command.data.options {
+        if let ("enabled", CommandDataOptionValue::Boolean(b)) = (opt.name.as_str(), &opt.value) {
+            enabled = *b;
+        }
+    }
+
+    let Some((key, data)) = state.key_for_channel(&command.channel_id.to_string()) else {
+        return respond_ephemeral(ctx, command, "❌ No moderator key is bound to this channel.").await;
+    };
+    if let Some(mut data) = state.key_store.get_mut(&key) {
+        data.auto_threads = enabled;
+    }
+    if let Err(e) = state.save_keys_to_disk() {
+        error!("Failed to save keys after changing auto threads: {}", e);
+    }
+
+    let mut content = if enabled {
+        "✅ Every new submission in this channel gets its own thread.".to_string()
+    } else {
+        "✅ Submissions are posted without threads.".to_string()
+    };
+    if let Some(forum) = &data.forum_channel_id {
+        content.push_str(&format!("\nSubmissions currently go to the forum <#{}>, where every post is a thread already. This applies when posting to the forum fails or `/set_forum` is cleared.", forum));
+    }
+    respond_ephemeral(ctx, command, content).await
+}
+
+async fn handle_triager_role(ctx: &Context, command: &serenity::all::CommandInteraction) -> Result<(), serenity::Error> {
+    let state = {
+        let data = ctx.data.read().await;
+        data.get::<ServerState>().cloned().expect("ServerState not found in TypeMap")
+    };
+
+    let mut role = None;
+    for opt in &command.data.options {
+        if let ("role", CommandDataOptionValue::Role(id)) = (opt.name.as_str(), &opt.value) {
+            role = Some(id.to_string());
+        }
+    }
+
+    let Some((key, _)) = state.key_for_channel(&command.channel_id.to_string()) else {
+        return respond_ephemeral(ctx, command, "❌ No moderator key is bound to this channel.").await;
+    };
+    if let Some(mut data) = state.key_store.get_mut(&key) {
+        data.triager_role_id = role.clone();
+    }
+    if let Err(e) = state.save_keys_to_disk() {
+        error!("Failed to save keys after changing the triager role: {}", e);
+    }
+
+    let content = match role {
+        Some(role) => format!("✅ Members with <@&{}> or Manage Messages can now change the bug reports of this channel.", role),
+        None => "✅ Only members with Manage Messages can change the bug reports of this channel.".to_string(),
+    };
+    respond_ephemeral(ctx, command, content).await
+}
+
+const OPEN_BUGS_LISTED: u32 = 20;
+
+/// Lists the newest unresolved bug reports of the channel's key, linked to their notifications
+async fn handle_open_bugs(ctx: &Context, command: &serenity::all::CommandInteraction) -> Result<(), serenity::Error> {
+    let state = {
+        let data = ctx.data.read().await;
+        data.get::<ServerState>().cloned().expect("ServerState not found in TypeMap")
+    };
+
+    let mut map_name = None;
+    for opt in &command.data.options {
+        if let ("map_name", CommandDataOptionValue::String(s)) = (opt.name.as_str(), &opt.value) {
+            map_name = Some(s.trim().to_string()).filter(|s| !s.is_empty());
+        }
+    }
+
+    let Some((key, data)) = key_for_interaction(&state, command.channel_id, command.channel.as_ref()) else {
+        return respond_ephemeral(ctx, command, "❌ No moderator key is bound to this channel.").await;
+    };
+    let (total, records) = match state.submissions.open_bugs(&key, map_name.as_deref(), OPEN_BUGS_LISTED) {
+        Ok(result) => result,
+        Err(e) => {
+            error!("Failed to query open bugs: {}", e);
+            return respond_ephemeral(ctx, command, "❌ Failed to read the submission index.").await;
+        }
+    };
+    let on_map = map_name.as_ref().map(|m| format!(" on `{}`", m)).unwrap_or_default();
+    if total == 0 {
+        return respond_ephemeral(ctx, command, format!("🎉 No open bug reports{}.", on_map)).await;
+    }
+
+    // Embed descriptions are limited to 4096 characters
+    let mut description = String::new();
+    let mut listed = 0;
+    for record in &records {
+        let bug = record.bug.clone().unwrap_or_default();
+        let link = match &bug.message {
+            Some((channel_id, message_id)) => format!("https://discord.com/channels/{}/{}/{}", data.guild_id, channel_id, message_id),
+            None => state.links.data_link(&record.id).url,
+        };
+        let mut line = format!(
+            "{} [`{}`]({}) by **{}** <t:{}:R>",
+            bug.status.emoji(), record.submission.map_name, link, record.submission.user_name, record.submission.submission_timestamp
+        );
+        if let Some(assignee_id) = &bug.assignee_id {
+            line.push_str(&format!(" → <@{}>", assignee_id));
+        }
+        if description.len() + line.len() + 1 > 4000 {
+            break;
+        }
+        description.push_str(&line);
+        description.push('\n');
+        listed += 1;
+    }
+
+    let embed = CreateEmbed::new()
+        .title(format!("🐞 Open Bug Reports{}", on_map))
+        .color(Colour::ORANGE)
+        .description(description)
+        .footer(serenity::builder::CreateEmbedFooter::new(format!("Showing {} of {} unresolved reports, newest first", listed, total)));
+    let builder = CreateInteractionResponse::Message(CreateInteractionResponseMessage::new().add_embed(embed));
+    command.create_response(&ctx.http, builder).await
+}
+
+const REFRESH_LINKS_PREFIX: &str = "refresh_links:";
+const REFRESH_EXPORT_PREFIX: &str = "refresh_export:";
+
+/// Button under submission notifications that hands out fresh signed links
+fn refresh_links_row(submission_id: &Uuid) -> Vec<CreateActionRow> {
+    let button = CreateButton::new(format!("{}{}", REFRESH_LINKS_PREFIX, submission_id))
+        .label("Refresh links")
+        .emoji('🔄')
+        .style(ButtonStyle::Secondary);
+    vec![CreateActionRow::Buttons(vec![button])]
+}
+
+const BUG_STATUS_PREFIX: &str = "bug_status:"; // followed by `{status}:{submission_id}`
+const BUG_ASSIGN_PREFIX: &str = "bug_assign:";
+const BUG_STATUS_FIELD: &str = "Bug Status";
+
+/// Components of a submission notification. Bug reports also get status buttons and an assignee picker.
+fn notification_rows(submission_id: &Uuid, is_bug_report: bool) -> Vec<CreateActionRow> {
+    let mut rows = Vec::new();
+    if is_bug_report {
+        let buttons = [BugStatus::Triaged, BugStatus::Fixed, BugStatus::WontFix, BugStatus::Duplicate, BugStatus::Open]
+            .into_iter()
+            .map(|status| {
+                let (label, style) = match status {
+                    BugStatus::Open => ("Reopen", ButtonStyle::Secondary),
+                    BugStatus::Fixed => (status.label(), ButtonStyle::Success),
+                    BugStatus::Triaged => (status.label(), ButtonStyle::Primary),
+                    _ => (status.label(), ButtonStyle::Secondary),
+                };
+                CreateButton::new(format!("{}{}:{}", BUG_STATUS_PREFIX, status.as_str(), submission_id))
+                    .label(label)
+                    .emoji(status.emoji())
+                    .style(style)
+            })
+            .collect();
+        let assignee = CreateSelectMenu::new(format!("{}{}", BUG_ASSIGN_PREFIX, submission_id), CreateSelectMenuKind::User { default_users: None })
+            .placeholder("Assign to…")
+            .min_values(0)
+            .max_values(1);
+        rows.push(CreateActionRow::Buttons(buttons));
+        rows.push(CreateActionRow::SelectMenu(assignee));
+    }
+    rows.extend(refresh_links_row(submission_id));
+    rows
+}
+
+/// Submissions sent from the main menu have no map
+fn map_label(submission: &FormSubmission) -> &str {
+    if submission.map_name.is_empty() { "main menu" } else { &submission.map_name }
+}
+
+fn survey_name(submission: &FormSubmission) -> &str {
+    submission.survey_id.split('/').next_back().unwrap_or("Survey")
+}
+
+fn submission_colour(submission: &FormSubmission) -> u32 {
+    match submission.custom_embed_color {
+        Some(color) => color as u32,
+        None => 0x00BFFF
+    }
+}
+
+/// Title of a notification, bug reports past `Open` are prefixed with their status
+fn notification_title(survey_filename: &str, status: BugStatus) -> String {
+    match status {
+        BugStatus::Open => format!("New Submission: {}", survey_filename),
+        _ => format!("{} {}: {}", status.emoji(), status.label(), survey_filename),
+    }
+}
+
+fn bug_status_text(bug: &BugState) -> String {
+    let mut text = format!("{} {}", bug.status.emoji(), bug.status.label());
+    if let Some(assignee_id) = &bug.assignee_id {
+        text.push_str(&format!("\nAssigned to <@{}>", assignee_id));
+    }
+    if let (Some(updated_by), Some(updated_at)) = (&bug.updated_by, bug.updated_at) {
+        text.push_str(&format!("\nUpdated by <@{}> <t:{}:R>", updated_by, updated_at));
+    }
+    text
+}
+
+/// The notification embed with the title, colour and status field of the report's current state
+fn restyle_bug_embed(mut embed: Embed, record: &StoredSubmission, bug: &BugState) -> CreateEmbed {
+    embed.title = Some(notification_title(survey_name(&record.submission), bug.status));
+    embed.colour = Some(Colour::new(bug.status.colour().unwrap_or_else(|| submission_colour(&record.submission))));
+    let value = bug_status_text(bug);
+    match embed.fields.iter_mut().find(|field| field.name == BUG_STATUS_FIELD) {
+        Some(field) => field.value = value,
+        None => embed.fields.push(EmbedField::new(BUG_STATUS_FIELD, value, true)),
+    }
+    CreateEmbed::from(embed)
+}
+
+const MAX_DEMOS_PER_ATTACHMENT: usize = 5; // keeps the files field under Discord's 1024 characters
+
+/// An attachment line with signed links to the file and its previews, followed by the headers of its demos
+fn attachment_line(file: &FileMetadata, links: &LinkSigner) -> String {
+    let mut line = format!("📎 [{}]({})", file.original_name, links.data_link(&file.id).url);
+    for preview in &file.previews {
+        let label = match preview.kind {
+            PreviewKind::Poster => "poster",
+            PreviewKind::Clip => "preview",
+        };
+        line.push_str(&format!(" · [{}]({})", label, links.data_link(&preview.file_id).url));
+    }
+    line.push('\n');
+
+    match &file.demo_info {
+        Some(DemoInfo::Parsed { demos, invalid }) => {
+            for demo in demos.iter().take(MAX_DEMOS_PER_ATTACHMENT) {
+                let secs = demo.playback_secs.round() as u64;
+                line.push_str(&format!(
+                    "↳ 🎬 `{}` on `{}` by {}, {}:{:02} ({} ticks)\n",
+                    demo.file_name, demo.map_name, demo.client_name, secs / 60, secs % 60, demo.ticks
+                ));
+            }
+            if demos.len() > MAX_DEMOS_PER_ATTACHMENT {
+                line.push_str(&format!("↳ 🎬 and {} more demos\n", demos.len() - MAX_DEMOS_PER_ATTACHMENT));
+            }
+            if !invalid.is_empty() {
+                line.push_str(&format!("↳ ⚠️ {} unreadable demo entries\n", invalid.len()));
+            }
+        }
+        Some(DemoInfo::Rejected { reason }) => line.push_str(&format!("↳ ⚠️ Demos not inspected: {}\n", reason)),
+        None => {}
+    }
+    line
+}
+
+/// Summary of the problems found in the attached console logs, None without console logs
+fn log_findings_text(files: &[FileMetadata]) -> Option<String> {
+    let logs: Vec<(&FileMetadata, &LogFindings)> = files.iter()
+        .filter_map(|file| file.log_findings.as_ref().map(|findings| (file, findings)))
+        .collect();
+    if logs.is_empty() {
+        return None;
+    }
+
+    let mut text = String::new();
+    for (file, log) in &logs {
+        if logs.len() > 1 {
+            text.push_str(&format!("`{}`\n", file.original_name));
+        }
+        if log.findings.is_empty() {
+            text.push_str("✅ No known problems\n");
+        }
+        for finding in &log.findings {
+            let details = finding.details.iter()
+                .take(3)
+                .map(|detail| format!("`{}` ({})", detail.text.replace('`', "'"), detail.count))
+                .collect::<Vec<_>>()
+                .join(", ");
+            text.push_str(&format!("⚠️ **{}** ×{}", finding.pattern, finding.count));
+            if !details.is_empty() {
+                text.push_str(&format!(": {}", details));
+            }
+            text.push('\n');
+        }
+        if log.truncated {
+            text.push_str(&format!("Only the first {} lines were scanned\n", log.lines_scanned));
+        }
+    }
+
+    // Embed fields are limited to 1024 characters
+    if text.chars().count() > 1024 {
+        text = text.chars().take(1020).collect::<String>() + " …";
+    }
+    Some(text)
+}
+
+/// Lines linking the earlier reports a bug report likely duplicates
+fn duplicates_text(duplicates: &[DuplicateMatch], links: &LinkSigner) -> Option<String> {
+    if duplicates.is_empty() {
+        return None;
+    }
+    let lines: Vec<String> = duplicates.iter()
+        .map(|duplicate| format!(
+            "possible duplicate of [{}'s report]({}) from <t:{}:R> ({:.0}% match, {:.0} units away)",
+            duplicate.user_name,
+            links.data_link(&duplicate.submission_id).url,
+            duplicate.submission_timestamp,
+            duplicate.score * 100.0,
+            duplicate.distance,
+        ))
+        .collect();
+    Some(lines.join("\n"))
+}
+
+/// The key of the channel an interaction came from. In a forum post or thread that is the key of the parent channel or forum.
+fn key_for_interaction(state: &ServerState, channel_id: ChannelId, channel: Option<&PartialChannel>) -> Option<(String, ModeratorKeyData)> {
+    state.key_for_channel(&channel_id.to_string())
+        .or_else(|| state.key_for_channel(&channel?.parent_id?.to_string()))
+}
+
+async fn respond_component_ephemeral(ctx: &Context, component: &ComponentInteraction, content: impl Into<String>) -> Result<(), serenity::Error> {
+    let builder = CreateInteractionResponse::Message(CreateInteractionResponseMessage::new().content(content).ephemeral(true));
+    component.create_response(&ctx.http, builder).await
+}
+
+/// Replies with newly signed links to a submission's JSON and attachments, only in the channel of its key
+async fn handle_refresh_links(ctx: &Context, component: &ComponentInteraction, submission_id: &str) -> Result<(), serenity::Error> {
+    let state = {
+        let data = ctx.data.read().await;
+        data.get::<ServerState>().cloned().expect("ServerState not found in TypeMap")
+    };
+
+    let Ok(submission_id) = Uuid::parse_str(submission_id) else {
+        return respond_component_ephemeral(ctx, component, "❌ This button is broken.").await;
+    };
+    let Some((key, _)) = key_for_interaction(&state, component.channel_id, component.channel.as_ref()) else {
+        return respond_component_ephemeral(ctx, component, "❌ No moderator key is bound to this channel.").await;
+    };
+    let record = match state.submissions.get(&key, &submission_id) {
+        Ok(Some(record)) => record,
+        Ok(None) => return respond_component_ephemeral(ctx, component, "❌ This submission does not belong to this channel's key.").await,
+        Err(e) => {
+            error!("Failed to load submission {} for link refresh: {}", submission_id, e);
+            return respond_component_ephemeral(ctx, component, "❌ Failed to read the submission index.").await;
+        }
+    };
+
+    let json_link = state.links.data_link(&record.id);
+    let mut content = format!("📄 [Raw JSON]({})\n", json_link.url);
+    for file_id in &record.file_ids {
+        let Some(file) = state.file_manager.files.get(file_id).map(|f| f.clone()) else { continue };
+        match file.status {
+            FileStatus::Active => content.push_str(&attachment_line(&file, &state.links)),
+            FileStatus::Expired { deleted_at } => content.push_str(&format!("📎 {} (deleted <t:{}:R>)\n", file.original_name, deleted_at)),
+        }
+    }
+    content.push_str(&format!("Links expire <t:{}:R>", json_link.expires_at));
+
+    respond_component_ephemeral(ctx, component, content).await
+}
+
+/// Whether the member who used a bug report button may change it, buttons used outside a guild never may
+fn can_triage(component: &ComponentInteraction, data: &ModeratorKeyData) -> bool {
+    component.member.as_ref().is_some_and(|member| may_triage(member.permissions, &member.roles, data))
+}
+
+/// Manage Messages in the channel, or the key's triager role
+fn may_triage(permissions: Option<Permissions>, roles: &[RoleId], data: &ModeratorKeyData) -> bool {
+    permissions.is_some_and(|permissions| permissions.manage_messages())
+        || data.triager_role_id.as_ref().is_some_and(|role| roles.iter().any(|id| id.to_string() == *role))
+}
+
+const TRIAGE_REFUSED: &str = "❌ Only members with Manage Messages or the triager role (see `/triager_role`) can change bug reports.";
+
+/// Sets the status of a bug report from the buttons under its notification
+async fn handle_bug_status(ctx: &Context, component: &ComponentInteraction, custom_id: &str) -> Result<(), serenity::Error> {
+    let state = {
+        let data = ctx.data.read().await;
+        data.get::<ServerState>().cloned().expect("ServerState not found in TypeMap")
+    };
+
+    let parsed = custom_id.split_once(':')
+        .and_then(|(status, id)| Some((BugStatus::parse(status)?, Uuid::parse_str(id).ok()?)));
+    let Some((status, submission_id)) = parsed else {
+        return respond_component_ephemeral(ctx, component, "❌ This button is broken.").await;
+    };
+    let Some((key, data)) = key_for_interaction(&state, component.channel_id, component.channel.as_ref()) else {
+        return respond_component_ephemeral(ctx, component, "❌ No moderator key is bound to this channel.").await;
+    };
+    if !can_triage(component, &data) {
+        return respond_component_ephemeral(ctx, component, TRIAGE_REFUSED).await;
+    }
+
+    let updated_by = component.user.id.to_string();
+    match state.submissions.set_bug_status(&key, &submission_id, status, &updated_by) {
+        Ok(true) => {}
+        Ok(false) => return respond_component_ephemeral(ctx, component, "❌ This submission does not belong to this channel's key.").await,
+        Err(e) => {
+            error!("Failed to set the status of bug report {}: {}", submission_id, e);
+            return respond_component_ephemeral(ctx, component, "❌ Failed to update the submission index.").await;
+        }
+    }
+    info!("User {} marked bug report {} as {}", updated_by, submission_id, status.as_str());
+
+    update_bug_message(ctx, component, &state, &key, &submission_id).await
+}
+
+/// Assigns a bug report to the user picked under its notification, or unassigns it when the pick is cleared
+async fn handle_bug_assign(ctx: &Context, component: &ComponentInteraction, submission_id: &str) -> Result<(), serenity::Error> {
+    let state = {
+        let data = ctx.data.read().await;
+        data.get::<ServerState>().cloned().expect("ServerState not found in TypeMap")
+    };
+
+    let (Ok(submission_id), ComponentInteractionDataKind::UserSelect { values }) = (Uuid::parse_str(submission_id), &component.data.kind) else {
+        return respond_component_ephemeral(ctx, component, "❌ This menu is broken.").await;
+    };
+    let Some((key, data)) = key_for_interaction(&state, component.channel_id, component.channel.as_ref()) else {
+        return respond_component_ephemeral(ctx, component, "❌ No moderator key is bound to this channel.").await;
+    };
+    if !can_triage(component, &data) {
+        return respond_component_ephemeral(ctx, component, TRIAGE_REFUSED).await;
+    }
+
+    let assignee = match values.first() {
+        Some(user_id) => {
+            let name = match user_id.to_user(&ctx.http).await {
+                Ok(user) => user.name,
+                Err(_) => user_id.to_string(),
+            };
+            Some((user_id.to_string(), name))
+        }
+        None => None,
+    };
+    let updated_by = component.user.id.to_string();
+    let assignee_ref = assignee.as_ref().map(|(id, name)| (id.as_str(), name.as_str()));
+    match state.submissions.set_bug_assignee(&key, &submission_id, assignee_ref, &updated_by) {
+        Ok(true) => {}
+        Ok(false) => return respond_component_ephemeral(ctx, component, "❌ This submission does not belong to this channel's key.").await,
+        Err(e) => {
+            error!("Failed to assign bug report {}: {}", submission_id, e);
+            return respond_component_ephemeral(ctx, component, "❌ Failed to update the submission index.").await;
+        }
+    }
+
+    update_bug_message(ctx, component, &state, &key, &submission_id).await
+}
+
+/// Redraws the notification a status or assignee change came from
+async fn update_bug_message(
+    ctx: &Context,
+    component: &ComponentInteraction,
+    state: &ServerState,
+    key: &str,
+    submission_id: &Uuid,
+) -> Result<(), serenity::Error> {
+    let record = match state.submissions.get(key, submission_id) {
+        Ok(Some(record)) => record,
+        Ok(None) => return respond_component_ephemeral(ctx, component, "❌ This submission does not belong to this channel's key.").await,
+        Err(e) => {
+            error!("Failed to load bug report {}: {}", submission_id, e);
+            return respond_component_ephemeral(ctx, component, "❌ Failed to read the submission index.").await;
+        }
+    };
+    let (Some(bug), Some(embed)) = (&record.bug, component.message.embeds.first()) else {
+        return respond_component_ephemeral(ctx, component, "❌ This submission is not a bug report.").await;
+    };
+
+    let embed = restyle_bug_embed(embed.clone(), &record, bug);
+    let builder = CreateInteractionResponse::UpdateMessage(CreateInteractionResponseMessage::new().embed(embed));
+    component.create_response(&ctx.http, builder).await
+}
+
+async fn handle_refresh_export(ctx: &Context, component: &ComponentInteraction, filename: &str) -> Result<(), serenity::Error> {
+    let state = {
+        let data = ctx.data.read().await;
+        data.get::<ServerState>().cloned().expect("ServerState not found in TypeMap")
+    };
+
+    if key_for_interaction(&state, component.channel_id, component.channel.as_ref()).is_none() {
+        return respond_component_ephemeral(ctx, component, "❌ No moderator key is bound to this channel.").await;
+    }
+
+    // Only exports that are still kept can be handed out again, the button may be older than the retention period
+    let Some(job) = state.file_manager.exports.completed_by_filename(filename) else {
+        return respond_component_ephemeral(ctx, component, "❌ This export has been deleted. Run `/export_data` again.").await;
+    };
+    let Some(filename) = job.filename.as_deref() else { return Ok(()) };
+
+    let link = state.links.export_link(filename);
+    let mut content = format!("📦 [Download export]({})\nLink expires <t:{}:R>", link.url, link.expires_at);
+    if let Some(expires_at) = job.expires_at {
+        content.push_str(&format!(", the export is deleted <t:{}:R>", expires_at));
+    }
+    respond_component_ephemeral(ctx, component, content).await
+}
+
+const MAX_WEBHOOKS_PER_KEY: usize = 5;
+const WEBHOOK_LOG_ENTRIES: u32 = 10;
+
+async fn handle_add_webhook(ctx: &Context, command: &serenity::all::CommandInteraction) -> Result<(), serenity::Error> {
+    let state = {
+        let data = ctx.data.read().await;
+        data.get::<ServerState>().cloned().expect("ServerState not found in TypeMap")
+    };
+
+    let mut url = String::new();
+    let mut secret = None;
+    for opt in &command.data.options {
+        match (opt.name.as_str(), &opt.value) {
+            ("url", CommandDataOptionValue::String(s)) => url = s.trim().to_string(),
+            ("secret", CommandDataOptionValue::String(s)) => secret = Some(s.to_string()).filter(|s| !s.is_empty()),
+            _ => {}
+        }
+    }
+
+    if !(url.starts_with("https://") || url.starts_with("http://")) || reqwest::Url::parse(&url).is_err() {
+        return respond_ephemeral(ctx, command, "❌ The webhook URL must be a valid http:// or https:// URL.").await;
+    }
+    if let Err(e) = crate::webhooks::resolve_target(&url, crate::webhooks::allow_private_networks()).await {
+        return respond_ephemeral(ctx, command, format!("❌ The webhook URL can't be used: {}.", e)).await;
+    }
+
+    let Some((mod_key, data)) = state.key_for_channel(&command.channel_id.to_string()) else {
+        return respond_ephemeral(ctx, command, "❌ No moderator key is bound to this channel.").await;
+    };
+    if data.webhooks.len() >= MAX_WEBHOOKS_PER_KEY {
+        return respond_ephemeral(ctx, command, format!("❌ A key can have at most {} webhooks.", MAX_WEBHOOKS_PER_KEY)).await;
+    }
+
+    let webhook = WebhookConfig {
+        id: Uuid::new_v4(),
+        url: url.clone(),
+        secret: secret.clone(),
+        created_at: now_secs(),
+    };
+    if let Some(mut data) = state.key_store.get_mut(&mod_key) {
+        data.webhooks.push(webhook.clone());
+    }
+    if let Err(e) = state.save_keys_to_disk() {
+        error!("Failed to save keys after adding a webhook: {}", e);
+    }
+
+    let signing = if secret.is_some() {
+        "Requests are signed: `X-Webhook-Signature: sha256=<HMAC-SHA256 of \"{X-Webhook-Timestamp}.{body}\">`."
+    } else {
+        "Requests are not signed. Add a `secret` to verify them on your side."
+    };
+    let content = format!("✅ **Webhook added** (`{}`)\nNew submissions will be POSTed to <{}>.\n{}", webhook.id, url, signing);
+    respond_ephemeral(ctx, command, content).await
+}
+
+async fn handle_remove_webhook(ctx: &Context, command: &serenity::all::CommandInteraction) -> Result<(), serenity::Error> {
+    let state = {
+        let data = ctx.data.read().await;
+        data.get::<ServerState>().cloned().expect("ServerState not found in TypeMap")
+    };
+
+    let mut requested = String::new();
+    for opt in &command.data.options {
+        if let ("id", CommandDataOptionValue::String(s)) = (opt.name.as_str(), &opt.value) {
+            requested = s.trim().to_string();
+        }
+    }
+
+    let Some((mod_key, _)) = state.key_for_channel(&command.channel_id.to_string()) else {
+        return respond_ephemeral(ctx, command, "❌ No moderator key is bound to this channel.").await;
+    };
+
+    let removed = match state.key_store.get_mut(&mod_key) {
+        Some(mut data) if requested.len() >= KEY_PREFIX_LEN => {
+            let before = data.webhooks.len();
+            data.webhooks.retain(|w| !w.id.to_string().starts_with(&requested));
+            before - data.webhooks.len()
+        }
+        _ => 0,
+    };
+
+    if removed == 0 {
+        return respond_ephemeral(ctx, command, "❌ No webhook of this channel matches that ID.").await;
+    }
+    if let Err(e) = state.save_keys_to_disk() {
+        error!("Failed to save keys after removing a webhook: {}", e);
+    }
+    respond_ephemeral(ctx, command, format!("🗑️ Removed {} webhook(s).", removed)).await
+}
+
+async fn handle_list_webhooks(ctx: &Context, command: &serenity::all::CommandInteraction) -> Result<(), serenity::Error> {
+    let state = {
+        let data = ctx.data.read().await;
+        data.get::<ServerState>().cloned().expect("ServerState not found in TypeMap")
+    };
+
+    let Some((mod_key, data)) = state.key_for_channel(&command.channel_id.to_string()) else {
+        return respond_ephemeral(ctx, command, "❌ No moderator key is bound to this channel.").await;
+    };
+    if data.webhooks.is_empty() {
+        return respond_ephemeral(ctx, command, "This channel's key has no webhooks. Add one with `/add_webhook`.").await;
+    }
+
+    let deliveries = state.submissions.recent_deliveries(&mod_key, WEBHOOK_LOG_ENTRIES).unwrap_or_else(|e| {
+        error!("Failed to read the webhook delivery log: {}", e);
+        Vec::new()
+    });
+
+    let mut embed = CreateEmbed::new()
+        .title("🪝 Webhooks")
+        .color(Colour::DARK_TEAL);
+    for webhook in &data.webhooks {
+        let value = format!(
+            "URL: <{}>\nSigned: {}\nAdded: <t:{}:R>",
+            webhook.url,
+            if webhook.secret.is_some() { "yes" } else { "no" },
+            webhook.created_at
+        );
+        embed = embed.field(format!("`{}`", webhook.id), value, false);
+    }
+
+    let mut log = String::new();
+    for delivery in &deliveries {
+        let outcome = match (delivery.status_code, &delivery.error) {
+            (Some(code), _) if (200..300).contains(&code) => format!("✅ {}", code),
+            (Some(code), _) => format!("❌ {}", code),
+            (None, Some(e)) => format!("❌ {}", e.chars().take(60).collect::<String>()),
+            (None, None) => "❌".to_string(),
+        };
+        log.push_str(&format!(
+            "<t:{}:R> `{}` attempt {} → {} ({} ms)\n",
+            delivery.attempted_at, mask_key(&delivery.webhook_id.to_string()), delivery.attempt, outcome, delivery.duration_ms
+        ));
+    }
+    if log.is_empty() {
+        log.push_str("No deliveries yet.");
+    }
+    embed = embed.field("Recent Deliveries", log, false);
+
+    let builder = CreateInteractionResponse::Message(CreateInteractionResponseMessage::new().add_embed(embed).ephemeral(true));
+    command.create_response(&ctx.http, builder).await
+}
+
+/// Posts submissions as embeds to the channel the key is bound to
+pub struct DiscordNotifier {
+    pub http: Arc<Http>,
+    pub links: Arc<LinkSigner>,
+    pub submissions: Arc<SubmissionStore>,
+}
+
+const MAX_FORUM_TAGS: usize = 5; // Discord's limit per post
+const MAX_POST_TITLE_CHARS: usize = 100;
+
+impl DiscordNotifier {
+    /// Sends a notification where the key wants it: as a post in its forum, or to its channel with an optional thread.
+    /// Returns the channel and id of the sent message, for forums the post and its starter message.
+    async fn post(&self, event: &SubmissionEvent, title: &str, message: CreateMessage) -> Result<(ChannelId, MessageId), serenity::Error> {
+        let destination = &event.destination;
+        let title: String = title.chars().take(MAX_POST_TITLE_CHARS).collect();
+
+        if let Some(forum_id) = destination.forum_channel_id.as_deref().and_then(|id| id.parse::<u64>().ok()) {
+            let forum = ChannelId::new(forum_id);
+            let tags = self.forum_tags(forum, destination, &event.submission).await;
+            match forum.create_forum_post(&self.http, CreateForumPost::new(title.clone(), message.clone()).set_applied_tags(tags)).await {
+                // The starter message of a forum post has the id of the post
+                Ok(post) => return Ok((post.id, MessageId::new(post.id.get()))),
+                Err(e) => warn!("Failed to create a forum post in {}, sending to channel {} instead: {:?}", forum, destination.channel_id, e),
+            }
+        }
+
+        let channel_id = ChannelId::new(destination.channel_id.parse::<u64>().unwrap_or(0));
+        let sent = channel_id.send_message(&self.http, message).await?;
+        if destination.auto_threads
+            && let Err(e) = channel_id.create_thread_from_message(&self.http, sent.id, CreateThread::new(title)).await
+        {
+            warn!("Failed to start a thread for submission {}: {:?}", event.submission_id, e);
+        }
+        Ok((channel_id, sent.id))
+    }
+
+    /// Tags of the forum whose names match the answers to the key's tag questions
+    async fn forum_tags(&self, forum: ChannelId, destination: &ModeratorKeyData, submission: &FormSubmission) -> Vec<ForumTagId> {
+        if destination.forum_tag_questions.is_empty() {
+            return Vec::new();
+        }
+        let available = match forum.to_channel(&self.http).await {
+            Ok(Channel::Guild(channel)) => channel.available_tags,
+            Ok(_) => return Vec::new(),
+            Err(e) => {
+                warn!("Failed to load the tags of forum {}: {:?}", forum, e);
+                return Vec::new();
+            }
+        };
+        forum_tags_for(&available, &destination.forum_tag_questions, submission)
+    }
+}
+
+/// Question texts match with or without their trailing colon, ignoring case
+fn same_question(a: &str, b: &str) -> bool {
+    a.trim().trim_end_matches(':').trim().eq_ignore_ascii_case(b.trim().trim_end_matches(':').trim())
+}
+
+/// Picks the forum tags named like an answer to one of `questions`. Checkbox answers can match a tag per choice.
+fn forum_tags_for(available: &[ForumTag], questions: &[String], submission: &FormSubmission) -> Vec<ForumTagId> {
+    let mut tags = Vec::new();
+    for question in questions {
+        let Some(answer) = submission.answers.iter().find(|(key, _)| same_question(key, question)).map(|(_, answer)| answer) else {
+            continue;
+        };
+        let values = std::iter::once(answer.as_str()).chain(answer.split(", "));
+        for value in values {
+            let tag = available.iter().find(|tag| tag.name.trim().eq_ignore_ascii_case(value.trim()));
+            if let Some(tag) = tag
+                && !tags.contains(&tag.id)
+            {
+                tags.push(tag.id);
+            }
+        }
+    }
+    tags.truncate(MAX_FORUM_TAGS);
+    tags
+}
+
+#[async_trait]
+impl Notifier for DiscordNotifier {
+    fn name(&self) -> &'static str {
+        "discord"
+    }
+
+    async fn notify(&self, event: &SubmissionEvent) -> Result<(), String> {
+        info!("Received event for guild {}", event.destination.guild_id);
+        let submission = &event.submission;
+
+        // --- METADATA ---
+        // time in game:
+        let game_seconds = submission.game_timestamp;
+        let formatted_game_time = format!("{:.0} min {:.2} sec", (game_seconds / 60.0).floor(), game_seconds % 60.0);
+        // survey name
+        let survey_filename = survey_name(submission);
+        let is_bug_report = bug_status::is_bug_report(submission);
+
+        // --- CREATE EMBED ---
+        let mut embed = CreateEmbed::new()
+            .title(notification_title(survey_filename, BugStatus::Open))
+            .description(format!("From user **{}** (`{}`)", submission.user_name, submission.user_xuid))
+            .color(submission_colour(submission))
+            .field("Map", format!("`{}`", map_label(submission)), true)
+            .field("Game Timestamp", formatted_game_time, true);
+        if is_bug_report {
+            embed = embed.field(BUG_STATUS_FIELD, bug_status_text(&BugState::default()), true);
+        }
+        if let Some(duplicates) = duplicates_text(&event.possible_duplicates, &self.links) {
+            embed = embed.field("⚠️ Possible duplicate", duplicates, false);
+        }
+
+        // section 1: Metadata
+        if !submission.extra_data.is_empty() {
+            for (key, value) in &submission.extra_data {
+                // Format the value nicely, removing quotes from strings.
+                let value_str = format!("`{}`", value.to_string().trim_matches('"'));
+                embed = embed.field(key, value_str, true);
+            }
+        }
+
+        // section 2: Survey Answers
+        embed = embed.field("\u{200B}", "**Survey Answers**", false)
+            .fields(submission.answers.iter().map(|(q, a)| (q.clone(), a.clone(), false)));
+
+        // section 3: files
+        let json_link = self.links.data_link(&event.submission_id);
+        let mut files_text = String::new();
+        files_text.push_str(&format!("📄 [Raw JSON]({})\n", json_link.url));
+        for file in &event.attached_files {
+            files_text.push_str(&attachment_line(file, &self.links));
+        }
+        files_text.push_str(&format!("Links expire <t:{}:R>", json_link.expires_at));
+
+        embed = embed.field("**Files:**", &files_text, false);
+        if let Some(findings) = log_findings_text(&event.attached_files) {
+            embed = embed.field("**Log findings:**", findings, false);
+        }
+
+        // Poster frame of the first recording, so triagers see what it shows without downloading it
+        let poster = event.attached_files.iter()
+            .flat_map(|file| &file.previews)
+            .find(|preview| preview.kind == PreviewKind::Poster);
+        if let Some(poster) = poster {
+            embed = embed.image(self.links.data_link(&poster.file_id).url);
+        }
+
+        // --- SEND MESSAGE ---
+        let post_title = format!("{}: {} by {}", survey_filename, map_label(submission), submission.user_name);
+        let components = notification_rows(&event.submission_id, is_bug_report);
+        let builder = CreateMessage::new().embed(embed).components(components.clone());
+
+        let sent = match self.post(event, &post_title, builder).await {
+            Ok(sent) => {
+                info!("Successfully sent message to channel {}", sent.0);
+                sent
+            }
+            Err(why) => {
+                warn!("Failed to send notification embed for submission {}: {:?}", event.submission_id, why);
+
+                // If sending the embed fails (e.g., too large), send a fallback message.
+                let fallback_embed = CreateEmbed::new()
+                    .title("📄 Submission Received (Manual View Required)")
+                    .color(0x99AAB5)
+                    .description(format!(
+                        "The full submission for `{}` was received successfully, but it is too large to be displayed as a summary here.",
+                        survey_filename
+                    ))
+                    .field(
+                        "Submitted By",
+                        format!("**{}** (`{}`)", submission.user_name, submission.user_xuid),
+                        false
+                    )
+                    .field("Links", files_text, false);
+
+
+                let fallback_builder = CreateMessage::new().embed(fallback_embed).components(components);
+                match self.post(event, &post_title, fallback_builder).await {
+                    Ok(sent) => {
+                        info!("Successfully sent fallback message to channel {}", sent.0);
+                        sent
+                    }
+                    Err(fallback_why) => {
+                        return Err(format!("Failed to send fallback notification for submission {}: {:?}", event.submission_id, fallback_why));
+                    }
+                }
+            }
+        };
+
+        // `/open_bugs` links to the notification of each report
+        if is_bug_report
+            && let Err(e) = self.submissions.set_bug_message(&event.submission_id, &sent.0.to_string(), &sent.1.to_string())
+        {
+            warn!("Failed to record the notification of bug report {}: {}", event.submission_id, e);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    #[test]
+    fn manage_messages_or_the_triager_role_may_triage() {
+        let mut data = test_support::key_data("Server");
+        let member_role = [RoleId::new(10)];
+
+        assert!(may_triage(Some(Permissions::MANAGE_MESSAGES | Permissions::SEND_MESSAGES), &[], &data));
+        assert!(!may_triage(Some(Permissions::SEND_MESSAGES), &member_role, &data));
+        assert!(!may_triage(None, &member_role, &data));
+
+        data.triager_role_id = Some("10".to_string());
+        assert!(may_triage(Some(Permissions::SEND_MESSAGES), &member_role, &data));
+        assert!(may_triage(None, &member_role, &data));
+        assert!(!may_triage(Some(Permissions::SEND_MESSAGES), &[RoleId::new(11)], &data));
+    }
 }
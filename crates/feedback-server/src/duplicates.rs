@@ -0,0 +1,287 @@
+use crate::models::FormSubmission;
+use crate::submission_store::{StoredSubmission, SubmissionFilter, SubmissionStore};
+use crate::surveys::{self, Question};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+/// Extra data the bug report window attaches, formatted like `(vector : (x, y, z))`
+pub const POSITION_KEY: &str = "Player Position";
+const MAX_LINKED: usize = 3;
+const POSITION_WEIGHT: f32 = 0.5;
+const CHOICE_WEIGHT: f32 = 0.25;
+const ESSAY_WEIGHT: f32 = 0.25;
+
+/// An earlier report a new one likely duplicates
+#[derive(Serialize, Debug, Clone)]
+pub struct DuplicateMatch {
+    pub submission_id: Uuid,
+    pub score: f32,    // 0 to 1, weighted over the position and the answers both reports have
+    pub distance: f32, // between the player positions, in world units
+    pub user_name: String,
+    pub submission_timestamp: u64,
+}
+
+/// Compares new bug reports against the recent reports of the same survey, key and map
+#[derive(Debug, Clone)]
+pub struct DuplicateDetector {
+    window_secs: u64,
+    max_distance: f32,
+    min_score: f32,
+}
+
+impl DuplicateDetector {
+    /// Reports of the last `DUPLICATE_WINDOW_HOURS` (default 72, 0 disables detection) whose positions are at most
+    /// `DUPLICATE_MAX_DISTANCE` units apart (default 256) are linked when they score `DUPLICATE_MIN_SCORE` (default 0.7).
+    pub fn from_env() -> Self {
+        let env = |name: &str| std::env::var(name).ok();
+        let window_hours: u64 = env("DUPLICATE_WINDOW_HOURS").and_then(|v| v.parse().ok()).unwrap_or(72);
+        let max_distance: f32 = env("DUPLICATE_MAX_DISTANCE").and_then(|v| v.parse().ok()).unwrap_or(256.0);
+        let min_score: f32 = env("DUPLICATE_MIN_SCORE").and_then(|v| v.parse().ok()).unwrap_or(0.7);
+
+        Self {
+            window_secs: window_hours * 3600,
+            max_distance: max_distance.max(1.0),
+            min_score: min_score.clamp(0.0, 1.0),
+        }
+    }
+
+    /// Earlier reports `record` likely duplicates, best match first. Submissions without a player position are not bug reports.
+    pub fn find(&self, store: &SubmissionStore, record: &StoredSubmission) -> rusqlite::Result<Vec<DuplicateMatch>> {
+        let submission = &record.submission;
+        let Some(position) = player_position(submission) else { return Ok(Vec::new()) };
+        if self.window_secs == 0 {
+            return Ok(Vec::new());
+        }
+
+        let filter = SubmissionFilter {
+            survey_id: Some(submission.survey_id.clone()),
+            map_name: Some(submission.map_name.clone()),
+            from_timestamp: Some(submission.submission_timestamp.saturating_sub(self.window_secs)),
+            to_timestamp: Some(submission.submission_timestamp),
+            ..Default::default()
+        };
+        let candidates = store.query(&record.mod_key, &filter)?;
+        if candidates.iter().all(|candidate| candidate.id == record.id) {
+            return Ok(Vec::new());
+        }
+
+        let definitions = store.definitions(&record.mod_key, Some(&submission.survey_id))?;
+        let questions = surveys::question_types(&definitions.iter().collect::<Vec<_>>());
+
+        let mut matches: Vec<DuplicateMatch> = candidates.iter()
+            .filter(|candidate| candidate.id != record.id)
+            .filter_map(|candidate| self.compare(position, submission, candidate, &questions))
+            .collect();
+        matches.sort_by(|a, b| b.score.total_cmp(&a.score));
+        matches.truncate(MAX_LINKED);
+        Ok(matches)
+    }
+
+    fn compare(
+        &self,
+        position: [f32; 3],
+        submission: &FormSubmission,
+        candidate: &StoredSubmission,
+        questions: &HashMap<String, Question>,
+    ) -> Option<DuplicateMatch> {
+        let other = player_position(&candidate.submission)?;
+        let distance = position.iter().zip(other).map(|(a, b)| (a - b).powi(2)).sum::<f32>().sqrt();
+        if distance > self.max_distance {
+            return None;
+        }
+
+        // Answers only count when both reports have them, a skipped question says nothing either way
+        let mut weighted = POSITION_WEIGHT * (1.0 - distance / self.max_distance);
+        let mut total = POSITION_WEIGHT;
+        for (weight, similarity) in [
+            (CHOICE_WEIGHT, choice_similarity(submission, &candidate.submission, questions)),
+            (ESSAY_WEIGHT, essay_similarity(submission, &candidate.submission, questions)),
+        ] {
+            if let Some(similarity) = similarity {
+                weighted += weight * similarity;
+                total += weight;
+            }
+        }
+
+        let score = weighted / total;
+        (score >= self.min_score).then(|| DuplicateMatch {
+            submission_id: candidate.id,
+            score,
+            distance,
+            user_name: candidate.submission.user_name.clone(),
+            submission_timestamp: candidate.submission.submission_timestamp,
+        })
+    }
+}
+
+/// The reporter's position, `None` when it is missing or the client could not find the player (the origin)
+pub fn player_position(submission: &FormSubmission) -> Option<[f32; 3]> {
+    let text = submission.extra_data.get(POSITION_KEY)?.as_str()?;
+    let numbers: Vec<f32> = text.split(|c: char| !(c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E')))
+        .filter_map(|part| part.parse().ok())
+        .filter(|n: &f32| n.is_finite())
+        .collect();
+    match numbers[..] {
+        [x, y, z] if [x, y, z] != [0.0; 3] => Some([x, y, z]),
+        _ => None,
+    }
+}
+
+/// Average agreement of the RadioChoices and Checkboxes questions both reports answered
+fn choice_similarity(a: &FormSubmission, b: &FormSubmission, questions: &HashMap<String, Question>) -> Option<f32> {
+    average(answered_by_both(a, b).filter_map(|(key, answer_a, answer_b)| match questions.get(key)? {
+        Question::RadioChoices { .. } => Some(if answer_a == answer_b { 1.0 } else { 0.0 }),
+        Question::Checkboxes { choices, .. } => {
+            let selected_a: HashSet<&str> = surveys::selected_choices(answer_a, choices).into_iter().collect();
+            let selected_b: HashSet<&str> = surveys::selected_choices(answer_b, choices).into_iter().collect();
+            jaccard(&selected_a, &selected_b)
+        }
+        _ => None,
+    }))
+}
+
+/// Average word overlap of the Essay questions both reports answered
+fn essay_similarity(a: &FormSubmission, b: &FormSubmission, questions: &HashMap<String, Question>) -> Option<f32> {
+    average(answered_by_both(a, b).filter_map(|(key, answer_a, answer_b)| match questions.get(key)? {
+        Question::Essay { .. } => jaccard(&words(answer_a), &words(answer_b)),
+        _ => None,
+    }))
+}
+
+fn answered_by_both<'a>(a: &'a FormSubmission, b: &'a FormSubmission) -> impl Iterator<Item = (&'a str, &'a str, &'a str)> {
+    a.answers.iter()
+        .filter_map(|(key, answer_a)| Some((key.as_str(), answer_a.trim(), b.answers.get(key)?.trim())))
+        .filter(|(_, answer_a, answer_b)| !answer_a.is_empty() && !answer_b.is_empty())
+}
+
+/// Lowercased words of a free-text answer, short words carry little meaning and are skipped
+fn words(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() > 2)
+        .map(|word| word.to_lowercase())
+        .collect()
+}
+
+fn jaccard<T: Eq + std::hash::Hash>(a: &HashSet<T>, b: &HashSet<T>) -> Option<f32> {
+    let union = a.union(b).count();
+    (union > 0).then(|| a.intersection(b).count() as f32 / union as f32)
+}
+
+fn average(values: impl Iterator<Item = f32>) -> Option<f32> {
+    let (sum, count) = values.fold((0.0, 0), |(sum, count), value| (sum + value, count + 1));
+    (count > 0).then(|| sum / count as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    const DEFINITION: &str = r#"{"title": "Bug report", "widgets": [
+        {"type": "RadioChoices", "text": "Severity", "choices": ["Low", "High"]},
+        {"type": "Checkboxes", "text": "Where?", "choices": ["Menu", "Puzzle", "Elevator"]},
+        {"type": "Essay", "text": "What happened?"}
+    ]}"#;
+
+    fn detector() -> DuplicateDetector {
+        DuplicateDetector { window_secs: 72 * 3600, max_distance: 256.0, min_score: 0.7 }
+    }
+
+    fn questions() -> HashMap<String, Question> {
+        let definition: surveys::SurveyDefinition = serde_json::from_str(DEFINITION).unwrap();
+        definition.widgets.into_iter().filter_map(|q| Some((q.answer_key()?.to_string(), q))).collect()
+    }
+
+    fn report(map_name: &str, position: [f32; 3], answers: &[(&str, &str)]) -> StoredSubmission {
+        let position = format!("(vector : ({}, {}, {}))", position[0], position[1], position[2]);
+        test_support::stored("key", test_support::submission(map_name, answers, serde_json::json!({ POSITION_KEY: position })))
+    }
+
+    fn score(a: &StoredSubmission, b: &StoredSubmission) -> Option<f32> {
+        let position = player_position(&a.submission).unwrap();
+        detector().compare(position, &a.submission, b, &questions()).map(|m| m.score)
+    }
+
+    fn assert_close(actual: Option<f32>, expected: f32) {
+        let actual = actual.expect("expected a match");
+        assert!((actual - expected).abs() < 1e-4, "score {} instead of {}", actual, expected);
+    }
+
+    #[test]
+    fn positions_are_read_from_the_extra_data() {
+        let position = |text: &str| player_position(&test_support::submission("map", &[], serde_json::json!({ POSITION_KEY: text })));
+        assert_eq!(position("(vector : (1.5, -2, 3e2))"), Some([1.5, -2.0, 300.0]));
+        assert_eq!(position("(vector : (0, 0, 0))"), None);
+        assert_eq!(position("(vector : (1, 2))"), None);
+        assert_eq!(player_position(&test_support::submission("map", &[], serde_json::json!({}))), None);
+    }
+
+    #[test]
+    fn position_alone_scores_by_distance() {
+        let a = report("map", [0.0, 0.0, 1.0], &[]);
+        assert_close(score(&a, &report("map", [0.0, 0.0, 1.0], &[])), 1.0);
+        assert_close(score(&a, &report("map", [0.0, 51.2, 1.0], &[])), 0.8);
+        // Half the maximum distance is below the minimum score, beyond it nothing is compared
+        assert_eq!(score(&a, &report("map", [0.0, 128.0, 1.0], &[])), None);
+        assert_eq!(score(&a, &report("map", [0.0, 300.0, 1.0], &[])), None);
+    }
+
+    #[test]
+    fn answers_both_reports_have_are_weighted_in() {
+        let a = report("map", [10.0, 10.0, 10.0], &[
+            ("Severity", "High"), ("Where?", "Puzzle, Elevator"), ("What happened?", "Player fell through floor"),
+        ]);
+        let same_choices = report("map", [10.0, 10.0, 10.0], &[
+            ("Severity", "High"), ("Where?", "Puzzle"), ("What happened?", "fell through the floor near door"),
+        ]);
+        // Position 0.5 * 1, choices 0.25 * (1 + 1/2) / 2, essay 0.25 * 3/7
+        assert_close(score(&a, &same_choices), 0.5 + 0.25 * 0.75 + 0.25 * 3.0 / 7.0);
+
+        let other_choices = report("map", [10.0, 10.0, 10.0], &[
+            ("Severity", "Low"), ("Where?", "Menu"), ("What happened?", "fell through the floor near door"),
+        ]);
+        assert_eq!(score(&a, &other_choices), None);
+
+        // Skipped questions are left out of the weighting instead of counting as disagreement
+        let only_severity = report("map", [10.0, 10.0, 10.0], &[("Severity", "High"), ("What happened?", "")]);
+        assert_close(score(&a, &only_severity), (0.5 + 0.25) / 0.75);
+    }
+
+    #[test]
+    fn find_links_recent_reports_of_the_same_map_best_first() {
+        let store = SubmissionStore::open(test_support::temp_dir().join("submissions.db")).unwrap();
+        store.register_definition("key", "bug_report.json", "hash", DEFINITION).unwrap();
+
+        let record = report("map", [0.0, 0.0, 10.0], &[("Severity", "High")]);
+        let close = report("map", [0.0, 0.0, 20.0], &[("Severity", "High")]);
+        let closer = report("map", [0.0, 0.0, 11.0], &[("Severity", "High")]);
+        let other_map = report("other", [0.0, 0.0, 10.0], &[("Severity", "High")]);
+        let mut too_old = report("map", [0.0, 0.0, 10.0], &[("Severity", "High")]);
+        too_old.submission.submission_timestamp -= 73 * 3600;
+        let mut not_a_bug_report = report("map", [0.0, 0.0, 10.0], &[]);
+        not_a_bug_report.submission.extra_data.clear();
+        for earlier in [&close, &closer, &other_map, &too_old, &not_a_bug_report] {
+            store.insert(earlier).unwrap();
+        }
+        store.insert(&record).unwrap();
+
+        let matches = detector().find(&store, &record).unwrap();
+        let ids: Vec<Uuid> = matches.iter().map(|m| m.submission_id).collect();
+        assert_eq!(ids, [closer.id, close.id]);
+        assert_eq!(matches[0].distance, 1.0);
+    }
+
+    #[test]
+    fn disabled_detection_finds_nothing() {
+        let store = SubmissionStore::open(test_support::temp_dir().join("submissions.db")).unwrap();
+        let earlier = report("map", [1.0, 1.0, 1.0], &[]);
+        let record = report("map", [1.0, 1.0, 1.0], &[]);
+        store.insert(&earlier).unwrap();
+        store.insert(&record).unwrap();
+
+        let disabled = DuplicateDetector { window_secs: 0, ..detector() };
+        assert!(disabled.find(&store, &record).unwrap().is_empty());
+        assert_eq!(detector().find(&store, &record).unwrap().len(), 1);
+    }
+}
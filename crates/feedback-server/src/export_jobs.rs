@@ -0,0 +1,383 @@
+use crate::exporter::{self, ExportFormat};
+use crate::persistence::{self, Loaded};
+use crate::state::ServerState;
+use crate::storage::StorageBackend;
+use crate::submission_store::SubmissionFilter;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+const INDEX_FILE: &str = "export_index.json";
+const DEFAULT_RETENTION_DAYS: u64 = 14;
+
+/// What an export job selects, as requested by the caller
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ExportFilters {
+    pub survey_id: Option<String>,
+    pub map_name: Option<String>,
+    pub user_xuid: Option<String>,
+    pub from: Option<u64>, // submission_timestamp, inclusive
+    pub to: Option<u64>,   // submission_timestamp, inclusive
+    #[serde(default)]
+    pub since_last_export: bool, // only submissions indexed after the last export with the same survey/map/tester filters
+}
+
+impl ExportFilters {
+    /// Exports of the same survey, map and tester continue each other in "since last export" mode
+    fn same_selection(&self, other: &ExportFilters) -> bool {
+        self.survey_id == other.survey_id && self.map_name == other.map_name && self.user_xuid == other.user_xuid
+    }
+
+    fn submission_filter(&self, after_seq: Option<i64>) -> SubmissionFilter {
+        SubmissionFilter {
+            survey_id: self.survey_id.clone(),
+            map_name: self.map_name.clone(),
+            user_xuid: self.user_xuid.clone(),
+            from_timestamp: self.from,
+            to_timestamp: self.to,
+            after_seq,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum ExportStatus {
+    Running,
+    Completed,
+    Failed { error: String },
+    Expired { deleted_at: u64 }, // the file was removed after the retention period
+}
+
+/// An export and its output file, `EXPORTS/{filename}` in the storage backend, tracked in `export_index.json`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ExportJob {
+    pub id: Uuid,
+    pub mod_key: String,
+    pub format: ExportFormat,
+    pub filters: ExportFilters,
+    pub status: ExportStatus,
+    pub total: usize,     // submissions selected
+    pub processed: usize, // submissions written so far
+    pub filename: Option<String>,
+    pub size_bytes: u64,
+    pub created_at: u64,
+    pub finished_at: Option<u64>,
+    pub expires_at: Option<u64>, // when the file gets deleted
+    pub since_seq: Option<i64>,  // submissions before this index position were skipped ("since last export")
+    pub up_to_seq: i64,          // index position of the newest submission when the records were read
+}
+
+/// Why an export could not be started
+#[derive(Debug)]
+pub enum StartError {
+    NoSubmissions,
+    NothingNew { last_export_at: u64 },
+    Index(String),
+}
+
+impl fmt::Display for StartError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoSubmissions => write!(f, "no submissions match the filters"),
+            Self::NothingNew { last_export_at } => write!(f, "no new submissions since the last export at {}", last_export_at),
+            Self::Index(e) => write!(f, "failed to read the submission index: {}", e),
+        }
+    }
+}
+
+/// Key of an export file in the storage backend
+pub fn storage_key(filename: &str) -> String {
+    format!("EXPORTS/{}", filename)
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+pub struct ExportJobs {
+    jobs: DashMap<Uuid, ExportJob>,
+    export_dir: PathBuf,
+    index_path: PathBuf,
+    pub retention_secs: u64,
+}
+
+impl ExportJobs {
+    /// Loads the job index. Jobs still running when the server stopped are marked failed and their partial files removed.
+    pub fn load(base_dir: &Path) -> Self {
+        let retention_days = std::env::var("EXPORT_RETENTION_DAYS").ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_RETENTION_DAYS);
+        let index_path = base_dir.join(INDEX_FILE);
+
+        let loaded: Vec<ExportJob> = match persistence::load_json(&index_path) {
+            Ok(Loaded::Primary(jobs)) => jobs,
+            Ok(Loaded::Backup(jobs)) => {
+                warn!("{} was unreadable, restored it from {}.bak", INDEX_FILE, INDEX_FILE);
+                jobs
+            }
+            Ok(Loaded::Missing) => Vec::new(),
+            Err(e) => {
+                error!("Failed to load the export index, starting with an empty one: {}", e);
+                persistence::quarantine(&index_path);
+                Vec::new()
+            }
+        };
+
+        let exports = Self {
+            jobs: DashMap::new(),
+            export_dir: base_dir.join("EXPORTS"),
+            index_path,
+            retention_secs: retention_days * 24 * 60 * 60,
+        };
+
+        let mut interrupted = 0;
+        for mut job in loaded {
+            if matches!(job.status, ExportStatus::Running) {
+                exports.remove_partial_files(&job);
+                job.status = ExportStatus::Failed { error: "Interrupted by a server restart".to_string() };
+                job.finished_at = Some(now_secs());
+                interrupted += 1;
+            }
+            exports.jobs.insert(job.id, job);
+        }
+        if interrupted > 0 {
+            warn!("{} exports were interrupted by the last shutdown", interrupted);
+            exports.save_to_disk();
+        }
+        info!("Loaded {} export jobs from {}", exports.jobs.len(), INDEX_FILE);
+        exports
+    }
+
+    pub fn save_to_disk(&self) {
+        let mut jobs: Vec<ExportJob> = self.jobs.iter().map(|job| job.clone()).collect();
+        jobs.sort_by_key(|job| job.created_at);
+
+        match serde_json::to_string_pretty(&jobs) {
+            Ok(json) => {
+                if let Err(e) = persistence::write_atomic_with_backup(&self.index_path, json.as_bytes()) {
+                    error!("Failed to save {}: {}", INDEX_FILE, e);
+                }
+            }
+            Err(e) => error!("Failed to serialize the export index: {}", e),
+        }
+    }
+
+    pub fn get(&self, id: &Uuid) -> Option<ExportJob> {
+        self.jobs.get(id).map(|job| job.clone())
+    }
+
+    /// The key's export jobs, newest first
+    pub fn for_key(&self, mod_key: &str) -> Vec<ExportJob> {
+        let mut jobs: Vec<ExportJob> = self.jobs.iter()
+            .filter(|job| job.mod_key == mod_key)
+            .map(|job| job.clone())
+            .collect();
+        jobs.sort_by_key(|job| std::cmp::Reverse(job.created_at));
+        jobs
+    }
+
+    /// The completed job whose output file is `filename`, if it has not expired yet
+    pub fn completed_by_filename(&self, filename: &str) -> Option<ExportJob> {
+        self.jobs.iter()
+            .find(|job| job.filename.as_deref() == Some(filename) && matches!(job.status, ExportStatus::Completed))
+            .map(|job| job.clone())
+    }
+
+    /// The newest successful export of the same selection, expired ones included since only their position matters
+    fn last_export(&self, mod_key: &str, filters: &ExportFilters) -> Option<ExportJob> {
+        self.jobs.iter()
+            .filter(|job| job.mod_key == mod_key && job.filters.same_selection(filters))
+            .filter(|job| matches!(job.status, ExportStatus::Completed | ExportStatus::Expired { .. }))
+            .max_by_key(|job| job.up_to_seq)
+            .map(|job| job.clone())
+    }
+
+    fn record_progress(&self, id: &Uuid) {
+        if let Some(mut job) = self.jobs.get_mut(id) {
+            job.processed += 1;
+        }
+    }
+
+    /// Output names of `write_export`, which may not have finished writing
+    fn remove_partial_files(&self, job: &ExportJob) {
+        for extension in ["zip", job.format.extension()] {
+            let _ = fs::remove_file(self.export_dir.join(format!("{}.{}", job.id, extension)));
+        }
+    }
+
+    /// Transfers the export history of `old_key` to `new_key`, so "since last export" continues after rotation
+    pub fn reassign_key(&self, old_key: &str, new_key: &str) {
+        for mut job in self.jobs.iter_mut() {
+            if job.mod_key == old_key {
+                job.mod_key = new_key.to_string();
+            }
+        }
+        self.save_to_disk();
+    }
+
+    /// Deletes the files of exports past their retention, forgets failed jobs after the same period
+    /// and removes untracked files left in the local EXPORTS directory by older versions.
+    pub fn cleanup_expired(&self, storage: &dyn StorageBackend) {
+        let now = now_secs();
+        let mut changed = false;
+
+        for mut job in self.jobs.iter_mut() {
+            if matches!(job.status, ExportStatus::Completed) && job.expires_at.is_some_and(|exp| now >= exp) {
+                if let Some(filename) = &job.filename
+                    && let Err(e) = storage.delete(&storage_key(filename)) {
+                    error!("Failed to delete expired export {}: {}", filename, e);
+                    continue;
+                }
+                info!("Deleted expired export {} of {} ({} bytes)", job.id, job.mod_key, job.size_bytes);
+                job.status = ExportStatus::Expired { deleted_at: now };
+                changed = true;
+            }
+        }
+
+        let before = self.jobs.len();
+        self.jobs.retain(|_, job| {
+            !matches!(job.status, ExportStatus::Failed { .. })
+                || job.finished_at.is_none_or(|finished| now.saturating_sub(finished) < self.retention_secs)
+        });
+        changed |= self.jobs.len() != before;
+
+        let tracked: HashSet<String> = self.jobs.iter()
+            .filter(|job| matches!(job.status, ExportStatus::Running | ExportStatus::Completed))
+            .filter_map(|job| job.filename.clone())
+            .collect();
+        let running: HashSet<Uuid> = self.jobs.iter()
+            .filter(|job| matches!(job.status, ExportStatus::Running))
+            .map(|job| job.id)
+            .collect();
+        if let Ok(entries) = fs::read_dir(&self.export_dir) {
+            for entry in entries.flatten() {
+                let name = entry.file_name().to_string_lossy().to_string();
+                let is_running = Path::new(&name).file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .and_then(|stem| Uuid::parse_str(stem).ok())
+                    .is_some_and(|id| running.contains(&id));
+                if tracked.contains(&name) || is_running {
+                    continue;
+                }
+                let age = entry.metadata().ok()
+                    .and_then(|meta| meta.modified().ok())
+                    .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+                    .map(|age| age.as_secs());
+                if age.is_some_and(|age| age >= self.retention_secs) && fs::remove_file(entry.path()).is_ok() {
+                    info!("Deleted untracked export {}", name);
+                }
+            }
+        }
+
+        if changed {
+            self.save_to_disk();
+        }
+    }
+}
+
+/// Selects the submissions, records a running job and writes the export on a blocking thread.
+/// The returned handle resolves to the finished job, completed or failed; progress is visible through `ExportJobs::get` meanwhile.
+pub fn start_export(
+    state: &ServerState,
+    mod_key: &str,
+    format: ExportFormat,
+    filters: ExportFilters,
+) -> Result<(ExportJob, JoinHandle<ExportJob>), StartError> {
+    let exports = &state.file_manager.exports;
+
+    let previous = filters.since_last_export.then(|| exports.last_export(mod_key, &filters)).flatten();
+    let since_seq = previous.as_ref().map(|job| job.up_to_seq);
+
+    // Read the position first: a submission indexed during the query is exported again next time rather than skipped
+    let up_to_seq = state.submissions.latest_seq().map_err(|e| StartError::Index(e.to_string()))?;
+    let records = state.submissions.query(mod_key, &filters.submission_filter(since_seq))
+        .map_err(|e| StartError::Index(e.to_string()))?;
+    if records.is_empty() {
+        return Err(match previous {
+            Some(previous) => StartError::NothingNew { last_export_at: previous.created_at },
+            None => StartError::NoSubmissions,
+        });
+    }
+
+    let definitions = state.submissions.definitions(mod_key, None).unwrap_or_else(|e| {
+        error!("Failed to load survey definitions for export: {}", e);
+        Vec::new()
+    });
+
+    let job = ExportJob {
+        id: Uuid::new_v4(),
+        mod_key: mod_key.to_string(),
+        format,
+        filters,
+        status: ExportStatus::Running,
+        total: records.len(),
+        processed: 0,
+        filename: None,
+        size_bytes: 0,
+        created_at: now_secs(),
+        finished_at: None,
+        expires_at: None,
+        since_seq,
+        up_to_seq,
+    };
+    exports.jobs.insert(job.id, job.clone());
+    exports.save_to_disk();
+    info!("Started {} export {} of {} ({} submissions)", format.label(), job.id, mod_key, records.len());
+
+    let file_manager = state.file_manager.clone();
+    let job_id = job.id;
+    let handle = tokio::spawn(async move {
+        // Move heavy export operation to a blocking thread to avoid freezing the async runtime
+        let export_result = tokio::task::spawn_blocking({
+            let file_manager = file_manager.clone();
+            move || {
+                let exports = &file_manager.exports;
+                // Written locally first, ZIP archives need to seek; then handed to the storage backend
+                let filename = exporter::write_export(
+                    &records,
+                    &file_manager,
+                    &definitions,
+                    format,
+                    &exports.export_dir,
+                    job_id,
+                    &|| exports.record_progress(&job_id),
+                )?;
+                let size_bytes = file_manager.storage.put_file(&storage_key(&filename), &exports.export_dir.join(&filename))?;
+                Ok((filename, size_bytes))
+            }
+        }).await.unwrap_or_else(|e| Err(format!("Task panicked: {}", e)));
+
+        let exports = &file_manager.exports;
+        let now = now_secs();
+        let mut job = exports.jobs.get_mut(&job_id).expect("running export jobs are never removed");
+        job.finished_at = Some(now);
+        match export_result {
+            Ok((filename, size_bytes)) => {
+                job.size_bytes = size_bytes;
+                job.filename = Some(filename);
+                job.expires_at = Some(now + exports.retention_secs);
+                job.status = ExportStatus::Completed;
+                info!("Export {} finished ({} bytes)", job_id, job.size_bytes);
+            }
+            Err(e) => {
+                error!("Failed to create export {}: {}", job_id, e);
+                exports.remove_partial_files(&job);
+                job.status = ExportStatus::Failed { error: e };
+            }
+        }
+        let finished = job.clone();
+        drop(job);
+        exports.save_to_disk();
+        finished
+    });
+
+    Ok((job, handle))
+}
@@ -0,0 +1,415 @@
+use crate::file_manager::{FileManager, FileStatus};
+use crate::models::FormSubmission;
+use crate::submission_store::StoredSubmission;
+use crate::surveys::{self, Question, StoredDefinition};
+use indexmap::{IndexMap, IndexSet};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use uuid::Uuid;
+use zip::write::SimpleFileOptions;
+use zip::ZipWriter;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// Raw submission JSONs and attachments, same layout as ANSWERS
+    #[default]
+    Zip,
+    /// One row per submission and one column per question
+    Csv,
+    /// One submission JSON object per line
+    Ndjson,
+}
+
+impl ExportFormat {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "zip" => Some(Self::Zip),
+            "csv" => Some(Self::Csv),
+            "ndjson" => Some(Self::Ndjson),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Zip => "zip",
+            Self::Csv => "csv",
+            Self::Ndjson => "ndjson",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Zip => "ZIP",
+            Self::Csv => "CSV",
+            Self::Ndjson => "NDJSON",
+        }
+    }
+}
+
+/// A line of the NDJSON export
+#[derive(Serialize)]
+struct NdjsonRow<'a> {
+    submission_id: Uuid,
+    received_at: u64,
+    survey_version: Option<u32>,
+    #[serde(flatten)]
+    submission: &'a FormSubmission,
+}
+
+/// Writes an export of `records` into `export_dir` and returns the created file name.
+///
+/// CSV and NDJSON exports are split by survey_id: if the records answer more than one survey,
+/// the result is a ZIP with one file per survey, otherwise a single plain file.
+/// `definitions` are the key's registered survey definitions, used for CSV question types.
+/// `on_record` is called after each record is written, to report progress.
+pub fn write_export(
+    records: &[StoredSubmission],
+    file_manager: &FileManager,
+    definitions: &[StoredDefinition],
+    format: ExportFormat,
+    export_dir: &Path,
+    export_id: Uuid,
+    on_record: &dyn Fn(),
+) -> Result<String, String> {
+    if format == ExportFormat::Zip {
+        let filename = format!("{}.zip", export_id);
+        let file = File::create(export_dir.join(&filename)).map_err(|e| e.to_string())?;
+        write_raw_archive(records, file_manager, file, on_record)?;
+        return Ok(filename);
+    }
+
+    let mut by_survey: IndexMap<&str, Vec<&StoredSubmission>> = IndexMap::new();
+    for record in records {
+        by_survey.entry(record.submission.survey_id.as_str()).or_default().push(record);
+    }
+
+    if by_survey.len() <= 1 {
+        let filename = format!("{}.{}", export_id, format.extension());
+        let file = File::create(export_dir.join(&filename)).map_err(|e| e.to_string())?;
+        let group: Vec<&StoredSubmission> = records.iter().collect();
+        write_table(&group, definitions, format, file, on_record)?;
+        return Ok(filename);
+    }
+
+    let filename = format!("{}.zip", export_id);
+    let file = File::create(export_dir.join(&filename)).map_err(|e| e.to_string())?;
+    let mut zip = ZipWriter::new(file);
+    let options = SimpleFileOptions::default();
+    let mut used_names = HashSet::new();
+
+    for (survey_id, group) in &by_survey {
+        let stem = Path::new(survey_id).file_stem().and_then(|s| s.to_str()).unwrap_or("survey");
+        // Survey ids in different folders can share a file name
+        let mut name = format!("{}.{}", stem, format.extension());
+        let mut n = 1;
+        while !used_names.insert(name.clone()) {
+            n += 1;
+            name = format!("{}_{}.{}", stem, n, format.extension());
+        }
+
+        zip.start_file(name, options).map_err(|e| e.to_string())?;
+        write_table(group, definitions, format, &mut zip, on_record)?;
+    }
+    zip.finish().map_err(|e| e.to_string())?;
+
+    Ok(filename)
+}
+
+fn write_table<W: Write>(
+    records: &[&StoredSubmission],
+    definitions: &[StoredDefinition],
+    format: ExportFormat,
+    writer: W,
+    on_record: &dyn Fn(),
+) -> Result<(), String> {
+    match format {
+        ExportFormat::Csv => write_csv(records, definitions, writer, on_record),
+        ExportFormat::Ndjson => write_ndjson(records, writer, on_record),
+        ExportFormat::Zip => unreachable!("ZIP exports are not tabular"),
+    }
+}
+
+fn write_ndjson<W: Write>(records: &[&StoredSubmission], mut writer: W, on_record: &dyn Fn()) -> Result<(), String> {
+    for record in records {
+        let row = NdjsonRow {
+            submission_id: record.id,
+            received_at: record.received_at,
+            survey_version: record.survey_version,
+            submission: &record.submission,
+        };
+        serde_json::to_writer(&mut writer, &row).map_err(|e| e.to_string())?;
+        writer.write_all(b"\n").map_err(|e| e.to_string())?;
+        on_record();
+    }
+    writer.flush().map_err(|e| e.to_string())
+}
+
+fn write_csv<W: Write>(
+    records: &[&StoredSubmission],
+    definitions: &[StoredDefinition],
+    writer: W,
+    on_record: &dyn Fn(),
+) -> Result<(), String> {
+    // Columns are collected in first-seen order, so they follow the survey's question order
+    let mut questions = IndexSet::new();
+    let mut extra_fields = IndexSet::new();
+    for record in records {
+        questions.extend(record.submission.answers.keys().cloned());
+        extra_fields.extend(record.submission.extra_data.keys().cloned());
+    }
+
+    // Checkbox questions of the answered definitions get an extra 0/1 column per choice
+    let answered: Vec<&StoredDefinition> = definitions.iter()
+        .filter(|d| records.iter().any(|r| r.submission.survey_id == d.survey_id && r.survey_version == Some(d.version)))
+        .collect();
+    let question_types = surveys::question_types(&answered);
+    let checkbox_choices: Vec<(&String, &[String])> = questions.iter()
+        .filter_map(|q| match question_types.get(q) {
+            Some(Question::Checkboxes { choices, .. }) => Some((q, choices.as_slice())),
+            _ => None,
+        })
+        .collect();
+
+    let mut csv = csv::Writer::from_writer(writer);
+
+    let mut header = vec![
+        "submission_id".to_string(),
+        "survey_id".to_string(),
+        "map_name".to_string(),
+        "user_name".to_string(),
+        "user_xuid".to_string(),
+        "game_timestamp".to_string(),
+        "submission_timestamp".to_string(),
+        "received_at".to_string(),
+        "survey_version".to_string(),
+        "files".to_string(),
+    ];
+    header.extend(questions.iter().cloned());
+    for (q, choices) in &checkbox_choices {
+        header.extend(choices.iter().map(|c| format!("{} [{}]", q, c)));
+    }
+    header.extend(extra_fields.iter().map(|field| format!("extra:{}", field)));
+    csv.write_record(header.into_iter().map(csv_cell)).map_err(|e| e.to_string())?;
+
+    for record in records {
+        let submission = &record.submission;
+        let mut row = vec![
+            record.id.to_string(),
+            submission.survey_id.clone(),
+            submission.map_name.clone(),
+            submission.user_name.clone(),
+            submission.user_xuid.clone(),
+            submission.game_timestamp.to_string(),
+            submission.submission_timestamp.to_string(),
+            record.received_at.to_string(),
+            record.survey_version.map(|v| v.to_string()).unwrap_or_default(),
+            submission.files.iter().map(|(_, name)| name.as_str()).collect::<Vec<_>>().join("; "),
+        ];
+        row.extend(questions.iter().map(|q| submission.answers.get(q).cloned().unwrap_or_default()));
+        for (q, choices) in &checkbox_choices {
+            let selected = submission.answers.get(*q)
+                .map(|a| surveys::selected_choices(a, choices))
+                .unwrap_or_default();
+            row.extend(choices.iter().map(|c| if selected.contains(&c.as_str()) { "1" } else { "0" }.to_string()));
+        }
+        row.extend(extra_fields.iter().map(|field| match submission.extra_data.get(field) {
+            Some(serde_json::Value::String(s)) => s.clone(),
+            Some(value) => value.to_string(),
+            None => String::new(),
+        }));
+        csv.write_record(row.into_iter().map(csv_cell)).map_err(|e| e.to_string())?;
+        on_record();
+    }
+
+    csv.flush().map_err(|e| e.to_string())
+}
+
+/// Player-written text starting like a formula is prefixed with `'`, so spreadsheets show it instead of evaluating it.
+/// Numbers are left alone, negative ones included.
+fn csv_cell(value: String) -> String {
+    if value.starts_with(['=', '+', '-', '@', '\t', '\r']) && value.parse::<f64>().is_err() {
+        format!("'{}", value)
+    } else {
+        value
+    }
+}
+
+/// Writes submission JSONs and their active attachments: {user_xuid}/{submission}.json and {user_xuid}/files/{attachment}
+fn write_raw_archive<W: Write + std::io::Seek>(
+    records: &[StoredSubmission],
+    file_manager: &FileManager,
+    writer: W,
+    on_record: &dyn Fn(),
+) -> Result<(), String> {
+    let mut zip = ZipWriter::new(writer);
+    let options = SimpleFileOptions::default();
+    let mut used_names = HashSet::new();
+
+    for record in records {
+        let user_dir = Path::new(&record.submission.user_xuid)
+            .file_name()
+            .and_then(|s| s.to_str())
+            .unwrap_or("unknown_user");
+
+        let json_name = file_manager.files.get(&record.id)
+            .map(|meta| meta.original_name.clone())
+            .unwrap_or_else(|| format!("{}.json", record.id));
+        let json = serde_json::to_string_pretty(&record.submission).map_err(|e| e.to_string())?;
+
+        zip.start_file(unique_zip_name(&mut used_names, user_dir, &json_name, &record.id), options).map_err(|e| e.to_string())?;
+        zip.write_all(json.as_bytes()).map_err(|e| e.to_string())?;
+
+        for file_id in &record.file_ids {
+            let Some(meta) = file_manager.files.get(file_id).map(|m| m.clone()) else { continue };
+            if !matches!(meta.status, FileStatus::Active) {
+                continue;
+            }
+
+            let name = unique_zip_name(&mut used_names, &format!("{}/files", user_dir), &meta.original_name, &meta.id);
+            zip.start_file(name, options).map_err(|e| e.to_string())?;
+            let mut f = file_manager.open_file(&meta)?;
+            std::io::copy(&mut f, &mut zip).map_err(|e| e.to_string())?;
+        }
+        on_record();
+    }
+    zip.finish().map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Builds a path inside the export archive, prefixing the entry id when the name is already taken
+fn unique_zip_name(used_names: &mut HashSet<String>, dir: &str, name: &str, id: &Uuid) -> String {
+    let candidate = format!("{}/{}", dir, name);
+    if used_names.insert(candidate.clone()) {
+        return candidate;
+    }
+    let fallback = format!("{}/{}_{}", dir, id, name);
+    used_names.insert(fallback.clone());
+    fallback
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::surveys::SurveyDefinition;
+    use crate::test_support;
+    use std::io::Read;
+
+    fn record(survey_id: &str, answers: &[(&str, &str)], extra: serde_json::Value) -> StoredSubmission {
+        let mut submission = test_support::submission("Forest", answers, extra);
+        submission.survey_id = survey_id.to_string();
+        StoredSubmission { survey_version: Some(1), ..test_support::stored("key", submission) }
+    }
+
+    fn definition(survey_id: &str, widgets: serde_json::Value) -> StoredDefinition {
+        StoredDefinition {
+            survey_id: survey_id.to_string(),
+            version: 1,
+            content_hash: String::new(),
+            registered_at: 0,
+            definition: SurveyDefinition { title: String::new(), widgets: serde_json::from_value(widgets).unwrap() },
+        }
+    }
+
+    fn csv_rows(records: &[StoredSubmission], definitions: &[StoredDefinition]) -> Vec<Vec<String>> {
+        let mut out = Vec::new();
+        write_csv(&records.iter().collect::<Vec<_>>(), definitions, &mut out, &|| {}).unwrap();
+        csv::Reader::from_reader(out.as_slice()).into_records()
+            .map(|row| row.unwrap().iter().map(str::to_string).collect())
+            .collect()
+    }
+
+    fn csv_header(records: &[StoredSubmission], definitions: &[StoredDefinition]) -> Vec<String> {
+        let mut out = Vec::new();
+        write_csv(&records.iter().collect::<Vec<_>>(), definitions, &mut out, &|| {}).unwrap();
+        csv::Reader::from_reader(out.as_slice()).headers().unwrap().iter().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn csv_has_fixed_columns_then_questions_then_extra_data() {
+        let records = [
+            record("bug_report.json", &[("What happened?", "Fell through"), ("Rating", "3")], serde_json::json!({ "Build": "1.2" })),
+            record("bug_report.json", &[("What happened?", "Crash"), ("Steps", "Load map")], serde_json::json!({ "Seed": 7 })),
+        ];
+
+        assert_eq!(csv_header(&records, &[]), [
+            "submission_id", "survey_id", "map_name", "user_name", "user_xuid", "game_timestamp", "submission_timestamp",
+            "received_at", "survey_version", "files", "What happened?", "Rating", "Steps", "extra:Build", "extra:Seed",
+        ]);
+        let rows = csv_rows(&records, &[]);
+        assert_eq!(rows[0][0], records[0].id.to_string());
+        assert_eq!(rows[0][10..], ["Fell through", "3", "", "1.2", ""]);
+        assert_eq!(rows[1][10..], ["Crash", "", "Load map", "", "7"]);
+    }
+
+    #[test]
+    fn checkbox_questions_get_a_column_per_choice() {
+        let definitions = [definition("bug_report.json", serde_json::json!([
+            { "type": "Checkboxes", "text": "Where?", "choices": ["Menu", "In game, online", "Loading"] },
+        ]))];
+        let records = [
+            record("bug_report.json", &[("Where?", "Menu, In game, online")], serde_json::json!({})),
+            record("bug_report.json", &[], serde_json::json!({})),
+        ];
+
+        let header = csv_header(&records, &definitions);
+        assert_eq!(header[10..], ["Where?", "Where? [Menu]", "Where? [In game, online]", "Where? [Loading]"]);
+        let rows = csv_rows(&records, &definitions);
+        assert_eq!(rows[0][10..], ["Menu, In game, online", "1", "1", "0"]);
+        assert_eq!(rows[1][10..], ["", "0", "0", "0"]);
+    }
+
+    #[test]
+    fn csv_cells_cannot_start_a_formula() {
+        let records = [record("bug_report.json", &[
+            ("A", "=HYPERLINK(\"http://x\")"), ("B", "+1+1"), ("C", "-2"), ("D", "@SUM(A1)"), ("E", "\tTab"), ("F", "fine = ok"),
+        ], serde_json::json!({}))];
+
+        let rows = csv_rows(&records, &[]);
+        assert_eq!(rows[0][10..], ["'=HYPERLINK(\"http://x\")", "'+1+1", "-2", "'@SUM(A1)", "'\tTab", "fine = ok"]);
+    }
+
+    #[test]
+    fn surveys_are_split_into_files_of_a_zip() {
+        let dir = test_support::temp_dir();
+        let file_manager = FileManager::new(100, &dir);
+        let records = [
+            record("bug_report.json", &[("What happened?", "Crash")], serde_json::json!({})),
+            record("feedback.json", &[("Rating", "9")], serde_json::json!({})),
+            record("old/feedback.json", &[("Rating", "2")], serde_json::json!({})),
+            record("older/feedback.json", &[("Rating", "1")], serde_json::json!({})),
+        ];
+
+        let export_id = Uuid::new_v4();
+        let filename = write_export(&records, &file_manager, &[], ExportFormat::Csv, &dir, export_id, &|| {}).unwrap();
+        assert_eq!(filename, format!("{}.zip", export_id));
+
+        let mut zip = zip::ZipArchive::new(File::open(dir.join(&filename)).unwrap()).unwrap();
+        let names: Vec<&str> = zip.file_names().collect();
+        let mut sorted = names.clone();
+        sorted.sort();
+        assert_eq!(sorted, ["bug_report.csv", "feedback.csv", "feedback_2.csv", "feedback_3.csv"]);
+
+        let mut feedback = String::new();
+        zip.by_name("feedback.csv").unwrap().read_to_string(&mut feedback).unwrap();
+        assert_eq!(feedback.lines().count(), 2);
+        assert!(feedback.lines().next().unwrap().ends_with(",Rating"));
+    }
+
+    #[test]
+    fn one_survey_is_exported_as_a_plain_file() {
+        let dir = test_support::temp_dir();
+        let file_manager = FileManager::new(100, &dir);
+        let records = [record("feedback.json", &[("Rating", "9")], serde_json::json!({}))];
+
+        let filename = write_export(&records, &file_manager, &[], ExportFormat::Ndjson, &dir, Uuid::new_v4(), &|| {}).unwrap();
+        assert!(filename.ends_with(".ndjson"));
+        let line: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(dir.join(filename)).unwrap()).unwrap();
+        assert_eq!(line["submission_id"], records[0].id.to_string());
+        assert_eq!(line["answers"]["Rating"], "9");
+    }
+}
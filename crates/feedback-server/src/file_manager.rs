@@ -1,10 +1,21 @@
+use crate::demos::{self, DemoInfo};
+use crate::export_jobs::ExportJobs;
+use crate::log_triage::{LogAnalyzer, LogFindings};
+use crate::metrics::Metrics;
+use crate::persistence::{self, Loaded};
+use crate::previews::Preview;
+use crate::retention::{self, RetentionPolicy};
+use crate::storage::{self, Served, StorageBackend};
+use dashmap::mapref::entry::Entry;
 use dashmap::DashMap;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
 use std::fs;
+use std::io::Read;
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tracing::{error, info, warn};
 use uuid::Uuid;
 
@@ -23,18 +34,79 @@ pub struct FileMetadata {
     pub mod_key: String,
     pub original_name: String,
     pub size_bytes: u64,
-    pub path: PathBuf,
+    pub path: PathBuf, // local path, or the storage backend location of blobs
     pub uploaded_at: u64,
     pub expires_at: Option<u64>,
     pub is_priority: bool,
     pub status: FileStatus,
+    #[serde(default)]
+    pub content_hash: Option<String>, // SHA-256, set for deduplicated uploads stored in BLOBS
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub previews: Vec<Preview>, // poster frame and preview clip generated from a video
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub derived_from: Option<Uuid>, // the video a preview was generated from, expires with it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub demo_info: Option<DemoInfo>, // headers of the demos in an uploaded .dem or demo ZIP
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_findings: Option<LogFindings>, // known problems found in an uploaded console log
+}
+
+/// A stored object in BLOBS shared by every `FileMetadata` with the same content hash
+#[derive(Debug, Clone)]
+pub struct BlobRef {
+    pub key: String,
+    pub size_bytes: u64,
+    pub ref_count: usize,
+}
+
+/// Deduplicated bytes stored for one moderator key, kept next to the index so quota checks don't scan it
+#[derive(Debug, Default)]
+struct KeyUsage {
+    bytes: u64,
+    blob_refs: HashMap<String, usize>,
+}
+
+/// A resumable upload in progress. Received bytes are appended to TEMP_UPLOADS/{upload_id},
+/// so the current offset is always the size of that file.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UploadSession {
+    pub mod_key: String,
+    pub original_name: String,
+    pub total_bytes: u64,
+    pub created_at: u64,
+    #[serde(skip)]
+    pub is_receiving: bool, // a chunk is currently being written
+    pub is_finalized: bool, // complete, waiting to be attached to a submission
+}
+
+/// An upload waiting in TEMP_UPLOADS to be attached to a submission
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TempUpload {
+    pub mod_key: String,
+    pub original_name: String,
+}
+
+/// Uploads not attached to a submission yet, kept in pending_uploads.json so clients can resume and attach them after a restart
+#[derive(Serialize, Deserialize, Default)]
+struct PendingUploads {
+    sessions: HashMap<Uuid, UploadSession>,
+    uploads: HashMap<Uuid, TempUpload>,
 }
 
 pub struct FileManager {
     pub files: Arc<DashMap<Uuid, FileMetadata>>,
     pub max_storage_bytes: u64,
     pub base_dir: PathBuf,
-    pub temp_file_names: DashMap<Uuid, String>,
+    pub temp_uploads: DashMap<Uuid, TempUpload>,
+    pub upload_sessions: DashMap<Uuid, UploadSession>,
+    pub blobs: DashMap<String, BlobRef>,
+    key_usage: DashMap<String, KeyUsage>,
+    pub exports: ExportJobs,
+    pub retention: RwLock<RetentionPolicy>,
+    retention_path: PathBuf,
+    pub storage: Arc<dyn StorageBackend>,
+    demo_inspect_max_bytes: u64,
+    log_analyzer: LogAnalyzer,
 }
 
 impl FileManager {
@@ -46,53 +118,171 @@ impl FileManager {
         let _ = fs::create_dir_all(base_dir.join("TEMP_UPLOADS"));
         let _ = fs::create_dir_all(base_dir.join("ANSWERS"));
         let _ = fs::create_dir_all(base_dir.join("EXPORTS"));
+        let _ = fs::create_dir_all(base_dir.join("BLOBS"));
 
         let files = Arc::new(DashMap::new());
 
-        // Load existing metadata if available
+        // Load existing metadata if available, an unreadable index is kept aside and rebuilt by reconciliation
         let index_path = base_dir.join("file_index.json");
-        if let Ok(data) = fs::read_to_string(&index_path) {
-            if let Ok(parsed_map) = serde_json::from_str::<HashMap<Uuid, FileMetadata>>(&data) {
-                for (k, v) in parsed_map {
-                    files.insert(k, v);
-                }
-                info!("Loaded {} files from file_index.json", files.len());
+        let loaded = match persistence::load_json::<HashMap<Uuid, FileMetadata>>(&index_path) {
+            Ok(Loaded::Primary(map)) => map,
+            Ok(Loaded::Backup(map)) => {
+                warn!("file_index.json was unreadable, restored {} files from file_index.json.bak", map.len());
+                map
+            }
+            Ok(Loaded::Missing) => HashMap::new(),
+            Err(e) => {
+                error!("Failed to load the file index, starting with an empty one: {}", e);
+                persistence::quarantine(&index_path);
+                HashMap::new()
+            }
+        };
+        for (k, v) in loaded {
+            files.insert(k, v);
+        }
+        info!("Loaded {} files from file_index.json", files.len());
+
+        let pending = match persistence::load_json::<PendingUploads>(&base_dir.join("pending_uploads.json")) {
+            Ok(Loaded::Primary(pending)) | Ok(Loaded::Backup(pending)) => pending,
+            Ok(Loaded::Missing) => PendingUploads::default(),
+            Err(e) => {
+                error!("Failed to load pending uploads, clients have to upload them again: {}", e);
+                PendingUploads::default()
             }
+        };
+        if !pending.sessions.is_empty() || !pending.uploads.is_empty() {
+            info!("Loaded {} upload sessions and {} pending uploads", pending.sessions.len(), pending.uploads.len());
         }
 
-        Self {
+        let exports = ExportJobs::load(&base_dir);
+        let storage = storage::from_env(&base_dir);
+
+        let retention_path = RetentionPolicy::config_path(&base_dir);
+        let retention = RetentionPolicy::load(&retention_path).unwrap_or_else(|e| {
+            error!("Failed to load the retention policy, using the built-in tiers until it is fixed: {}", e);
+            RetentionPolicy::default()
+        });
+        retention.check_reservations(max_storage_bytes);
+
+        let log_analyzer = LogAnalyzer::load(&base_dir);
+
+        // Demo ZIPs above this size are stored without reading their headers
+        let demo_inspect_max_mb = std::env::var("DEMO_INSPECT_MAX_MB").ok().and_then(|v| v.parse().ok()).unwrap_or(512);
+
+        let file_manager = Self {
             files,
             max_storage_bytes,
             base_dir,
-            temp_file_names: DashMap::new(),
+            temp_uploads: pending.uploads.into_iter().collect(),
+            upload_sessions: pending.sessions.into_iter().collect(),
+            blobs: DashMap::new(),
+            key_usage: DashMap::new(),
+            exports,
+            retention: RwLock::new(retention),
+            retention_path,
+            storage,
+            demo_inspect_max_bytes: demo_inspect_max_mb * ONE_MB,
+            log_analyzer,
+        };
+        file_manager.rebuild_blob_refs();
+        file_manager
+    }
+
+    /// Reference counts are derived from the index, so they can never drift from it
+    pub fn rebuild_blob_refs(&self) {
+        self.blobs.clear();
+        for entry in self.files.iter() {
+            let meta: &FileMetadata = entry.value();
+            if let (FileStatus::Active, Some(hash)) = (&meta.status, &meta.content_hash) {
+                self.blobs.entry(hash.clone())
+                    .or_insert_with(|| BlobRef { key: Self::blob_key(hash), size_bytes: meta.size_bytes, ref_count: 0 })
+                    .ref_count += 1;
+            }
+        }
+        self.rebuild_key_usage();
+    }
+
+    fn rebuild_key_usage(&self) {
+        let mut usage: HashMap<String, KeyUsage> = HashMap::new();
+        for entry in self.files.iter() {
+            Self::add_usage(usage.entry(entry.mod_key.clone()).or_default(), entry.value());
+        }
+        self.key_usage.clear();
+        for (key, key_usage) in usage {
+            self.key_usage.insert(key, key_usage);
+        }
+    }
+
+    /// Counts an active file towards its key, a blob only once per key
+    fn add_usage(usage: &mut KeyUsage, meta: &FileMetadata) {
+        if !matches!(meta.status, FileStatus::Active) {
+            return;
+        }
+        match &meta.content_hash {
+            Some(hash) => {
+                let refs = usage.blob_refs.entry(hash.clone()).or_insert(0);
+                *refs += 1;
+                if *refs == 1 {
+                    usage.bytes += meta.size_bytes;
+                }
+            }
+            None => usage.bytes += meta.size_bytes,
+        }
+    }
+
+    fn remove_usage(usage: &mut KeyUsage, meta: &FileMetadata) {
+        if !matches!(meta.status, FileStatus::Active) {
+            return;
+        }
+        match &meta.content_hash {
+            Some(hash) => {
+                if let Some(refs) = usage.blob_refs.get_mut(hash) {
+                    *refs -= 1;
+                    if *refs == 0 {
+                        usage.blob_refs.remove(hash);
+                        usage.bytes = usage.bytes.saturating_sub(meta.size_bytes);
+                    }
+                }
+            }
+            None => usage.bytes = usage.bytes.saturating_sub(meta.size_bytes),
         }
     }
 
+    /// Deduplicated bytes of the active files of `mod_key`, same as its entry in `storage_usage`
+    pub fn key_usage(&self, mod_key: &str) -> u64 {
+        self.key_usage.get(mod_key).map_or(0, |usage| usage.bytes)
+    }
+
+    pub fn temp_path(&self, temp_file_id: &Uuid) -> PathBuf {
+        self.base_dir.join("TEMP_UPLOADS").join(temp_file_id.to_string())
+    }
+
     pub fn save_to_disk(&self) {
         let index_path = self.base_dir.join("file_index.json");
         let map: HashMap<_, _> = self.files.iter().map(|kv| (*kv.key(), kv.value().clone())).collect();
 
         if let Ok(json) = serde_json::to_string_pretty(&map) {
-            if let Err(e) = fs::write(&index_path, json) {
+            if let Err(e) = persistence::write_atomic_with_backup(&index_path, json.as_bytes()) {
                 error!("Failed to save file_index.json: {}", e);
             }
         }
     }
 
-    fn current_timestamp() -> u64 {
-        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+    /// Writes the upload sessions and temp uploads, call after adding or removing one
+    pub fn save_pending_uploads(&self) {
+        let pending = PendingUploads {
+            sessions: self.upload_sessions.iter().map(|kv| (*kv.key(), kv.value().clone())).collect(),
+            uploads: self.temp_uploads.iter().map(|kv| (*kv.key(), kv.value().clone())).collect(),
+        };
+        if let Ok(json) = serde_json::to_string_pretty(&pending)
+            && let Err(e) = persistence::write_atomic(&self.base_dir.join("pending_uploads.json"), json.as_bytes())
+        {
+            error!("Failed to save pending_uploads.json: {}", e);
+        }
     }
 
-    // Dynamic lifetime calculation based on file size
-    fn calculate_expiration(is_priority: bool, size_bytes: u64) -> Option<u64> {
-        let now = Self::current_timestamp();
-        if size_bytes < ONE_MB || is_priority {
-            None // < 1MB: Keep forever (until global limit hit)
-        } else if size_bytes <= 10 * ONE_MB {
-            Some(now + 30 * 24 * 60 * 60) // 1-10MB: 30 days
-        } else {
-            Some(now + 7 * 24 * 60 * 60) // > 10MB: 7 days
-        }
+    fn current_timestamp() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
     }
 
     pub fn commit_file(
@@ -104,34 +294,85 @@ impl FileManager {
         path: PathBuf,
         size_bytes: u64,
     ) -> Result<(), String> {
+        let uploaded_at = Self::current_timestamp();
         let file_meta = FileMetadata {
             id: file_id,
             mod_key: mod_key.to_string(),
             original_name: original_name.to_string(),
             size_bytes,
             path,
-            uploaded_at: Self::current_timestamp(),
-            expires_at: Self::calculate_expiration(is_priority, size_bytes),
+            uploaded_at,
+            expires_at: retention::default_expiration(is_priority, size_bytes, uploaded_at),
             is_priority,
             status: FileStatus::Active,
+            content_hash: None,
+            previews: Vec::new(),
+            derived_from: None,
+            demo_info: None,
+            log_findings: None,
         };
 
+        Self::add_usage(&mut self.key_usage.entry(mod_key.to_string()).or_default(), &file_meta);
         self.files.insert(file_id, file_meta);
         self.save_to_disk();
 
         Ok(())
     }
 
-    /// Moves a file from TEMP_UPLOADS to its final destination in ANSWERS
+    fn hash_file(path: &Path) -> Result<String, String> {
+        let mut file = fs::File::open(path).map_err(|e| e.to_string())?;
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut file, &mut hasher).map_err(|e| e.to_string())?;
+        Ok(format!("{:x}", hasher.finalize()))
+    }
+
+    pub fn blob_key(content_hash: &str) -> String {
+        format!("BLOBS/{}/{}", &content_hash[..2], content_hash)
+    }
+
+    /// Reads a file's contents, from the storage backend for blobs
+    pub fn open_file(&self, meta: &FileMetadata) -> Result<Box<dyn Read + Send>, String> {
+        match &meta.content_hash {
+            Some(hash) => self.storage.open(&Self::blob_key(hash)),
+            None => Ok(Box::new(fs::File::open(&meta.path).map_err(|e| e.to_string())?)),
+        }
+    }
+
+    /// How to deliver a file to a client, blobs may be redirected to the storage backend
+    pub fn serve_file(&self, meta: &FileMetadata) -> Result<Served, String> {
+        match &meta.content_hash {
+            Some(hash) => self.storage.serve(&Self::blob_key(hash), &meta.original_name),
+            None => Ok(Served::File { path: meta.path.clone(), content_type: storage::content_type(&meta.original_name) }),
+        }
+    }
+
+    /// Moves a file from TEMP_UPLOADS into content-addressed storage (BLOBS/{hash[..2]}/{hash}).
+    /// If identical content is already stored, the upload is dropped and the existing blob is referenced instead.
+    /// An upload that cannot be stored is dropped too, the submission goes on without it and nothing refers to it anymore.
     pub fn commit_temp_file(
         &self,
         temp_file_id: Uuid,
         mod_key: &str,
-        user_xuid: &str,
         original_name: &str,
         is_priority: bool,
     ) -> Result<FileMetadata, String> {
-        let temp_path = self.base_dir.join("TEMP_UPLOADS").join(temp_file_id.to_string());
+        let committed = self.store_temp_file(temp_file_id, mod_key, original_name, is_priority);
+        if committed.is_err() {
+            let _ = fs::remove_file(self.temp_path(&temp_file_id));
+            self.temp_uploads.remove(&temp_file_id);
+            self.upload_sessions.remove(&temp_file_id);
+        }
+        committed
+    }
+
+    fn store_temp_file(
+        &self,
+        temp_file_id: Uuid,
+        mod_key: &str,
+        original_name: &str,
+        is_priority: bool,
+    ) -> Result<FileMetadata, String> {
+        let temp_path = self.temp_path(&temp_file_id);
 
         if !temp_path.exists() {
             return Err("Temp file not found or already processed".to_string());
@@ -139,43 +380,153 @@ impl FileManager {
 
         let metadata = fs::metadata(&temp_path).map_err(|e| e.to_string())?;
         let size_bytes = metadata.len();
+        let content_hash = Self::hash_file(&temp_path)?;
+        // Read while the upload is still on local disk
+        let demo_info = demos::inspect(&temp_path, original_name, self.demo_inspect_max_bytes);
+        if let Some(DemoInfo::Rejected { reason }) = &demo_info {
+            warn!("Could not read the demos in {} ({}): {}", temp_file_id, original_name, reason);
+        }
+        let log_findings = self.log_analyzer.analyze(&temp_path, original_name);
+
+        // Store the content before taking the entry, remote uploads must not hold up other users of the index.
+        // A known blob whose object went missing is restored by this upload.
+        let key = Self::blob_key(&content_hash);
+        let deduplicated = self.blobs.contains_key(&content_hash) && self.storage.exists(&key);
+        if !deduplicated {
+            self.storage.put_file(&key, &temp_path)?;
+        }
 
-        // Create the final directory structure: ANSWERS/{mod_key}/{user_xuid}/files/
-        let final_dir = self.base_dir.join("ANSWERS").join(mod_key).join(user_xuid).join("files");
-        fs::create_dir_all(&final_dir).map_err(|e| e.to_string())?;
-
-        let final_path = final_dir.join(original_name);
-
-        fs::rename(&temp_path, &final_path).map_err(|e| e.to_string())?;
+        match self.blobs.entry(content_hash.clone()) {
+            Entry::Occupied(mut blob) => blob.get_mut().ref_count += 1,
+            Entry::Vacant(entry) => {
+                // The last other reference expired and deleted the object since the check above
+                if deduplicated {
+                    self.storage.put_file(&key, &temp_path)?;
+                }
+                entry.insert(BlobRef { key: key.clone(), size_bytes, ref_count: 1 });
+            }
+        }
+        if deduplicated {
+            let _ = fs::remove_file(&temp_path);
+            info!("Deduplicated upload {} ({} bytes) against blob {}", temp_file_id, size_bytes, content_hash);
+        }
+        let final_path = self.storage.location(&key);
 
+        let uploaded_at = Self::current_timestamp();
+        let expires_at = self.retention.read().unwrap()
+            .expiration(mod_key, original_name, size_bytes, is_priority, uploaded_at);
         let file_meta = FileMetadata {
             id: temp_file_id,
             mod_key: mod_key.to_string(),
             original_name: original_name.to_string(),
             size_bytes,
             path: final_path,
-            uploaded_at: Self::current_timestamp(),
-            expires_at: Self::calculate_expiration(is_priority, size_bytes),
+            uploaded_at,
+            expires_at,
             is_priority,
             status: FileStatus::Active,
+            content_hash: Some(content_hash),
+            previews: Vec::new(),
+            derived_from: None,
+            demo_info,
+            log_findings,
         };
 
+        Self::add_usage(&mut self.key_usage.entry(mod_key.to_string()).or_default(), &file_meta);
         self.files.insert(temp_file_id, file_meta.clone());
+        self.temp_uploads.remove(&temp_file_id);
+        self.upload_sessions.remove(&temp_file_id);
         self.save_to_disk();
+        self.save_pending_uploads();
 
         Ok(file_meta)
     }
 
+    /// Transfers ownership of all files and pending uploads of `old_key` to `new_key`
+    pub fn reassign_key(&self, old_key: &str, new_key: &str) {
+        for mut entry in self.files.iter_mut() {
+            if entry.mod_key == old_key {
+                entry.mod_key = new_key.to_string();
+            }
+        }
+        for mut session in self.upload_sessions.iter_mut() {
+            if session.mod_key == old_key {
+                session.mod_key = new_key.to_string();
+            }
+        }
+        for mut upload in self.temp_uploads.iter_mut() {
+            if upload.mod_key == old_key {
+                upload.mod_key = new_key.to_string();
+            }
+        }
+        self.rebuild_key_usage();
+        self.save_to_disk();
+        self.save_pending_uploads();
+        self.exports.reassign_key(old_key, new_key);
+    }
+
+    /// Earliest expiration among the key's active files, i.e. when its stored bytes go down next
+    pub fn next_expiry_for_key(&self, mod_key: &str) -> Option<u64> {
+        self.files.iter()
+            .filter(|entry| entry.mod_key == mod_key && matches!(entry.status, FileStatus::Active))
+            .filter_map(|entry| entry.expires_at)
+            .min()
+    }
+
+    /// Returns the bytes actually occupied by active files, counting shared blobs once,
+    /// both in total and per mod_key (a blob referenced several times by one key counts once for it)
+    pub fn storage_usage(&self) -> (u64, HashMap<String, u64>) {
+        let mut total_bytes: u64 = 0;
+        let mut usage_per_key: HashMap<String, u64> = HashMap::new();
+        let mut counted_blobs = HashSet::new();
+        let mut counted_key_blobs = HashSet::new();
+
+        for entry in self.files.iter() {
+            let meta = entry.value();
+            if !matches!(meta.status, FileStatus::Active) {
+                continue;
+            }
+
+            match &meta.content_hash {
+                Some(hash) => {
+                    if counted_blobs.insert(hash.clone()) {
+                        total_bytes += meta.size_bytes;
+                    }
+                    if counted_key_blobs.insert((meta.mod_key.clone(), hash.clone())) {
+                        *usage_per_key.entry(meta.mod_key.clone()).or_insert(0) += meta.size_bytes;
+                    }
+                }
+                None => {
+                    total_bytes += meta.size_bytes;
+                    *usage_per_key.entry(meta.mod_key.clone()).or_insert(0) += meta.size_bytes;
+                }
+            }
+        }
+
+        (total_bytes, usage_per_key)
+    }
+
     /// Core background task loop for cleanup and maintenance
-    pub async fn run_background_tasks(self: Arc<Self>) {
+    pub async fn run_background_tasks(self: Arc<Self>, metrics: Arc<Metrics>) {
         let mut interval = tokio::time::interval(Duration::from_secs(3 * 60 * 60)); // Run every 3 hours
+        let timed = |task: &'static str, run: &dyn Fn()| {
+            let started = Instant::now();
+            run();
+            metrics.record_background_task(task, started.elapsed());
+        };
+
         loop {
             interval.tick().await;
             info!("Running FMS background tasks...");
-            self.cleanup_orphans();
-            self.expire_old_files();
-            self.enforce_storage_limit();
-            self.save_to_disk();
+            let pass_started = Instant::now();
+            timed("cleanup_orphans", &|| self.cleanup_orphans());
+            timed("cleanup_upload_sessions", &|| self.cleanup_upload_sessions());
+            timed("apply_retention_policy", &|| self.apply_retention_policy());
+            timed("expire_old_files", &|| self.expire_old_files());
+            timed("enforce_storage_limit", &|| self.enforce_storage_limit());
+            timed("cleanup_exports", &|| self.exports.cleanup_expired(self.storage.as_ref()));
+            timed("save_to_disk", &|| self.save_to_disk());
+            metrics.record_background_task("pass", pass_started.elapsed());
         }
     }
 
@@ -199,6 +550,48 @@ impl FileManager {
         }
     }
 
+    /// Forgets resumable uploads and pending temp uploads whose temp file was never created or has been cleaned up
+    fn cleanup_upload_sessions(&self) {
+        let now = Self::current_timestamp();
+        let before = self.upload_sessions.len() + self.temp_uploads.len();
+        self.upload_sessions.retain(|id, session| {
+            now.saturating_sub(session.created_at) <= TEMP_LIFETIME_SECS || self.temp_path(id).exists()
+        });
+        self.temp_uploads.retain(|id, _| self.temp_path(id).exists());
+        if self.upload_sessions.len() + self.temp_uploads.len() != before {
+            self.save_pending_uploads();
+        }
+    }
+
+    /// Re-reads the retention policy and recomputes the expiration of every active attachment from its upload time,
+    /// so edited rules also apply to files already stored. Submission JSONs keep their own lifetime.
+    fn apply_retention_policy(&self) {
+        match RetentionPolicy::load(&self.retention_path) {
+            Ok(policy) => {
+                policy.check_reservations(self.max_storage_bytes);
+                *self.retention.write().unwrap() = policy;
+            }
+            Err(e) => error!("Failed to reload the retention policy, keeping the previous one: {}", e),
+        }
+
+        let policy = self.retention.read().unwrap();
+        let answers_dir = self.base_dir.join("ANSWERS");
+        let mut changed = 0;
+        for mut entry in self.files.iter_mut() {
+            if !matches!(entry.status, FileStatus::Active) || entry.path.starts_with(&answers_dir) || entry.derived_from.is_some() {
+                continue;
+            }
+            let expires_at = policy.expiration(&entry.mod_key, &entry.original_name, entry.size_bytes, entry.is_priority, entry.uploaded_at);
+            if entry.expires_at != expires_at {
+                entry.expires_at = expires_at;
+                changed += 1;
+            }
+        }
+        if changed > 0 {
+            info!("Retention policy changed the expiration of {} files", changed);
+        }
+    }
+
     /// Scans for files that have passed their expiration date and marks them as expired
     fn expire_old_files(&self) {
         let now = Self::current_timestamp();
@@ -220,27 +613,67 @@ impl FileManager {
         }
     }
 
-    /// Physically deletes an expired file and updates its metadata status
-    fn mark_as_expired(&self, id: &Uuid) {
-        if let Some(mut meta) = self.files.get_mut(id) {
-            let _ = fs::remove_file(&meta.path);
+    /// Marks a file and its previews as expired and physically deletes them,
+    /// unless their blobs are still referenced by other files. Returns the number of bytes freed on disk.
+    fn mark_as_expired(&self, id: &Uuid) -> u64 {
+        // Release the index entry before deleting anything, storage may be remote
+        let meta = {
+            let Some(mut meta) = self.files.get_mut(id) else { return 0 };
+            if !matches!(meta.status, FileStatus::Active) {
+                return 0;
+            }
+            if let Some(mut usage) = self.key_usage.get_mut(&meta.mod_key) {
+                Self::remove_usage(&mut usage, &meta);
+            }
+            let expired = meta.clone();
             meta.status = FileStatus::Expired { deleted_at: Self::current_timestamp() };
+            expired
+        };
+
+        let mut freed = 0;
+        match &meta.content_hash {
+            Some(hash) => {
+                // The blob entry stays locked until the object is gone, so a concurrent upload of the same content stores it again
+                if let Entry::Occupied(mut blob) = self.blobs.entry(hash.clone()) {
+                    blob.get_mut().ref_count = blob.get().ref_count.saturating_sub(1);
+                    if blob.get().ref_count == 0 {
+                        if let Err(e) = self.storage.delete(&blob.get().key) {
+                            error!("Failed to delete blob {}: {}", blob.get().key, e);
+                        }
+                        freed = blob.get().size_bytes;
+                        blob.remove();
+                    }
+                }
+            }
+            None => {
+                let _ = fs::remove_file(&meta.path);
+                freed = meta.size_bytes;
+            }
+        }
+
+        for preview in meta.previews {
+            freed += self.mark_as_expired(&preview.file_id);
         }
+        freed
     }
 
-    /// Smart Deletion Algorithm: Targets the heaviest mod_key users first when storage limits are exceeded
+    /// Smart Deletion Algorithm: Targets the heaviest mod_key users first when storage limits are exceeded.
+    /// Usage is measured in deduplicated bytes, so dropping a reference to a shared blob frees nothing until the last one goes.
+    /// Space reserved for a key in the retention policy is not counted towards its usage, and never reclaimed.
     fn enforce_storage_limit(&self) {
-        let mut total_active_bytes: u64 = 0;
-        let mut usage_per_key: HashMap<String, u64> = HashMap::new();
-
-        // 1. Calculate current total and per-key usage
-        for entry in self.files.iter() {
-            let meta = entry.value();
-            if let FileStatus::Active = meta.status {
-                total_active_bytes += meta.size_bytes;
-                *usage_per_key.entry(meta.mod_key.clone()).or_insert(0) += meta.size_bytes;
-            }
-        }
+        // 1. Calculate current total and per-key usage above the reservations
+        let (total_active_bytes, usage_per_key) = self.storage_usage();
+        let policy = self.retention.read().unwrap().clone();
+        let unreserved = |usage: HashMap<String, u64>| -> HashMap<String, u64> {
+            usage.into_iter()
+                .map(|(key, usage)| {
+                    let excess = usage.saturating_sub(policy.reserved_bytes(&key));
+                    (key, excess)
+                })
+                .filter(|(_, excess)| *excess > 0)
+                .collect()
+        };
+        let mut usage_per_key = unreserved(usage_per_key);
 
         if total_active_bytes <= self.max_storage_bytes {
             return; // Under limit, no action needed
@@ -248,6 +681,7 @@ impl FileManager {
 
         warn!("Storage limit exceeded ({} / {} bytes). Running Smart Deletion...", total_active_bytes, self.max_storage_bytes);
         let target_bytes = (self.max_storage_bytes as f64 * 0.9) as u64; // Target 90% capacity
+        let mut total_active_bytes = total_active_bytes;
 
         // 2. Delete files until we reach target capacity
         while total_active_bytes > target_bytes {
@@ -258,7 +692,11 @@ impl FileManager {
 
             let target_mod_key = match biggest_offender {
                 Some(key) => key,
-                None => break, // No more files to process
+                None => {
+                    // Every key is within its reserved space
+                    warn!("Smart Deletion: nothing left to remove outside of reserved space ({} / {} bytes)", total_active_bytes, self.max_storage_bytes);
+                    break;
+                }
             };
 
             // Find the oldest, non-priority active file for that user
@@ -268,33 +706,134 @@ impl FileManager {
             for entry in self.files.iter() {
                 let meta = entry.value();
                 if let FileStatus::Active = meta.status {
-                    if meta.mod_key == target_mod_key && !meta.is_priority {
-                        if meta.uploaded_at < oldest_time {
-                            oldest_time = meta.uploaded_at;
-                            oldest_file_id = Some(*entry.key());
-                        }
+                    if meta.mod_key == target_mod_key && !meta.is_priority && meta.uploaded_at < oldest_time {
+                        oldest_time = meta.uploaded_at;
+                        oldest_file_id = Some(*entry.key());
                     }
                 }
             }
 
             if let Some(id) = oldest_file_id {
                 // Remove the oldest file found
-                let size_freed = self.files.get(&id).unwrap().size_bytes;
-                self.mark_as_expired(&id);
-
-                total_active_bytes -= size_freed;
-                if let Some(usage) = usage_per_key.get_mut(&target_mod_key) {
-                    *usage -= size_freed;
-                }
+                let size_freed = self.mark_as_expired(&id);
                 info!("Smart Deletion: Removed file {} from {} (freed {} bytes)", id, target_mod_key, size_freed);
+
+                // Shared blobs make the freed amount hard to attribute, so re-measure
+                let (new_total, new_usage) = self.storage_usage();
+                let new_usage = unreserved(new_usage);
+                total_active_bytes = new_total;
+                usage_per_key.retain(|key, usage| {
+                    *usage = new_usage.get(key).copied().unwrap_or(0);
+                    *usage > 0
+                });
             } else {
                 // If the biggest offender only has priority files, stop targeting them
                 usage_per_key.remove(&target_mod_key);
                 if usage_per_key.is_empty() {
-                    error!("CRITICAL: Cannot free enough space! All remaining files are priority.");
+                    error!("CRITICAL: Cannot free enough space! All remaining files are priority or within reserved space.");
                     break;
                 }
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    fn upload(file_manager: &FileManager, content: &[u8]) -> Uuid {
+        let id = Uuid::new_v4();
+        fs::write(file_manager.temp_path(&id), content).unwrap();
+        id
+    }
+
+    fn commit(file_manager: &FileManager, mod_key: &str, content: &[u8]) -> FileMetadata {
+        let id = upload(file_manager, content);
+        file_manager.commit_temp_file(id, mod_key, "clip.bin", false).unwrap()
+    }
+
+    fn stored_blobs(file_manager: &FileManager) -> usize {
+        file_manager.storage.list("BLOBS").unwrap().len()
+    }
+
+    #[test]
+    fn same_content_committed_twice_is_stored_once() {
+        let file_manager = FileManager::new(100, test_support::temp_dir());
+
+        let first = commit(&file_manager, "key", b"same content");
+        let second_id = upload(&file_manager, b"same content");
+        let second = file_manager.commit_temp_file(second_id, "key", "clip.bin", false).unwrap();
+
+        assert_ne!(first.id, second.id);
+        assert_eq!(first.content_hash, second.content_hash);
+        assert_eq!(first.path, second.path);
+        assert!(!file_manager.temp_path(&second_id).exists());
+        assert_eq!(stored_blobs(&file_manager), 1);
+        assert_eq!(file_manager.blobs.get(first.content_hash.as_ref().unwrap()).unwrap().ref_count, 2);
+
+        // Shared bytes count once, for the server and for the key
+        assert_eq!(file_manager.storage_usage().0, 12);
+        assert_eq!(file_manager.key_usage("key"), 12);
+    }
+
+    #[test]
+    fn shared_blob_is_deleted_with_its_last_reference() {
+        let file_manager = FileManager::new(100, test_support::temp_dir());
+        let first = commit(&file_manager, "a", b"shared");
+        let second = commit(&file_manager, "b", b"shared");
+        let hash = first.content_hash.clone().unwrap();
+        let key = FileManager::blob_key(&hash);
+
+        assert_eq!(file_manager.mark_as_expired(&first.id), 0);
+        assert!(file_manager.storage.exists(&key));
+        assert_eq!(file_manager.blobs.get(&hash).unwrap().ref_count, 1);
+        assert_eq!(file_manager.key_usage("a"), 0);
+        assert_eq!(file_manager.key_usage("b"), 6);
+
+        // Expiring twice must not drop the reference of the other file
+        assert_eq!(file_manager.mark_as_expired(&first.id), 0);
+        assert_eq!(file_manager.blobs.get(&hash).unwrap().ref_count, 1);
+
+        assert_eq!(file_manager.mark_as_expired(&second.id), 6);
+        assert!(!file_manager.storage.exists(&key));
+        assert!(file_manager.blobs.is_empty());
+        assert_eq!(file_manager.key_usage("b"), 0);
+    }
+
+    #[test]
+    fn reference_counts_are_rebuilt_from_the_index() {
+        let dir = test_support::temp_dir();
+        let file_manager = FileManager::new(100, &dir);
+        let first = commit(&file_manager, "a", b"shared");
+        commit(&file_manager, "a", b"shared");
+        commit(&file_manager, "b", b"shared");
+        commit(&file_manager, "b", b"other");
+        file_manager.mark_as_expired(&first.id);
+        file_manager.save_to_disk();
+
+        let reloaded = FileManager::new(100, &dir);
+        let hash = first.content_hash.unwrap();
+        assert_eq!(reloaded.blobs.get(&hash).unwrap().ref_count, 2);
+        assert_eq!(reloaded.blobs.len(), 2);
+        assert_eq!(reloaded.key_usage("a"), 6);
+        assert_eq!(reloaded.key_usage("b"), 11);
+    }
+
+    #[test]
+    fn key_usage_matches_a_full_scan() {
+        let file_manager = FileManager::new(100, test_support::temp_dir());
+        let expired = commit(&file_manager, "a", b"one");
+        commit(&file_manager, "a", b"two");
+        commit(&file_manager, "b", b"two");
+        commit(&file_manager, "c", b"three");
+        file_manager.mark_as_expired(&expired.id);
+        file_manager.reassign_key("c", "b");
+
+        let (_, usage_per_key) = file_manager.storage_usage();
+        for key in ["a", "b", "c"] {
+            assert_eq!(file_manager.key_usage(key), usage_per_key.get(key).copied().unwrap_or(0), "usage of {}", key);
+        }
+    }
+}
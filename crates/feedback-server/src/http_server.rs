@@ -1,17 +1,29 @@
-use crate::models::{FormSubmission, SubmissionEvent};
+use crate::export_jobs;
+use crate::models::{FormSubmission, ModeratorKeyData, SubmissionEvent};
 use crate::state::ServerState;
-use crate::file_manager::{FileMetadata, FileStatus};
+use crate::file_manager::{FileMetadata, FileStatus, TempUpload, UploadSession};
+use crate::idempotency::Begin;
+use crate::links::{LinkError, LinkQuery};
+use crate::storage::Served;
+use crate::submission_store::StoredSubmission;
+use crate::surveys;
+use crate::validation::validate_submission;
 use axum::extract::DefaultBodyLimit;
+use dashmap::DashMap;
 use axum::{
+    body::Body,
     debug_handler,
-    extract::{Json, Path, State},
+    extract::{Json, Path, Query, State},
     http::{Request, StatusCode, HeaderMap},
-    response::{IntoResponse, Response},
+    middleware,
+    response::{IntoResponse, Redirect, Response},
     routing::{get, post},
     Router,
 };
 use tower_http::services::ServeFile;
 use tower::ServiceExt;
+use futures::StreamExt;
+use tokio::io::AsyncWriteExt;
 use uuid::Uuid;
 use std::fs;
 use std::path::{Path as StdPath, PathBuf};
@@ -19,33 +31,152 @@ use std::time::{SystemTime, UNIX_EPOCH};
 use tracing::{error, info, warn};
 
 pub fn create_router(state: ServerState) -> Router {
-    Router::new()
+    // Routes that store data on behalf of a key are subject to its rate limits and quotas
+    let limited = Router::new()
         .route("/submit", post(handle_submission))
         .route("/upload", post(upload_file))
+        .route("/upload/sessions", post(create_upload_session))
+        .route("/surveys", post(register_survey))
+        .route_layer(middleware::from_fn_with_state(state.clone(), crate::rate_limit::enforce));
+
+    Router::new()
+        .merge(limited)
+        .route("/upload/sessions/:id", get(get_upload_offset).put(upload_chunk))
+        .route("/upload/sessions/:id/finalize", post(finalize_upload))
         .route("/data/:id", get(serve_data))
         .route("/exports/:filename", get(serve_export))
         .route("/healthy", get(health_check))
-        // Set maximum body limit to 120MB for file uploads
+        .route("/metrics", get(crate::metrics::serve_metrics))
+        .merge(crate::api::router())
+        .merge(crate::dashboard::router())
+        // Set maximum body limit to 120MB for single-request file uploads, resumable uploads are streamed
         .layer(DefaultBodyLimit::max(120 * 1024 * 1024))
+        .layer(middleware::from_fn_with_state(state.clone(), crate::metrics::track_http))
         .with_state(state)
 }
 
+const KEY_USAGE_SAVE_SECS: u64 = 5 * 60; // last_used_at is persisted with this granularity
+
+/// Resolves the `X-Moderator-Key` header to a registered key and its data.
+/// Revoked keys get 410, and a rotated key still in its grace period resolves to its replacement.
+pub(crate) fn authorize(state: &ServerState, headers: &HeaderMap) -> Result<(String, ModeratorKeyData), StatusCode> {
+    let key = headers.get("X-Moderator-Key")
+        .and_then(|h| h.to_str().ok())
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+    resolve_key(state, key)
+}
+
+/// Same as `authorize`, for a key that did not come from the `X-Moderator-Key` header
+pub(crate) fn resolve_key(state: &ServerState, key: &str) -> Result<(String, ModeratorKeyData), StatusCode> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let (data, usage_changed) = match state.key_store.get_mut(key) {
+        Some(mut data) => {
+            if data.is_revoked(now) {
+                return Err(StatusCode::GONE);
+            }
+            let stale = data.last_used_at.is_none_or(|t| now.saturating_sub(t) >= KEY_USAGE_SAVE_SECS);
+            if stale {
+                data.last_used_at = Some(now);
+            }
+            (data.clone(), stale)
+        }
+        None => return Err(StatusCode::FORBIDDEN),
+    };
+
+    if usage_changed && let Err(e) = state.save_keys_to_disk() {
+        warn!("Failed to save key usage: {}", e);
+    }
+
+    // Data of rotated keys was moved to the replacement, keep storing everything there
+    let replacement = data.replaced_by.as_ref()
+        .and_then(|new_key| state.key_store.get(new_key).map(|d| (new_key.clone(), d.clone())))
+        .filter(|(_, new_data)| !new_data.is_revoked(now));
+    if let Some(replacement) = replacement {
+        return Ok(replacement);
+    }
+
+    Ok((key.to_string(), data))
+}
+
+/// Runs `process` at most once per `X-Idempotency-Key` and moderator key.
+/// Retries get the original JSON result back, requests without the header are always processed.
+/// Reusing a key for a different `request` is answered with 422.
+async fn with_idempotency<F, Fut>(
+    state: &ServerState,
+    key: &str,
+    headers: &HeaderMap,
+    request: &[u8],
+    process: F,
+) -> Result<Json<serde_json::Value>, StatusCode>
+where
+    F: FnOnce() -> Fut,
+    Fut: std::future::Future<Output = Result<serde_json::Value, StatusCode>>,
+{
+    let idempotency_key = headers.get("X-Idempotency-Key").and_then(|h| h.to_str().ok());
+    let Some(idempotency_key) = idempotency_key else {
+        return process().await.map(Json);
+    };
+
+    match state.idempotency.begin(key, idempotency_key, request) {
+        Begin::Completed(result) => {
+            info!("Replaying result for idempotency key {}", idempotency_key);
+            return Ok(Json(result));
+        }
+        Begin::InProgress => return Err(StatusCode::CONFLICT),
+        Begin::Mismatch => {
+            warn!("Idempotency key {} was reused for a different request", idempotency_key);
+            return Err(StatusCode::UNPROCESSABLE_ENTITY);
+        }
+        Begin::New => {}
+    }
+
+    match process().await {
+        Ok(result) => {
+            state.idempotency.complete(key, idempotency_key, result.clone());
+            Ok(Json(result))
+        }
+        Err(status) => {
+            state.idempotency.abandon(key, idempotency_key);
+            Err(status)
+        }
+    }
+}
+
 async fn handle_submission(
     State(state): State<ServerState>,
     headers: HeaderMap,
     Json(payload): Json<FormSubmission>,
-) -> StatusCode {
+) -> Response {
     // Validate the moderator key from headers
-    let key = match headers.get("X-Moderator-Key").and_then(|h| h.to_str().ok()) {
-        Some(k) => k,
-        None => return StatusCode::UNAUTHORIZED,
+    let (key, destination) = match authorize(&state, &headers) {
+        Ok(found) => found,
+        Err(status) => return status.into_response(),
     };
 
-    let destination = match state.key_store.get(key) {
-        Some(data) => data.clone(),
-        None => return StatusCode::FORBIDDEN,
-    };
+    if let Err(errors) = validate_submission(&payload, &key, &state.submission_limits, &state.file_manager) {
+        warn!("Rejected submission for survey '{}' with {} validation errors", payload.survey_id, errors.len());
+        return (StatusCode::UNPROCESSABLE_ENTITY, Json(serde_json::json!({ "errors": errors }))).into_response();
+    }
+
+    let request = serde_json::to_vec(&payload).unwrap_or_default();
+    let (task_state, task_key) = (state.clone(), key.clone());
+    with_idempotency(&state, &key, &headers, &request, || async move {
+        // Hashing and storing the attachments blocks, keep it off the async runtime
+        let processed = tokio::task::spawn_blocking(move || process_submission(&task_state, &task_key, destination, payload)).await;
+        let submission_id = processed.unwrap_or_else(|e| {
+            error!("Processing a submission panicked: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        })?;
+        Ok(serde_json::json!({ "submission_id": submission_id.to_string() }))
+    }).await.into_response()
+}
 
+fn process_submission(
+    state: &ServerState,
+    key: &str,
+    destination: ModeratorKeyData,
+    payload: FormSubmission,
+) -> Result<Uuid, StatusCode> {
     let is_priority = destination.is_priority;
 
     // Sanitize user_xuid to prevent path traversal attacks
@@ -65,59 +196,173 @@ async fn handle_submission(
     let answer_dir = state.file_manager.base_dir.join("ANSWERS").join(key).join(safe_user_xuid);
     if let Err(e) = fs::create_dir_all(&answer_dir) {
         error!("Failed to create directory {:?}: {}", answer_dir, e);
-        return StatusCode::INTERNAL_SERVER_ERROR;
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
     }
 
     let json_bytes = match serde_json::to_string_pretty(&payload) {
         Ok(s) => s.into_bytes(),
         Err(e) => {
             error!("Failed to serialize payload to JSON: {}", e);
-            return StatusCode::INTERNAL_SERVER_ERROR
+            return Err(StatusCode::INTERNAL_SERVER_ERROR)
         },
     };
 
     let file_path = answer_dir.join(&filename);
-    if let Err(e) = fs::write(&file_path, &json_bytes) {
+    if let Err(e) = crate::persistence::write_atomic(&file_path, &json_bytes) {
         error!("Failed to write to file {:?}: {}", file_path, e);
-        return StatusCode::INTERNAL_SERVER_ERROR;
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
     }
 
-    // Register the submission JSON in the file manager
+    // Index the submission before committing its files, a failed insert must not consume the uploads
+    // of a submission the client is going to retry
     let submission_id = Uuid::new_v4();
-    state.file_manager.commit_file(submission_id, key, &filename, true, file_path, json_bytes.len() as u64);
+    let pending_files: Vec<Uuid> = payload.files.iter()
+        .filter_map(|(file_id_str, _)| Uuid::parse_str(file_id_str).ok())
+        .collect();
+    let mut record = StoredSubmission {
+        id: submission_id,
+        mod_key: key.to_string(),
+        received_at: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
+        submission: payload.clone(),
+        file_ids: pending_files.clone(),
+        survey_version: None, // resolved by the store from survey_hash
+        possible_duplicates: Vec::new(),
+        bug: None,
+    };
+    if let Err(e) = state.submissions.insert(&record) {
+        error!("Failed to index submission {}: {}", submission_id, e);
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    // Register the submission JSON in the file manager
+    let _ = state.file_manager.commit_file(submission_id, key, &filename, true, file_path, json_bytes.len() as u64);
 
     // Process and commit attached files from temporary storage
     let mut attached_files = Vec::new();
-    for (file_id_str, _) in &payload.files {
-        if let Ok(file_uuid) = Uuid::parse_str(file_id_str) {
-            let original_name = state.file_manager.temp_file_names
-                .get(&file_uuid)
-                .map(|r| r.value().clone())
-                .unwrap_or_else(|| "unknown.bin".to_string());
-
-            // Move the file from TEMP_UPLOADS to the final destination
-            match state.file_manager.commit_temp_file(file_uuid, key, safe_user_xuid, &original_name, is_priority) {
-                Ok(meta) => attached_files.push(meta),
-                Err(e) => warn!("Failed to commit file {}: {}", file_uuid, e),
-            }
+    for file_uuid in pending_files {
+        let original_name = state.file_manager.temp_uploads
+            .get(&file_uuid)
+            .map(|r| r.original_name.clone())
+            .unwrap_or_else(|| "unknown.bin".to_string());
+
+        // Move the file from TEMP_UPLOADS to the final destination
+        match state.file_manager.commit_temp_file(file_uuid, key, &original_name, is_priority) {
+            Ok(meta) => attached_files.push(meta),
+            Err(e) => warn!("Failed to commit file {}: {}", file_uuid, e),
         }
     }
 
     state.file_manager.save_to_disk();
 
+    // Files that could not be committed are not part of the submission
+    let committed: Vec<Uuid> = attached_files.iter().map(|meta| meta.id).collect();
+    if committed.len() != record.file_ids.len() {
+        let missing: Vec<Uuid> = record.file_ids.iter().filter(|id| !committed.contains(id)).copied().collect();
+        if let Err(e) = state.submissions.detach_files(&submission_id, &missing) {
+            warn!("Failed to detach uncommitted files from submission {}: {}", submission_id, e);
+        }
+    }
+    record.file_ids = committed;
+
+    // Link bug reports to likely duplicates, the submission is kept even when this fails
+    let possible_duplicates = match state.duplicates.find(&state.submissions, &record) {
+        Ok(duplicates) => duplicates,
+        Err(e) => {
+            warn!("Failed to look for duplicates of submission {}: {}", submission_id, e);
+            Vec::new()
+        }
+    };
+    if !possible_duplicates.is_empty() {
+        info!("Submission {} is a possible duplicate of {} earlier report(s)", submission_id, possible_duplicates.len());
+        if let Err(e) = state.submissions.record_duplicates(&submission_id, &possible_duplicates) {
+            warn!("Failed to record duplicates of submission {}: {}", submission_id, e);
+        }
+    }
+
     // Trigger internal submission event
     let event = SubmissionEvent {
         submission_id,
+        mod_key: key.to_string(),
         destination,
         submission: payload,
         submission_bytes: json_bytes,
         filename,
         attached_files,
+        possible_duplicates,
     };
 
-    let _ = state.submission_sender.send(event);
+    state.metrics.record_submission(key, event.submission_bytes.len() as u64);
+
+    // Notifications wait for the previews of attached recordings, the client does not
+    if state.previews.wants_any(&event.attached_files) {
+        let state = state.clone();
+        tokio::spawn(async move {
+            let mut with_previews = event.clone();
+            let file_manager = state.file_manager.clone();
+            let previews = state.previews.clone();
+            let generated = tokio::task::spawn_blocking(move || {
+                previews.generate_all(&file_manager, &mut with_previews.attached_files);
+                with_previews
+            }).await;
+            let event = generated.unwrap_or_else(|e| {
+                error!("Preview generation for submission {} panicked: {}", submission_id, e);
+                event
+            });
+            let _ = state.submission_sender.send(event);
+        });
+    } else {
+        let _ = state.submission_sender.send(event);
+    }
 
-    StatusCode::OK
+    Ok(submission_id)
+}
+
+/// Registers the survey config a client is about to answer. The body is the `FormConfig` JSON,
+/// identified by `X-Survey-Id` and hashed as sent; `X-Survey-Hash` is checked against it when present.
+async fn register_survey(
+    State(state): State<ServerState>,
+    headers: HeaderMap,
+    body: axum::body::Bytes,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let (key, _) = authorize(&state, &headers)?;
+
+    let survey_id = headers.get("X-Survey-Id")
+        .and_then(|h| h.to_str().ok())
+        .filter(|s| !s.is_empty())
+        .ok_or(StatusCode::BAD_REQUEST)?;
+    if body.len() > surveys::MAX_DEFINITION_BYTES {
+        return Err(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    let content_hash = surveys::content_hash(&body);
+    let claimed_hash = headers.get("X-Survey-Hash").and_then(|h| h.to_str().ok());
+    if claimed_hash.is_some_and(|claimed| !claimed.eq_ignore_ascii_case(&content_hash)) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    // Only definitions the server can read are stored, so stats never hit an unreadable version
+    let definition_json = std::str::from_utf8(&body).map_err(|_| StatusCode::BAD_REQUEST)?;
+    if let Err(e) = serde_json::from_str::<surveys::SurveyDefinition>(definition_json) {
+        warn!("Rejected survey definition for '{}': {}", survey_id, e);
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    let (version, created) = state.submissions
+        .register_definition(&key, survey_id, &content_hash, definition_json)
+        .map_err(|e| {
+            error!("Failed to register survey definition: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    if created {
+        info!("Registered version {} of survey '{}'", version, survey_id);
+    }
+
+    Ok(Json(serde_json::json!({
+        "survey_id": survey_id,
+        "version": version,
+        "content_hash": content_hash,
+        "created": created,
+    })))
 }
 
 async fn upload_file(
@@ -125,43 +370,263 @@ async fn upload_file(
     headers: HeaderMap,
     body: axum::body::Bytes, // Raw binary file data
 ) -> Result<Json<serde_json::Value>, StatusCode> {
-    let key = headers.get("X-Moderator-Key").and_then(|h| h.to_str().ok()).ok_or(StatusCode::UNAUTHORIZED)?;
+    let (key, _) = authorize(&state, &headers)?;
 
-    if !state.key_store.contains_key(key) {
-        return Err(StatusCode::FORBIDDEN);
-    }
+    with_idempotency(&state, &key, &headers, &body, || async {
+        let original_name = file_name_from_headers(&headers);
+
+        let temp_id = Uuid::new_v4();
+        let temp_path = state.file_manager.temp_path(&temp_id);
+
+        let size_bytes = body.len() as u64;
+        if let Err(e) = fs::write(&temp_path, &body) {
+            error!("Failed to write temp file {:?}: {}", temp_path, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+        state.metrics.record_bytes_received(&key, size_bytes);
+        state.metrics.record_upload(&key);
+
+        // Remember who uploaded the file and its name for later use during submission
+        state.file_manager.temp_uploads.insert(temp_id, TempUpload { mod_key: key.clone(), original_name });
+        state.file_manager.save_pending_uploads();
 
-    // Extract and sanitize the original filename from headers to prevent path traversal
+        Ok(serde_json::json!({ "file_id": temp_id.to_string() }))
+    }).await
+}
+
+/// Extracts and sanitizes the original filename from headers to prevent path traversal
+fn file_name_from_headers(headers: &HeaderMap) -> String {
     let raw_name = headers.get("X-File-Name")
         .and_then(|h| h.to_str().ok())
         .unwrap_or("unknown.bin");
 
-    let original_name = StdPath::new(raw_name)
+    StdPath::new(raw_name)
         .file_name()
         .and_then(|s| s.to_str())
         .unwrap_or("unknown.bin")
-        .to_string();
+        .to_string()
+}
 
-    let temp_id = Uuid::new_v4();
-    let temp_path = state.file_manager.base_dir.join("TEMP_UPLOADS").join(temp_id.to_string());
+fn header_u64(headers: &HeaderMap, name: &str) -> Option<u64> {
+    headers.get(name).and_then(|h| h.to_str().ok()).and_then(|s| s.parse().ok())
+}
 
-    // Store temp_id -> original_name mapping for later use during submission
-    state.file_manager.temp_file_names.insert(temp_id, original_name.clone());
+fn max_upload_bytes() -> u64 {
+    std::env::var("MAX_UPLOAD_MB")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .unwrap_or(1024)
+        * 1024 * 1024
+}
 
-    if let Err(e) = fs::write(&temp_path, body) {
-        error!("Failed to write temp file {:?}: {}", temp_path, e);
+/// Starts a resumable upload. The client then PUTs chunks at increasing offsets and finalizes it
+async fn create_upload_session(
+    State(state): State<ServerState>,
+    headers: HeaderMap,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let (key, _) = authorize(&state, &headers)?;
+
+    let total_bytes = header_u64(&headers, "X-Upload-Length").ok_or(StatusCode::BAD_REQUEST)?;
+    if total_bytes > max_upload_bytes() {
+        return Err(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    // A retried create returns the same upload_id, the client then asks for the current offset
+    let request = format!("{}\n{}", file_name_from_headers(&headers), total_bytes);
+    with_idempotency(&state, &key, &headers, request.as_bytes(), || async {
+        let upload_id = Uuid::new_v4();
+        let session = UploadSession {
+            mod_key: key.clone(),
+            original_name: file_name_from_headers(&headers),
+            total_bytes,
+            created_at: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
+            is_receiving: false,
+            is_finalized: false,
+        };
+
+        if let Err(e) = fs::File::create(state.file_manager.temp_path(&upload_id)) {
+            error!("Failed to create temp file for upload {}: {}", upload_id, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+        state.file_manager.upload_sessions.insert(upload_id, session);
+        state.file_manager.save_pending_uploads();
+
+        Ok(serde_json::json!({ "upload_id": upload_id.to_string(), "offset": 0, "length": total_bytes }))
+    }).await
+}
+
+/// Looks up an upload session owned by the requesting key and returns the number of bytes received so far
+fn upload_session_offset(state: &ServerState, headers: &HeaderMap, upload_id: &Uuid) -> Result<(UploadSession, u64), StatusCode> {
+    let (key, _) = authorize(state, headers)?;
+
+    let session = state.file_manager.upload_sessions.get(upload_id)
+        .map(|s| s.clone())
+        .filter(|s| s.mod_key == key)
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let offset = fs::metadata(state.file_manager.temp_path(upload_id))
+        .map(|m| m.len())
+        .map_err(|_| StatusCode::NOT_FOUND)?;
+
+    Ok((session, offset))
+}
+
+async fn get_upload_offset(
+    State(state): State<ServerState>,
+    headers: HeaderMap,
+    Path(upload_id): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let (session, offset) = upload_session_offset(&state, &headers, &upload_id)?;
+    Ok(Json(serde_json::json!({ "offset": offset, "length": session.total_bytes })))
+}
+
+/// Clears `is_receiving` when a chunk is done, also when axum drops the handler because the client disconnected
+struct ReceivingGuard<'a> {
+    sessions: &'a DashMap<Uuid, UploadSession>,
+    upload_id: Uuid,
+}
+
+impl Drop for ReceivingGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(mut session) = self.sessions.get_mut(&self.upload_id) {
+            session.is_receiving = false;
+        }
+    }
+}
+
+/// Appends a chunk to an upload. `X-Upload-Offset` must match the bytes already received,
+/// otherwise 409 is returned with the current offset so the client can resume from there.
+async fn upload_chunk(
+    State(state): State<ServerState>,
+    headers: HeaderMap,
+    Path(upload_id): Path<Uuid>,
+    body: Body,
+) -> Response {
+    let (session, _) = match upload_session_offset(&state, &headers, &upload_id) {
+        Ok(found) => found,
+        Err(status) => return status.into_response(),
+    };
+
+    let Some(chunk_offset) = header_u64(&headers, "X-Upload-Offset") else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+
+    // Chunks count towards the key's daily upload volume as they arrive
+    let limits = state.key_store.get(&session.mod_key).map(|data| data.limits.clone()).unwrap_or_default();
+    if let Err(retry_after) = state.rate_limiter.upload_bytes_allowed(&session.mod_key, &limits, 1) {
+        warn!("Key is over its daily upload volume, chunk of upload {} refused for {}s", upload_id, retry_after);
+        return crate::rate_limit::too_many_requests(retry_after);
+    }
+
+    // Only one chunk at a time may be written to a session, and none after it was finalized
+    match state.file_manager.upload_sessions.get_mut(&upload_id) {
+        Some(mut s) if !s.is_receiving && !s.is_finalized => s.is_receiving = true,
+        _ => return StatusCode::CONFLICT.into_response(),
+    }
+    let receiving = ReceivingGuard { sessions: &state.file_manager.upload_sessions, upload_id };
+
+    let temp_path = state.file_manager.temp_path(&upload_id);
+    let offset = fs::metadata(&temp_path).map(|m| m.len()).unwrap_or(0);
+    let result = if chunk_offset != offset {
+        Err(StatusCode::CONFLICT)
+    } else {
+        append_chunk(&temp_path, body, session.total_bytes - offset).await
+    };
+
+    drop(receiving);
+
+    let new_offset = fs::metadata(&temp_path).map(|m| m.len()).unwrap_or(offset);
+    state.metrics.record_bytes_received(&session.mod_key, new_offset.saturating_sub(offset));
+    state.rate_limiter.record_upload_bytes(&session.mod_key, new_offset.saturating_sub(offset));
+    let offset = new_offset;
+    let current = Json(serde_json::json!({ "offset": offset, "length": session.total_bytes }));
+    match result {
+        Ok(_) => current.into_response(),
+        Err(status) => (status, current).into_response(),
+    }
+}
+
+/// Streams a request body to the end of `path`, refusing to write more than `remaining` bytes.
+/// Whatever arrived before a dropped connection stays on disk and counts towards the offset.
+async fn append_chunk(path: &StdPath, body: Body, remaining: u64) -> Result<u64, StatusCode> {
+    let mut file = tokio::fs::OpenOptions::new()
+        .append(true)
+        .open(path)
+        .await
+        .map_err(|_| StatusCode::NOT_FOUND)?;
+
+    let mut written: u64 = 0;
+    let mut stream = body.into_data_stream();
+    let mut outcome = Ok(());
+
+    while let Some(frame) = stream.next().await {
+        let data = match frame {
+            Ok(data) => data,
+            Err(e) => {
+                warn!("Upload chunk for {:?} was interrupted: {}", path, e);
+                outcome = Err(StatusCode::BAD_REQUEST);
+                break;
+            }
+        };
+
+        if written + data.len() as u64 > remaining {
+            outcome = Err(StatusCode::PAYLOAD_TOO_LARGE);
+            break;
+        }
+        if let Err(e) = file.write_all(&data).await {
+            error!("Failed to write upload chunk to {:?}: {}", path, e);
+            outcome = Err(StatusCode::INTERNAL_SERVER_ERROR);
+            break;
+        }
+        written += data.len() as u64;
+    }
+
+    if file.flush().await.is_err() {
         return Err(StatusCode::INTERNAL_SERVER_ERROR);
     }
+    outcome.map(|_| written)
+}
+
+/// Completes an upload and turns it into a regular temp file that can be attached to a submission
+async fn finalize_upload(
+    State(state): State<ServerState>,
+    headers: HeaderMap,
+    Path(upload_id): Path<Uuid>,
+) -> Response {
+    let (session, offset) = match upload_session_offset(&state, &headers, &upload_id) {
+        Ok(found) => found,
+        Err(status) => return status.into_response(),
+    };
+
+    if session.is_receiving || offset != session.total_bytes {
+        let current = serde_json::json!({ "offset": offset, "length": session.total_bytes });
+        return (StatusCode::CONFLICT, Json(current)).into_response();
+    }
+
+    // The session is kept (finalized) until its temp file is committed, so a retried finalize succeeds too
+    if let Some(mut s) = state.file_manager.upload_sessions.get_mut(&upload_id) {
+        s.is_finalized = true;
+    }
+    if !session.is_finalized {
+        state.metrics.record_upload(&session.mod_key);
+    }
+    state.file_manager.temp_uploads.insert(upload_id, TempUpload { mod_key: session.mod_key, original_name: session.original_name });
+    state.file_manager.save_pending_uploads();
 
-    Ok(Json(serde_json::json!({ "file_id": temp_id.to_string() })))
+    Json(serde_json::json!({ "file_id": upload_id.to_string() })).into_response()
 }
 
 #[debug_handler]
 async fn serve_data(
     State(state): State<ServerState>,
     Path(id): Path<Uuid>,
+    Query(link): Query<LinkQuery>,
     request: Request<axum::body::Body>,
 ) -> Response {
+    if let Err(e) = state.links.verify(&format!("/data/{}", id), &link) {
+        return link_error_response(e);
+    }
+
     let file_meta = match state.file_manager.files.get(&id) {
         Some(meta) => meta.clone(),
         None => return (StatusCode::NOT_FOUND, "File not found.").into_response(),
@@ -172,12 +637,21 @@ async fn serve_data(
             let msg = format!("This file is expired and was automatically deleted (Deleted at timestamp: {}).", deleted_at);
             (StatusCode::GONE, msg).into_response()
         }
-        FileStatus::Active => {
-            let service = ServeFile::new(&file_meta.path);
-            match service.oneshot(request).await {
-                Ok(response) => response.into_response(),
-                Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to serve file: {}", err)).into_response(),
-            }
+        FileStatus::Active => serve_stored(state.file_manager.serve_file(&file_meta), request).await,
+    }
+}
+
+/// Streams a local file, or redirects to a short-lived URL of the storage backend
+pub(crate) async fn serve_stored(served: Result<Served, String>, request: Request<axum::body::Body>) -> Response {
+    match served {
+        Ok(Served::File { path, content_type }) => match ServeFile::new_with_mime(&path, &content_type).oneshot(request).await {
+            Ok(response) => response.into_response(),
+            Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to serve file: {}", err)).into_response(),
+        },
+        Ok(Served::Redirect(url)) => Redirect::temporary(&url).into_response(),
+        Err(e) => {
+            error!("Failed to serve stored file: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to serve file.").into_response()
         }
     }
 }
@@ -186,39 +660,44 @@ async fn serve_data(
 async fn serve_export(
     State(state): State<ServerState>,
     Path(filename): Path<String>,
+    Query(link): Query<LinkQuery>,
     request: Request<axum::body::Body>,
 ) -> Response {
-    let export_dir = state.file_manager.base_dir.join("EXPORTS");
+    if let Err(e) = state.links.verify(&format!("/exports/{}", filename), &link) {
+        return link_error_response(e);
+    }
 
     // Sanitize export filename to prevent path traversal
     let safe_filename = StdPath::new(&filename)
         .file_name()
         .and_then(|s| s.to_str());
 
-    let file_path = match safe_filename {
-        Some(name) => export_dir.join(name),
-        None => return (StatusCode::BAD_REQUEST, "Invalid filename.").into_response(),
+    let Some(name) = safe_filename else {
+        return (StatusCode::BAD_REQUEST, "Invalid filename.").into_response();
     };
 
-    if !file_path.exists() {
+    let storage = &state.file_manager.storage;
+    let key = export_jobs::storage_key(name);
+    if !storage.exists(&key) {
         return (StatusCode::NOT_FOUND, "Archive not found.").into_response();
     }
 
-    let service = ServeFile::new(&file_path);
-    match service.oneshot(request).await {
-        Ok(response) => response.into_response(),
-        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to serve archive: {}", err)).into_response(),
+    serve_stored(storage.serve(&key, name), request).await
+}
+
+fn link_error_response(error: LinkError) -> Response {
+    match error {
+        LinkError::Unsigned => (StatusCode::UNAUTHORIZED, "This link is missing its signature.").into_response(),
+        LinkError::InvalidSignature => (StatusCode::FORBIDDEN, "This link is invalid.").into_response(),
+        LinkError::Expired => (
+            StatusCode::GONE,
+            "This link has expired. Use the \"Refresh links\" button in Discord to get a new one.",
+        ).into_response(),
     }
 }
 
 async fn health_check(State(state): State<ServerState>) -> StatusCode {
-    let mut total_active_bytes: u64 = 0;
-
-    for entry in state.file_manager.files.iter() {
-        if let FileStatus::Active = entry.value().status {
-            total_active_bytes += entry.value().size_bytes;
-        }
-    }
+    let (total_active_bytes, _) = state.file_manager.storage_usage();
 
     if total_active_bytes > state.file_manager.max_storage_bytes {
         StatusCode::INSUFFICIENT_STORAGE
@@ -226,3 +705,58 @@ async fn health_check(State(state): State<ServerState>) -> StatusCode {
         StatusCode::OK
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    async fn send(state: &ServerState, uri: &str, idempotency_key: &str, body: Vec<u8>) -> (StatusCode, serde_json::Value) {
+        let request = Request::post(uri)
+            .header("X-Moderator-Key", "key")
+            .header("X-Idempotency-Key", idempotency_key)
+            .header("X-File-Name", "clip.bin")
+            .header("Content-Type", "application/json")
+            .body(Body::from(body))
+            .unwrap();
+        let response = create_router(state.clone()).oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or_default())
+    }
+
+    #[tokio::test]
+    async fn replayed_upload_returns_the_first_response() {
+        let state = test_support::state(&[("key", test_support::key_data("Server"))]);
+
+        let (status, first) = send(&state, "/upload", "upload-1", b"recording".to_vec()).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, replayed) = send(&state, "/upload", "upload-1", b"recording".to_vec()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(replayed, first);
+        assert_eq!(state.file_manager.temp_uploads.len(), 1);
+
+        let (status, _) = send(&state, "/upload", "upload-1", b"another recording".to_vec()).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(state.file_manager.temp_uploads.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn replayed_submission_is_stored_once() {
+        let state = test_support::state(&[("key", test_support::key_data("Server"))]);
+        let submission = test_support::submission("sp_a1_intro1", &[("What happened?", "Crash")], serde_json::json!({}));
+        let body = serde_json::to_vec(&submission).unwrap();
+
+        let (status, first) = send(&state, "/submit", "submit-1", body.clone()).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, replayed) = send(&state, "/submit", "submit-1", body).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(replayed["submission_id"], first["submission_id"]);
+        assert_eq!(state.submissions.query("key", &Default::default()).unwrap().len(), 1);
+
+        let changed = FormSubmission { map_name: "sp_a1_intro2".to_string(), ..submission };
+        let (status, _) = send(&state, "/submit", "submit-1", serde_json::to_vec(&changed).unwrap()).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(state.submissions.query("key", &Default::default()).unwrap().len(), 1);
+    }
+}
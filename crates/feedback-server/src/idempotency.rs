@@ -0,0 +1,136 @@
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+use sha2::{Digest, Sha256};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const IDEMPOTENCY_TTL_SECS: u64 = 24 * 60 * 60; // 1 day
+
+#[derive(Debug, Clone)]
+enum RequestState {
+    InProgress,
+    Completed(serde_json::Value),
+}
+
+#[derive(Debug, Clone)]
+struct CachedRequest {
+    created_at: u64,
+    fingerprint: String, // hash of the request the key was first used for
+    state: RequestState,
+}
+
+/// What a handler should do with a request carrying an `X-Idempotency-Key`
+#[derive(Debug, Clone)]
+pub enum Begin {
+    /// First time this key is seen, process the request
+    New,
+    /// The same request is being processed right now
+    InProgress,
+    /// Already processed, reply with the original result
+    Completed(serde_json::Value),
+    /// The key was already used for a different request
+    Mismatch,
+}
+
+/// Remembers recent idempotency keys per moderator key, so client retries
+/// do not create duplicate submissions, uploads or notifications.
+#[derive(Default)]
+pub struct IdempotencyCache {
+    entries: DashMap<(String, String), CachedRequest>,
+}
+
+impl IdempotencyCache {
+    fn now() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+    }
+
+    /// Claims `idempotency_key` for `mod_key`, unless it was already used.
+    /// `request` identifies what was sent, a retry must send the same.
+    pub fn begin(&self, mod_key: &str, idempotency_key: &str, request: &[u8]) -> Begin {
+        let now = Self::now();
+        let fingerprint = format!("{:x}", Sha256::digest(request));
+        match self.entries.entry((mod_key.to_string(), idempotency_key.to_string())) {
+            Entry::Occupied(entry) if now.saturating_sub(entry.get().created_at) <= IDEMPOTENCY_TTL_SECS => {
+                if entry.get().fingerprint != fingerprint {
+                    return Begin::Mismatch;
+                }
+                match &entry.get().state {
+                    RequestState::InProgress => Begin::InProgress,
+                    RequestState::Completed(result) => Begin::Completed(result.clone()),
+                }
+            }
+            entry => {
+                entry.insert(CachedRequest { created_at: now, fingerprint, state: RequestState::InProgress });
+                Begin::New
+            }
+        }
+    }
+
+    /// Whether `idempotency_key` was already used for `mod_key`, without claiming it
+    pub fn contains(&self, mod_key: &str, idempotency_key: &str) -> bool {
+        let now = Self::now();
+        self.entries.get(&(mod_key.to_string(), idempotency_key.to_string()))
+            .is_some_and(|entry| now.saturating_sub(entry.created_at) <= IDEMPOTENCY_TTL_SECS)
+    }
+
+    /// Stores the result returned for the key's first request
+    pub fn complete(&self, mod_key: &str, idempotency_key: &str, result: serde_json::Value) {
+        if let Some(mut entry) = self.entries.get_mut(&(mod_key.to_string(), idempotency_key.to_string())) {
+            entry.state = RequestState::Completed(result);
+        }
+    }
+
+    /// Releases the key after a failed request, so a retry is processed again
+    pub fn abandon(&self, mod_key: &str, idempotency_key: &str) {
+        self.entries.remove(&(mod_key.to_string(), idempotency_key.to_string()));
+    }
+
+    pub fn purge_expired(&self) {
+        let now = Self::now();
+        self.entries.retain(|_, entry| now.saturating_sub(entry.created_at) <= IDEMPOTENCY_TTL_SECS);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_gets_the_first_result() {
+        let cache = IdempotencyCache::default();
+        assert!(matches!(cache.begin("key", "a", b"body"), Begin::New));
+        assert!(matches!(cache.begin("key", "a", b"body"), Begin::InProgress));
+
+        cache.complete("key", "a", serde_json::json!({ "file_id": "1" }));
+        match cache.begin("key", "a", b"body") {
+            Begin::Completed(result) => assert_eq!(result, serde_json::json!({ "file_id": "1" })),
+            other => panic!("expected the stored result, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reused_key_with_another_body_is_refused() {
+        let cache = IdempotencyCache::default();
+        cache.begin("key", "a", b"body");
+        assert!(matches!(cache.begin("key", "a", b"other body"), Begin::Mismatch));
+
+        cache.complete("key", "a", serde_json::json!({}));
+        assert!(matches!(cache.begin("key", "a", b"other body"), Begin::Mismatch));
+    }
+
+    #[test]
+    fn keys_are_separate_per_moderator_key() {
+        let cache = IdempotencyCache::default();
+        cache.begin("key", "a", b"body");
+        cache.complete("key", "a", serde_json::json!({}));
+        assert!(matches!(cache.begin("other", "a", b"other body"), Begin::New));
+    }
+
+    #[test]
+    fn abandoned_key_is_processed_again() {
+        let cache = IdempotencyCache::default();
+        cache.begin("key", "a", b"body");
+        cache.abandon("key", "a");
+        assert!(!cache.contains("key", "a"));
+        assert!(matches!(cache.begin("key", "a", b"other body"), Begin::New));
+    }
+}
@@ -0,0 +1,134 @@
+use crate::persistence;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+const SECRET_FILE: &str = "link_signing.key";
+
+/// A download URL that stops working at `expires_at`
+#[derive(Serialize, Debug, Clone)]
+pub struct SignedLink {
+    pub url: String,
+    pub expires_at: u64,
+}
+
+/// `?expires=...&sig=...` of a signed link
+#[derive(Deserialize, Debug)]
+pub struct LinkQuery {
+    expires: Option<u64>,
+    sig: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum LinkError {
+    Unsigned,
+    InvalidSignature,
+    Expired,
+}
+
+/// Signs `/data` and `/exports` URLs with HMAC-SHA256 over the path and an expiry timestamp
+pub struct LinkSigner {
+    secret: Vec<u8>,
+    data_ttl_secs: u64,
+    export_ttl_secs: u64,
+}
+
+impl LinkSigner {
+    /// Uses `LINK_SIGNING_SECRET`, or a random secret generated once and kept in `{base_dir}/link_signing.key`,
+    /// so links stay valid across restarts. Replacing the secret invalidates every link handed out.
+    pub fn load(base_dir: &Path) -> Self {
+        let ttl_hours = |name: &str, default: u64| {
+            std::env::var(name).ok().and_then(|v| v.parse::<u64>().ok()).unwrap_or(default)
+        };
+        let secret = match std::env::var("LINK_SIGNING_SECRET").ok().filter(|s| !s.is_empty()) {
+            Some(secret) => secret.into_bytes(),
+            None => Self::load_or_create_secret(&base_dir.join(SECRET_FILE)),
+        };
+
+        Self {
+            secret,
+            data_ttl_secs: ttl_hours("DATA_LINK_TTL_HOURS", 7 * 24) * 60 * 60,
+            export_ttl_secs: ttl_hours("EXPORT_LINK_TTL_HOURS", 24) * 60 * 60,
+        }
+    }
+
+    fn load_or_create_secret(path: &Path) -> Vec<u8> {
+        if let Ok(secret) = fs::read_to_string(path) {
+            let secret = secret.trim();
+            if !secret.is_empty() {
+                return secret.as_bytes().to_vec();
+            }
+        }
+
+        let secret = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+        match persistence::write_atomic(path, secret.as_bytes()) {
+            Ok(()) => {
+                #[cfg(unix)]
+                {
+                    use std::os::unix::fs::PermissionsExt;
+                    let _ = fs::set_permissions(path, fs::Permissions::from_mode(0o600));
+                }
+                info!("Generated a new link signing secret in {:?}", path);
+            }
+            Err(e) => warn!("Failed to save the link signing secret, links will break on restart: {}", e),
+        }
+        secret.into_bytes()
+    }
+
+    fn now() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+    }
+
+    fn mac(&self, path: &str, expires: u64) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.secret).expect("HMAC accepts keys of any length");
+        mac.update(format!("{}:{}", path, expires).as_bytes());
+        mac
+    }
+
+    fn sign(&self, path: &str, ttl_secs: u64) -> SignedLink {
+        let expires = Self::now() + ttl_secs;
+        let signature = format!("{:x}", self.mac(path, expires).finalize().into_bytes());
+        let base_url = std::env::var("BASE_URL").unwrap_or_default();
+        SignedLink {
+            url: format!("{}{}?expires={}&sig={}", base_url, path, expires, signature),
+            expires_at: expires,
+        }
+    }
+
+    pub fn data_link(&self, id: &Uuid) -> SignedLink {
+        self.sign(&format!("/data/{}", id), self.data_ttl_secs)
+    }
+
+    pub fn export_link(&self, filename: &str) -> SignedLink {
+        self.sign(&format!("/exports/{}", filename), self.export_ttl_secs)
+    }
+
+    /// Checks the signature of a request for `path` (e.g. `/data/{id}`), in constant time
+    pub fn verify(&self, path: &str, query: &LinkQuery) -> Result<(), LinkError> {
+        let (Some(expires), Some(sig)) = (query.expires, query.sig.as_deref()) else {
+            return Err(LinkError::Unsigned);
+        };
+        let signature = decode_hex(sig).ok_or(LinkError::InvalidSignature)?;
+        self.mac(path, expires).verify_slice(&signature).map_err(|_| LinkError::InvalidSignature)?;
+
+        if expires < Self::now() {
+            return Err(LinkError::Expired);
+        }
+        Ok(())
+    }
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| hex.get(i..i + 2).and_then(|b| u8::from_str_radix(b, 16).ok()))
+        .collect()
+}
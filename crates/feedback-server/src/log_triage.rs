@@ -0,0 +1,267 @@
+use indexmap::IndexMap;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+const MAX_DETAILS_PER_PATTERN: usize = 100; // distinct details counted, the rest are only in the total
+const MAX_DETAIL_CHARS: usize = 120;
+const REPORTED_DETAILS: usize = 5;
+
+/// One entry of `log_patterns.json`
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct LogPatternConfig {
+    pub name: String,
+    /// Matched against every line. The first capture group that matched, if any, is the detail hits are grouped by.
+    pub regex: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct LogPatternsFile {
+    patterns: Vec<LogPatternConfig>,
+}
+
+/// The patterns used when there is no `log_patterns.json`, see `log_patterns.example.json`
+fn default_patterns() -> Vec<LogPatternConfig> {
+    [
+        ("Host_Error", r"Host_Error:?\s*(.*)"),
+        ("VScript error", r"(?i)AN ERROR HAS OCCURR?ED \[(.*)\]"),
+        ("VScript error", r"(?i)^\s*(?:vscript|script)\s+error:?\s*(.*)"),
+        ("Missing model", r"(?i)(?:unable to (?:find|load) model|error loading model|model not found)\W*([\w/.\-]+\.mdl)"),
+        ("Missing model", r"(?i)\b(models/[\w/.\-]+\.mdl)\W*(?:not found|is missing|couldn't be loaded)"),
+        ("Missing material", r"(?i)(?:material|texture)\s+['\x22]?([\w/.\-]+)['\x22]?\s+(?:not found|is missing)"),
+        ("Missing material", r"(?i)missing (?:material|texture)\W+([\w/.\-]+)"),
+        ("Entity spawn failure", r"(?i)(?:attempted to create unknown entity type|can't init|failed to spawn|unable to spawn)\s+['\x22]?([\w.\-]+)"),
+        ("Missing sound", r"(?i)(?:failed to load sound|unable to (?:find|load) sound|missing sound(?: file)?)\W+([\w/.\-]+)"),
+    ]
+    .iter()
+    .map(|(name, regex)| LogPatternConfig { name: name.to_string(), regex: regex.to_string() })
+    .collect()
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FindingDetail {
+    pub text: String,
+    pub count: usize,
+}
+
+/// Hits of one pattern name, several patterns may share a name
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Finding {
+    pub pattern: String,
+    pub count: usize,
+    pub details: Vec<FindingDetail>, // most frequent first, at most REPORTED_DETAILS
+}
+
+/// Triage summary of a console log, stored with its `FileMetadata`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LogFindings {
+    pub findings: Vec<Finding>, // most hits first
+    pub lines_scanned: usize,
+    pub truncated: bool, // the log was larger than the scan limit, only its beginning was read
+}
+
+/// Scans console logs (`{map}_console_{timestamp}.log`) for known problems
+pub struct LogAnalyzer {
+    patterns: Vec<(String, Regex)>,
+    max_bytes: u64,
+}
+
+impl LogAnalyzer {
+    /// Reads the patterns from `LOG_PATTERNS_CONFIG` (default `{BASE_DIR}/log_patterns.json`), or uses the
+    /// built-in ones without the file. Logs are scanned up to `LOG_SCAN_MAX_MB` (default 32).
+    pub fn load(base_dir: &Path) -> Self {
+        let path = std::env::var("LOG_PATTERNS_CONFIG").map(PathBuf::from).unwrap_or_else(|_| base_dir.join("log_patterns.json"));
+        let configs = match fs::read_to_string(&path) {
+            Ok(data) => match serde_json::from_str::<LogPatternsFile>(&data) {
+                Ok(file) => {
+                    info!("Loaded {} log triage patterns from {:?}", file.patterns.len(), path);
+                    file.patterns
+                }
+                Err(e) => {
+                    warn!("Invalid {:?}, using the built-in log triage patterns: {}", path, e);
+                    default_patterns()
+                }
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => default_patterns(),
+            Err(e) => {
+                warn!("Failed to read {:?}, using the built-in log triage patterns: {}", path, e);
+                default_patterns()
+            }
+        };
+
+        let patterns = configs.into_iter()
+            .filter_map(|config| match Regex::new(&config.regex) {
+                Ok(regex) => Some((config.name, regex)),
+                Err(e) => {
+                    warn!("Skipping log triage pattern '{}': {}", config.name, e);
+                    None
+                }
+            })
+            .collect();
+        let max_mb: u64 = std::env::var("LOG_SCAN_MAX_MB").ok().and_then(|v| v.parse().ok()).unwrap_or(32);
+
+        Self { patterns, max_bytes: max_mb * 1024 * 1024 }
+    }
+
+    /// Whether an upload is a console log written by the client
+    pub fn is_console_log(file_name: &str) -> bool {
+        let lower = file_name.to_ascii_lowercase();
+        lower.contains("_console_") && lower.ends_with(".log")
+    }
+
+    /// Scans a console log line by line. Returns `None` for other files.
+    pub fn analyze(&self, path: &Path, file_name: &str) -> Option<LogFindings> {
+        if self.patterns.is_empty() || !Self::is_console_log(file_name) {
+            return None;
+        }
+        match self.scan(path) {
+            Ok(findings) => Some(findings),
+            Err(e) => {
+                warn!("Failed to scan console log {}: {}", file_name, e);
+                None
+            }
+        }
+    }
+
+    fn scan(&self, path: &Path) -> Result<LogFindings, String> {
+        let file = File::open(path).map_err(|e| e.to_string())?;
+        let size = file.metadata().map_err(|e| e.to_string())?.len();
+        let mut reader = BufReader::new(file.take(self.max_bytes));
+
+        // pattern name -> (hits, detail -> hits)
+        let mut hits: IndexMap<&str, (usize, IndexMap<String, usize>)> = IndexMap::new();
+        let mut lines_scanned = 0;
+        let mut buffer = Vec::new();
+        loop {
+            buffer.clear();
+            if reader.read_until(b'\n', &mut buffer).map_err(|e| e.to_string())? == 0 {
+                break;
+            }
+            lines_scanned += 1;
+            // Console logs mix encodings, a broken line is still worth matching
+            let line = String::from_utf8_lossy(&buffer);
+
+            // A line counts once per pattern name, even when several of its patterns match
+            let mut matched_names: Vec<&str> = Vec::new();
+            for (name, regex) in &self.patterns {
+                if matched_names.contains(&name.as_str()) {
+                    continue;
+                }
+                let Some(captures) = regex.captures(&line) else { continue };
+                matched_names.push(name);
+
+                let detail = captures.iter().skip(1).flatten().next()
+                    .map(|m| m.as_str().trim().chars().take(MAX_DETAIL_CHARS).collect::<String>())
+                    .filter(|detail| !detail.is_empty());
+                let (count, details) = hits.entry(name).or_default();
+                *count += 1;
+                if let Some(detail) = detail {
+                    if let Some(detail_count) = details.get_mut(&detail) {
+                        *detail_count += 1;
+                    } else if details.len() < MAX_DETAILS_PER_PATTERN {
+                        details.insert(detail, 1);
+                    }
+                }
+            }
+        }
+
+        let mut findings: Vec<Finding> = hits.into_iter()
+            .map(|(name, (count, details))| {
+                let mut details: Vec<FindingDetail> = details.into_iter().map(|(text, count)| FindingDetail { text, count }).collect();
+                details.sort_by_key(|detail| std::cmp::Reverse(detail.count));
+                details.truncate(REPORTED_DETAILS);
+                Finding { pattern: name.to_string(), count, details }
+            })
+            .collect();
+        findings.sort_by_key(|finding| std::cmp::Reverse(finding.count));
+
+        Ok(LogFindings { findings, lines_scanned, truncated: size > self.max_bytes })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/sp_a1_intro1_console_1700000000.log");
+
+    /// The built-in patterns, `base_dir` has no log_patterns.json
+    fn analyzer() -> LogAnalyzer {
+        LogAnalyzer::load(&test_support::temp_dir())
+    }
+
+    fn counts(findings: &LogFindings) -> Vec<(&str, usize)> {
+        findings.findings.iter().map(|finding| (finding.pattern.as_str(), finding.count)).collect()
+    }
+
+    fn details<'a>(findings: &'a LogFindings, pattern: &str) -> Vec<(&'a str, usize)> {
+        let finding = findings.findings.iter().find(|finding| finding.pattern == pattern).unwrap();
+        finding.details.iter().map(|detail| (detail.text.as_str(), detail.count)).collect()
+    }
+
+    #[test]
+    fn default_patterns_group_the_fixture_log() {
+        let findings = analyzer().analyze(Path::new(FIXTURE), "sp_a1_intro1_console_1700000000.log").unwrap();
+
+        assert_eq!(findings.lines_scanned, 15);
+        assert!(!findings.truncated);
+        assert_eq!(counts(&findings), [
+            ("Entity spawn failure", 3),
+            ("Missing model", 3),
+            ("Missing material", 2),
+            ("VScript error", 2),
+            ("Missing sound", 1),
+            ("Host_Error", 1),
+        ]);
+        assert_eq!(details(&findings, "Entity spawn failure"), [("prop_weighted_cube_custom", 2), ("point_playtest_marker", 1)]);
+        assert_eq!(details(&findings, "Missing material"), [("custom/wall_01", 1), ("custom/floor_02", 1)]);
+        assert_eq!(details(&findings, "VScript error"), [("the index 'cube' does not exist", 2)]);
+        assert_eq!(details(&findings, "Missing sound"), [("custom/ambient_hum.wav", 1)]);
+        assert_eq!(details(&findings, "Host_Error"), [("Map sp_a1_intro1 has too many entities", 1)]);
+    }
+
+    #[test]
+    fn a_line_counts_once_per_pattern_name() {
+        let findings = analyzer().analyze(Path::new(FIXTURE), "sp_a1_intro1_console_1700000000.log").unwrap();
+
+        // The custom_lift line matches both "Missing model" patterns, the detail comes from the first
+        assert_eq!(details(&findings, "Missing model"), [("models/props/custom_button.mdl", 2), ("models/props/custom_lift.mdl", 1)]);
+    }
+
+    #[test]
+    fn distinct_details_are_capped() {
+        let path = test_support::temp_dir().join("map_console_1.log");
+        let mut log: String = (0..MAX_DETAILS_PER_PATTERN + 20).map(|i| format!("Host_Error: error {}\n", i)).collect();
+        log.push_str(&format!("Host_Error: error {}\n", MAX_DETAILS_PER_PATTERN + 10).repeat(10));
+        log.push_str(&"Host_Error: error 0\n".repeat(2));
+        fs::write(&path, log).unwrap();
+
+        let findings = analyzer().analyze(&path, "map_console_1.log").unwrap();
+        assert_eq!(counts(&findings), [("Host_Error", MAX_DETAILS_PER_PATTERN + 32)]);
+        // Details first seen after the cap are only in the total, however often they repeat
+        let reported = details(&findings, "Host_Error");
+        assert_eq!(reported.len(), REPORTED_DETAILS);
+        assert_eq!(reported[0], ("error 0", 3));
+        assert!(reported.iter().all(|(text, _)| *text != format!("error {}", MAX_DETAILS_PER_PATTERN + 10)));
+    }
+
+    #[test]
+    fn only_the_beginning_of_a_large_log_is_scanned() {
+        let mut analyzer = analyzer();
+        analyzer.max_bytes = 182; // the first four lines
+
+        let findings = analyzer.analyze(Path::new(FIXTURE), "sp_a1_intro1_console_1700000000.log").unwrap();
+        assert!(findings.truncated);
+        assert_eq!(findings.lines_scanned, 4);
+        assert_eq!(counts(&findings), [("Entity spawn failure", 2)]);
+    }
+
+    #[test]
+    fn other_files_are_not_scanned() {
+        assert!(analyzer().analyze(Path::new(FIXTURE), "notes.txt").is_none());
+    }
+}
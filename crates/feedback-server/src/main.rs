@@ -1,13 +1,41 @@
+mod api;
+mod bug_status;
+mod dashboard;
+mod dates;
+mod demos;
 mod discord_bot;
+mod duplicates;
+mod export_jobs;
+mod exporter;
 mod http_server;
 mod models;
+mod notifier;
 mod state;
 mod file_manager;
+mod idempotency;
+mod links;
+mod log_triage;
+mod metrics;
+mod persistence;
+mod previews;
+mod rate_limit;
+mod reconcile;
+mod retention;
+mod s3_storage;
+mod storage;
+mod submission_store;
+mod surveys;
+#[cfg(test)]
+mod test_support;
+mod validation;
+mod webhooks;
 
+use crate::notifier::Notifier;
 use crate::state::ServerState;
 use serenity::prelude::*;
 use dotenv::dotenv;
 use std::env;
+use std::sync::Arc;
 
 #[tokio::main]
 async fn main() {
@@ -23,40 +51,81 @@ async fn main() {
 
     // Start background tasks for file management (cleanup, expiration, storage enforcement)
     let fm_clone = app_state.file_manager.clone();
+    let metrics_clone = app_state.metrics.clone();
     tokio::spawn(async move {
-        fm_clone.run_background_tasks().await;
+        fm_clone.run_background_tasks(metrics_clone).await;
+    });
+
+    // Forget idempotency keys once they are too old to belong to a client retry, and expired dashboard sessions
+    let idempotency_clone = app_state.idempotency.clone();
+    let dashboard_clone = app_state.dashboard.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(60 * 60));
+        loop {
+            interval.tick().await;
+            idempotency_clone.purge_expired();
+            dashboard_clone.purge_expired();
+        }
     });
 
     // --- Start Discord Bot ---
-    let discord_token = env::var("DISCORD_TOKEN").expect("Expected a token in the environment");
-    let intents = GatewayIntents::non_privileged() | GatewayIntents::GUILDS | GatewayIntents::MESSAGE_CONTENT;
+    // Optional: without a token the server runs headless and notifies through the other notifiers
+    let discord_token = env::var("DISCORD_TOKEN").ok().filter(|t| !t.trim().is_empty());
+    let has_discord_bot = discord_token.is_some();
+    let notifier_names = notifier::configured_notifiers(has_discord_bot);
+    let mut notifiers: Vec<Arc<dyn Notifier>> = Vec::new();
 
-    let discord_state_clone = app_state.clone();
-    let mut client = Client::builder(&discord_token, intents)
-        .event_handler(discord_bot::BotHandler)
-        .await
-        .expect("Error creating Discord client");
+    if let Some(discord_token) = discord_token {
+        let intents = GatewayIntents::non_privileged() | GatewayIntents::GUILDS | GatewayIntents::MESSAGE_CONTENT;
 
-    {
-        // Inject server state into the Discord client's data map
-        let mut data = client.data.write().await;
-        data.insert::<ServerState>(discord_state_clone);
-    }
+        let discord_state_clone = app_state.clone();
+        let mut client = Client::builder(&discord_token, intents)
+            .event_handler(discord_bot::BotHandler)
+            .await
+            .expect("Error creating Discord client");
 
-    // Start the notification listener to forward submissions to Discord
-    let http_arc = client.http.clone();
-    let listener_state_clone = app_state.clone();
-    tokio::spawn(async move {
-        discord_bot::notification_listener(listener_state_clone, http_arc).await;
-    });
+        {
+            // Inject server state into the Discord client's data map
+            let mut data = client.data.write().await;
+            data.insert::<ServerState>(discord_state_clone);
+        }
 
-    // Start the Discord bot client
-    let shard_manager = client.shard_manager.clone();
-    tokio::spawn(async move {
-        if let Err(why) = client.start().await {
-            tracing::error!("Discord client error: {:?}", why);
+        if notifier_names.iter().any(|n| n == "discord") {
+            notifiers.push(Arc::new(discord_bot::DiscordNotifier { http: client.http.clone(), links: app_state.links.clone(), submissions: app_state.submissions.clone() }));
         }
-    });
+
+        // Start the Discord bot client
+        tokio::spawn(async move {
+            if let Err(why) = client.start().await {
+                tracing::error!("Discord client error: {:?}", why);
+            }
+        });
+    } else {
+        tracing::warn!("DISCORD_TOKEN is not set, running without the Discord bot.");
+    }
+
+    // --- Start Notifiers ---
+    for name in &notifier_names {
+        match name.as_str() {
+            "discord" if has_discord_bot => {} // created together with the bot client
+            "discord" => tracing::warn!("The discord notifier is configured in NOTIFIERS but disabled, DISCORD_TOKEN is not set."),
+            "webhooks" => notifiers.push(Arc::new(webhooks::WebhookNotifier::new(app_state.clone()))),
+            "log" => notifiers.push(Arc::new(notifier::LogNotifier)),
+            "file" => {
+                if let Some(file_drop) = notifier::file_drop_notifier(&app_state) {
+                    notifiers.push(Arc::new(file_drop));
+                }
+            }
+            other => tracing::warn!("Unknown notifier '{}' in NOTIFIERS, ignoring it.", other),
+        }
+    }
+    if notifiers.is_empty() {
+        tracing::warn!("No notifiers are active, submissions will only be stored.");
+    }
+    for notifier in notifiers {
+        tokio::spawn(notifier::run_notifier(app_state.clone(), notifier));
+    }
+    tokio::spawn(webhooks::prune_delivery_log(app_state.clone()));
 
     // --- Start HTTP Server ---
     let host = env::var("SERVER_HOST").unwrap_or_else(|_| "0.0.0.0".to_string());
@@ -0,0 +1,237 @@
+use crate::file_manager::FileStatus;
+use crate::state::ServerState;
+use axum::{
+    body::Body,
+    extract::{MatchedPath, State},
+    http::{header, HeaderMap, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use dashmap::DashMap;
+use sha2::{Digest, Sha256};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Upper bounds (seconds) of the duration histogram buckets
+const DURATION_BUCKETS: [f64; 11] = [0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0, 30.0, 60.0, 300.0];
+
+/// Label value for a moderator key: the first 12 hex characters of its SHA-256. Keys are secrets, and
+/// even a prefix narrows them down, so `/metrics` only shows a hash an operator can compute from a known key
+fn key_label(mod_key: &str) -> String {
+    let digest = Sha256::digest(mod_key.as_bytes());
+    digest.iter().take(6).map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Escapes a label value for the Prometheus text format
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [AtomicU64; DURATION_BUCKETS.len()],
+    sum_micros: AtomicU64,
+    count: AtomicU64,
+}
+
+impl Histogram {
+    fn observe(&self, duration: Duration) {
+        let secs = duration.as_secs_f64();
+        for (bucket, bound) in self.buckets.iter().zip(DURATION_BUCKETS) {
+            if secs <= bound {
+                bucket.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.sum_micros.fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let sep = if labels.is_empty() { "" } else { "," };
+        for (bucket, bound) in self.buckets.iter().zip(DURATION_BUCKETS) {
+            let _ = writeln!(out, "{}_bucket{{{}{}le=\"{}\"}} {}", name, labels, sep, bound, bucket.load(Ordering::Relaxed));
+        }
+        let count = self.count.load(Ordering::Relaxed);
+        let _ = writeln!(out, "{}_bucket{{{}{}le=\"+Inf\"}} {}", name, labels, sep, count);
+        let _ = writeln!(out, "{}_sum{{{}}} {}", name, labels, self.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0);
+        let _ = writeln!(out, "{}_count{{{}}} {}", name, labels, count);
+    }
+}
+
+/// In-process counters and histograms, rendered in the Prometheus text format by `/metrics`
+#[derive(Default)]
+pub struct Metrics {
+    submissions: DashMap<String, AtomicU64>,         // key hash
+    uploads: DashMap<String, AtomicU64>,             // key hash
+    bytes_received: DashMap<String, AtomicU64>,      // key hash
+    http_responses: DashMap<(String, String, u16), AtomicU64>, // method, route, status
+    background_tasks: DashMap<&'static str, Histogram>,
+    notifications: DashMap<&'static str, Histogram>, // notifier
+    notification_failures: DashMap<&'static str, AtomicU64>,
+    webhook_deliveries: DashMap<&'static str, AtomicU64>, // outcome
+}
+
+impl Metrics {
+    fn inc<K: std::hash::Hash + Eq>(map: &DashMap<K, AtomicU64>, key: K, by: u64) {
+        map.entry(key).or_default().fetch_add(by, Ordering::Relaxed);
+    }
+
+    pub fn record_submission(&self, mod_key: &str, json_bytes: u64) {
+        Self::inc(&self.submissions, key_label(mod_key), 1);
+        Self::inc(&self.bytes_received, key_label(mod_key), json_bytes);
+    }
+
+    /// A completed upload (single request or finalized resumable upload)
+    pub fn record_upload(&self, mod_key: &str) {
+        Self::inc(&self.uploads, key_label(mod_key), 1);
+    }
+
+    pub fn record_bytes_received(&self, mod_key: &str, bytes: u64) {
+        Self::inc(&self.bytes_received, key_label(mod_key), bytes);
+    }
+
+    pub fn record_background_task(&self, task: &'static str, duration: Duration) {
+        self.background_tasks.entry(task).or_default().observe(duration);
+    }
+
+    pub fn record_notification(&self, notifier: &'static str, duration: Duration, failed: bool) {
+        self.notifications.entry(notifier).or_default().observe(duration);
+        if failed {
+            Self::inc(&self.notification_failures, notifier, 1);
+        }
+    }
+
+    pub fn record_webhook_delivery(&self, succeeded: bool) {
+        Self::inc(&self.webhook_deliveries, if succeeded { "success" } else { "failure" }, 1);
+    }
+
+    fn record_http_response(&self, method: &str, route: &str, status: u16) {
+        Self::inc(&self.http_responses, (method.to_string(), route.to_string(), status), 1);
+    }
+
+    fn render_counter(out: &mut String, name: &str, help: &str, label: &str, map: &DashMap<String, AtomicU64>) {
+        let _ = writeln!(out, "# HELP {} {}\n# TYPE {} counter", name, help, name);
+        for entry in map.iter() {
+            let _ = writeln!(out, "{}{{{}=\"{}\"}} {}", name, label, escape(entry.key()), entry.value().load(Ordering::Relaxed));
+        }
+    }
+
+    fn render_gauge(out: &mut String, name: &str, help: &str, values: &[(&str, u64)]) {
+        let _ = writeln!(out, "# HELP {} {}\n# TYPE {} gauge", name, help, name);
+        for (labels, value) in values {
+            if labels.is_empty() {
+                let _ = writeln!(out, "{} {}", name, value);
+            } else {
+                let _ = writeln!(out, "{}{{{}}} {}", name, labels, value);
+            }
+        }
+    }
+
+    /// Renders all metrics, storage gauges are measured at scrape time
+    pub fn render(&self, state: &ServerState) -> String {
+        let mut out = String::new();
+
+        Self::render_counter(&mut out, "feedback_submissions_total", "Submissions stored, per key hash.", "key", &self.submissions);
+        Self::render_counter(&mut out, "feedback_uploads_total", "Attachment uploads completed, per key hash.", "key", &self.uploads);
+        Self::render_counter(&mut out, "feedback_received_bytes_total", "Bytes received in submissions and uploads, per key hash.", "key", &self.bytes_received);
+
+        let _ = writeln!(out, "# HELP feedback_http_responses_total HTTP responses by method, route and status code.\n# TYPE feedback_http_responses_total counter");
+        for entry in self.http_responses.iter() {
+            let (method, route, status) = entry.key();
+            let _ = writeln!(
+                out,
+                "feedback_http_responses_total{{method=\"{}\",route=\"{}\",status=\"{}\"}} {}",
+                escape(method), escape(route), status, entry.value().load(Ordering::Relaxed)
+            );
+        }
+
+        // Storage, from the file index and the TEMP_UPLOADS directory
+        let (stored_bytes, _) = state.file_manager.storage_usage();
+        let (mut active_files, mut active_bytes, mut expired_files, mut expired_bytes) = (0, 0, 0, 0);
+        for entry in state.file_manager.files.iter() {
+            match entry.status {
+                FileStatus::Active => {
+                    active_files += 1;
+                    active_bytes += entry.size_bytes;
+                }
+                _ => {
+                    expired_files += 1;
+                    expired_bytes += entry.size_bytes;
+                }
+            }
+        }
+        let (temp_files, temp_bytes) = std::fs::read_dir(state.file_manager.base_dir.join("TEMP_UPLOADS"))
+            .map(|entries| entries.flatten()
+                .filter_map(|e| e.metadata().ok())
+                .filter(|m| m.is_file())
+                .fold((0, 0), |(n, bytes), m| (n + 1, bytes + m.len())))
+            .unwrap_or((0, 0));
+
+        Self::render_gauge(&mut out, "feedback_files", "Files in the file index by status.", &[
+            ("status=\"active\"", active_files),
+            ("status=\"expired\"", expired_files),
+        ]);
+        Self::render_gauge(&mut out, "feedback_file_bytes", "Size of indexed files by status, before deduplication.", &[
+            ("status=\"active\"", active_bytes),
+            ("status=\"expired\"", expired_bytes),
+        ]);
+        Self::render_gauge(&mut out, "feedback_storage_used_bytes", "Bytes occupied by active files on disk, deduplicated.", &[("", stored_bytes)]);
+        Self::render_gauge(&mut out, "feedback_storage_limit_bytes", "Configured MAX_STORAGE_MB in bytes.", &[("", state.file_manager.max_storage_bytes)]);
+        Self::render_gauge(&mut out, "feedback_temp_upload_files", "Files in TEMP_UPLOADS.", &[("", temp_files)]);
+        Self::render_gauge(&mut out, "feedback_temp_upload_bytes", "Bytes in TEMP_UPLOADS.", &[("", temp_bytes)]);
+        Self::render_gauge(&mut out, "feedback_upload_sessions", "Resumable upload sessions in progress.", &[("", state.file_manager.upload_sessions.len() as u64)]);
+
+        let _ = writeln!(out, "# HELP feedback_background_task_duration_seconds Duration of file manager background tasks.\n# TYPE feedback_background_task_duration_seconds histogram");
+        for entry in self.background_tasks.iter() {
+            entry.value().render(&mut out, "feedback_background_task_duration_seconds", &format!("task=\"{}\"", entry.key()));
+        }
+
+        let _ = writeln!(out, "# HELP feedback_notification_duration_seconds Time spent delivering a submission event, per notifier.\n# TYPE feedback_notification_duration_seconds histogram");
+        for entry in self.notifications.iter() {
+            entry.value().render(&mut out, "feedback_notification_duration_seconds", &format!("notifier=\"{}\"", entry.key()));
+        }
+
+        let _ = writeln!(out, "# HELP feedback_notification_failures_total Submission events a notifier failed to deliver.\n# TYPE feedback_notification_failures_total counter");
+        for entry in self.notification_failures.iter() {
+            let _ = writeln!(out, "feedback_notification_failures_total{{notifier=\"{}\"}} {}", entry.key(), entry.value().load(Ordering::Relaxed));
+        }
+
+        let _ = writeln!(out, "# HELP feedback_webhook_deliveries_total Webhook delivery attempts by outcome.\n# TYPE feedback_webhook_deliveries_total counter");
+        for entry in self.webhook_deliveries.iter() {
+            let _ = writeln!(out, "feedback_webhook_deliveries_total{{outcome=\"{}\"}} {}", entry.key(), entry.value().load(Ordering::Relaxed));
+        }
+
+        out
+    }
+}
+
+/// Counts responses by method, matched route and status code
+pub async fn track_http(State(state): State<ServerState>, request: Request<Body>, next: Next) -> Response {
+    let method = request.method().to_string();
+    // The route template keeps label cardinality low, unmatched paths are grouped together
+    let route = request.extensions().get::<MatchedPath>()
+        .map(|p| p.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+
+    let response = next.run(request).await;
+    state.metrics.record_http_response(&method, &route, response.status().as_u16());
+    response
+}
+
+/// `GET /metrics`. When `METRICS_TOKEN` is set and not empty, it must be sent as a bearer token
+pub async fn serve_metrics(State(state): State<ServerState>, headers: HeaderMap) -> Response {
+    if let Some(token) = std::env::var("METRICS_TOKEN").ok().filter(|t| !t.is_empty()) {
+        let authorized = headers.get(header::AUTHORIZATION)
+            .and_then(|h| h.to_str().ok())
+            .is_some_and(|h| h.strip_prefix("Bearer ") == Some(token.as_str()));
+        if !authorized {
+            return StatusCode::UNAUTHORIZED.into_response();
+        }
+    }
+
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.metrics.render(&state),
+    ).into_response()
+}
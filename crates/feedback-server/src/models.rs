@@ -1,5 +1,6 @@
 use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
+use crate::duplicates::DuplicateMatch;
 use crate::file_manager::FileMetadata;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -13,6 +14,8 @@ pub struct FormSubmission {
     pub answers: IndexMap<String, String>,
     pub custom_embed_color: Option<i32>,
     pub files: Vec<(String, String)>, // (file_id, file_name)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub survey_hash: Option<String>, // content hash of the survey definition, see POST /surveys
 
     #[serde(flatten)]
     pub extra_data: IndexMap<String, serde_json::Value>,
@@ -27,15 +30,74 @@ pub struct ModeratorKeyData {
     pub server_name: String,    // For display purposes
     #[serde(default)]
     pub is_priority: bool,      // Whether this key has priority status for storage
+    #[serde(default)]
+    pub limits: KeyLimits,      // Per-key overrides of the default rate limits and quotas
+    #[serde(default)]
+    pub label: Option<String>,  // Free-form name shown in /list_keys
+    #[serde(default)]
+    pub created_at: Option<u64>,    // Unknown for keys generated before this was recorded
+    #[serde(default)]
+    pub last_used_at: Option<u64>,
+    #[serde(default)]
+    pub revoked_at: Option<u64>,
+    #[serde(default)]
+    pub expires_at: Option<u64>,    // End of the grace period of a rotated key
+    #[serde(default)]
+    pub replaced_by: Option<String>, // Key that took over this key's data after a rotation
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub webhooks: Vec<WebhookConfig>, // Extra destinations notified about every submission
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub forum_channel_id: Option<String>, // Forum channel submissions are posted to instead, see /set_forum
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub forum_tag_questions: Vec<String>, // Questions whose answers pick the tags of forum posts
+    #[serde(default)]
+    pub auto_threads: bool,     // Start a thread on every notification sent to channel_id
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub triager_role_id: Option<String>, // Role allowed to change bug reports besides Manage Messages, see /triager_role
+}
+
+// An outgoing webhook receiving submission events as JSON POSTs
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WebhookConfig {
+    pub id: uuid::Uuid,
+    pub url: String,
+    #[serde(default)]
+    pub secret: Option<String>, // When set, requests are signed with HMAC-SHA256
+    pub created_at: u64,
+}
+
+impl ModeratorKeyData {
+    /// Whether the key was revoked or its rotation grace period is over
+    pub fn is_revoked(&self, now: u64) -> bool {
+        self.revoked_at.is_some() || self.expires_at.is_some_and(|exp| now >= exp)
+    }
+
+    /// The key that should be used for this channel: not revoked and not rotated out
+    pub fn is_current(&self, now: u64) -> bool {
+        !self.is_revoked(now) && self.replaced_by.is_none()
+    }
+}
+
+// Limits applied to a single moderator key. Unset fields fall back to the server-wide defaults
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+pub struct KeyLimits {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requests_per_minute: Option<u32>,   // Submissions, uploads and upload sessions started
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upload_bytes_per_day: Option<u64>,  // Attachment bytes accepted per UTC day
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_stored_bytes: Option<u64>,      // Active files kept on disk for this key
 }
 
 // The event passed internally after a submission is successfully processed
 #[derive(Debug, Clone)]
 pub struct SubmissionEvent {
     pub submission_id: uuid::Uuid,
+    pub mod_key: String,
     pub destination: ModeratorKeyData,
     pub submission: FormSubmission,
     pub submission_bytes: Vec<u8>,
     pub filename: String,
     pub attached_files: Vec<FileMetadata>,
+    pub possible_duplicates: Vec<DuplicateMatch>, // earlier bug reports this one likely duplicates
 }
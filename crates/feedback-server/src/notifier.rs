@@ -0,0 +1,116 @@
+use crate::links::LinkSigner;
+use crate::models::SubmissionEvent;
+use crate::state::ServerState;
+use async_trait::async_trait;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{error, info, warn};
+
+/// A destination for submission events, e.g. Discord, webhooks or the log
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    async fn notify(&self, event: &SubmissionEvent) -> Result<(), String>;
+}
+
+/// Logs a one-line summary of each submission, for headless setups
+pub struct LogNotifier;
+
+#[async_trait]
+impl Notifier for LogNotifier {
+    fn name(&self) -> &'static str {
+        "log"
+    }
+
+    async fn notify(&self, event: &SubmissionEvent) -> Result<(), String> {
+        let submission = &event.submission;
+        info!(
+            "New submission {}: survey '{}' on map '{}' from {} ({}), {} answers, {} files, server '{}'",
+            event.submission_id,
+            submission.survey_id,
+            submission.map_name,
+            submission.user_name,
+            submission.user_xuid,
+            submission.answers.len(),
+            event.attached_files.len(),
+            event.destination.server_name,
+        );
+        Ok(())
+    }
+}
+
+/// Writes each submission event as a JSON file into a directory watched by other tools
+pub struct FileDropNotifier {
+    pub dir: PathBuf,
+    pub links: Arc<LinkSigner>,
+}
+
+#[async_trait]
+impl Notifier for FileDropNotifier {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    async fn notify(&self, event: &SubmissionEvent) -> Result<(), String> {
+        let body = crate::webhooks::build_payload(event, &self.links).map_err(|e| e.to_string())?;
+        let filename = format!("{}_{}.json", event.submission.submission_timestamp, event.submission_id);
+
+        // Written under a temporary name first, so watchers never pick up a partial file
+        let temp_path = self.dir.join(format!(".{}.tmp", filename));
+        tokio::fs::write(&temp_path, body).await.map_err(|e| e.to_string())?;
+        tokio::fs::rename(&temp_path, self.dir.join(&filename)).await.map_err(|e| e.to_string())
+    }
+}
+
+/// Notifier names from the `NOTIFIERS` env var (comma separated).
+/// Defaults to Discord and webhooks when a Discord token is configured, and to the log and webhooks otherwise.
+pub fn configured_notifiers(has_discord_token: bool) -> Vec<String> {
+    match std::env::var("NOTIFIERS") {
+        Ok(list) => list.split(',')
+            .map(|name| name.trim().to_ascii_lowercase())
+            .filter(|name| !name.is_empty())
+            .collect(),
+        Err(_) if has_discord_token => vec!["discord".to_string(), "webhooks".to_string()],
+        Err(_) => vec!["log".to_string(), "webhooks".to_string()],
+    }
+}
+
+/// Builds the file-drop notifier, its directory comes from `NOTIFY_DROP_DIR` (default: `{BASE_DIR}/NOTIFICATIONS`)
+pub fn file_drop_notifier(state: &ServerState) -> Option<FileDropNotifier> {
+    let dir = std::env::var("NOTIFY_DROP_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| state.file_manager.base_dir.join("NOTIFICATIONS"));
+
+    if let Err(e) = std::fs::create_dir_all(&dir) {
+        error!("Failed to create notification drop directory {:?}: {}", dir, e);
+        return None;
+    }
+    Some(FileDropNotifier { dir, links: state.links.clone() })
+}
+
+/// Feeds submission events from the broadcast channel to a notifier until the channel closes.
+/// Each notifier has its own receiver, so a slow destination does not delay the others.
+pub async fn run_notifier(state: ServerState, notifier: Arc<dyn Notifier>) {
+    let mut receiver = state.submission_sender.subscribe();
+    info!("Notifier '{}' started.", notifier.name());
+
+    loop {
+        match receiver.recv().await {
+            Ok(event) => {
+                let started = Instant::now();
+                let result = notifier.notify(&event).await;
+                state.metrics.record_notification(notifier.name(), started.elapsed(), result.is_err());
+                if let Err(e) = result {
+                    error!("Notifier '{}' failed for submission {}: {}", notifier.name(), event.submission_id, e);
+                }
+            }
+            Err(RecvError::Lagged(skipped)) => {
+                warn!("Notifier '{}' lagged behind, {} events were skipped", notifier.name(), skipped);
+            }
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use serde::de::DeserializeOwned;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{error, warn};
+
+/// Serializes writers, so concurrent saves of the same file cannot interleave their temp files and backups
+static WRITE_LOCK: Mutex<()> = Mutex::new(());
+
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+pub fn backup_path(path: &Path) -> PathBuf {
+    sibling(path, ".bak")
+}
+
+/// Writes the contents to `{path}.tmp`, flushes them to disk and renames the temp file over `path`.
+/// Readers see either the old or the new contents, never a partial write.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let _guard = WRITE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let temp_path = write_temp(path, contents)?;
+    fs::rename(&temp_path, path)?;
+    sync_parent(path);
+    Ok(())
+}
+
+/// Like `write_atomic`, but the previous contents are kept as `{path}.bak`.
+/// If `path` goes missing or is unreadable, `load_json` falls back to the backup.
+pub fn write_atomic_with_backup(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let _guard = WRITE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let temp_path = write_temp(path, contents)?;
+    if path.exists() {
+        fs::rename(path, backup_path(path))?;
+    }
+    fs::rename(&temp_path, path)?;
+    sync_parent(path);
+    Ok(())
+}
+
+fn write_temp(path: &Path, contents: &[u8]) -> io::Result<PathBuf> {
+    let temp_path = sibling(path, ".tmp");
+    let mut file = fs::File::create(&temp_path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    Ok(temp_path)
+}
+
+/// Persists the renames themselves. Not supported for directories on every platform, so errors are ignored
+fn sync_parent(path: &Path) {
+    if let Some(dir) = path.parent().filter(|p| !p.as_os_str().is_empty())
+        && let Ok(dir) = fs::File::open(dir) {
+        let _ = dir.sync_all();
+    }
+}
+
+/// Where `load_json` found readable data
+#[derive(Debug)]
+pub enum Loaded<T> {
+    Primary(T),
+    Backup(T),
+    Missing,
+}
+
+/// Reads a JSON file written by `write_atomic_with_backup`, falling back to its backup.
+/// Fails only when data exists but neither generation can be parsed.
+pub fn load_json<T: DeserializeOwned>(path: &Path) -> Result<Loaded<T>, String> {
+    let read = |path: &Path| -> Option<Result<T, String>> {
+        match fs::read_to_string(path) {
+            Ok(data) => Some(serde_json::from_str(&data).map_err(|e| format!("{:?}: {}", path, e))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => Some(Err(format!("{:?}: {}", path, e))),
+        }
+    };
+
+    let primary_error = match read(path) {
+        Some(Ok(data)) => return Ok(Loaded::Primary(data)),
+        Some(Err(e)) => Some(e),
+        None => None,
+    };
+    if let Some(e) = &primary_error {
+        warn!("Failed to read {}, trying the backup", e);
+    }
+
+    match (read(&backup_path(path)), primary_error) {
+        (Some(Ok(data)), primary_error) => {
+            // Otherwise the next save would rotate the broken file into the backup slot
+            if primary_error.is_some() {
+                quarantine(path);
+            }
+            Ok(Loaded::Backup(data))
+        }
+        (Some(Err(backup_error)), Some(primary_error)) => Err(format!("{}; backup {}", primary_error, backup_error)),
+        (Some(Err(backup_error)), None) => Err(backup_error),
+        (None, Some(primary_error)) => Err(primary_error),
+        (None, None) => Ok(Loaded::Missing),
+    }
+}
+
+/// Renames an unreadable file out of the way, so the next save does not overwrite what may still be recovered by hand
+pub fn quarantine(path: &Path) {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let target = sibling(path, &format!(".corrupt-{}", now));
+    match fs::rename(path, &target) {
+        Ok(()) => error!("Moved unreadable {:?} to {:?}", path, target),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => error!("Failed to move unreadable {:?} aside: {}", path, e),
+    }
+}
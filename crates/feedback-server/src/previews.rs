@@ -0,0 +1,242 @@
+use crate::file_manager::{FileManager, FileMetadata};
+use crate::retention::FileKind;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+const FFMPEG_TIMEOUT: Duration = Duration::from_secs(60);
+const POSTER_AT_SECS: &str = "1"; // skips the black first frame most recordings start with
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum PreviewKind {
+    Poster, // a single JPEG frame
+    Clip,   // the first seconds as a small animated GIF or WebP
+}
+
+/// A file generated from a video, stored as its own `FileMetadata` with `derived_from` set
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Preview {
+    pub kind: PreviewKind,
+    pub file_id: Uuid,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClipFormat {
+    Gif,
+    Webp,
+}
+
+/// Poster frames and preview clips of uploaded recordings, made with a local ffmpeg
+#[derive(Debug, Clone)]
+pub struct VideoPreviews {
+    ffmpeg: Option<PathBuf>, // None when previews are disabled or ffmpeg could not be run
+    clip: Option<ClipFormat>,
+    clip_secs: u32,
+    clip_width: u32,
+}
+
+impl VideoPreviews {
+    /// Reads `FFMPEG_PATH` (default `ffmpeg`, empty disables previews), `VIDEO_PREVIEW_CLIP` (`none`, `gif` or `webp`),
+    /// `VIDEO_PREVIEW_SECONDS` and `VIDEO_PREVIEW_WIDTH`, and checks that ffmpeg can be run
+    pub fn from_env() -> Self {
+        let ffmpeg = std::env::var("FFMPEG_PATH").unwrap_or_else(|_| "ffmpeg".to_string());
+        let ffmpeg = if ffmpeg.is_empty() {
+            info!("FFMPEG_PATH is empty, video previews are disabled");
+            None
+        } else {
+            match Command::new(&ffmpeg).arg("-version").stdout(Stdio::null()).stderr(Stdio::null()).status() {
+                Ok(status) if status.success() => Some(PathBuf::from(ffmpeg)),
+                Ok(status) => {
+                    warn!("'{} -version' exited with {}, video previews are disabled", ffmpeg, status);
+                    None
+                }
+                Err(e) => {
+                    info!("ffmpeg not found at '{}' ({}), video previews are disabled", ffmpeg, e);
+                    None
+                }
+            }
+        };
+
+        let clip = match std::env::var("VIDEO_PREVIEW_CLIP").unwrap_or_default().to_ascii_lowercase().as_str() {
+            "gif" => Some(ClipFormat::Gif),
+            "webp" => Some(ClipFormat::Webp),
+            "" | "none" => None,
+            other => {
+                warn!("Unknown VIDEO_PREVIEW_CLIP '{}', expected 'none', 'gif' or 'webp'. Only posters are generated.", other);
+                None
+            }
+        };
+        let env_u32 = |name: &str, default: u32| std::env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default);
+
+        Self {
+            ffmpeg,
+            clip,
+            clip_secs: env_u32("VIDEO_PREVIEW_SECONDS", 3).clamp(1, 10),
+            clip_width: env_u32("VIDEO_PREVIEW_WIDTH", 320).clamp(64, 1280),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn disabled() -> Self {
+        Self { ffmpeg: None, clip: None, clip_secs: 3, clip_width: 320 }
+    }
+
+    /// Whether any of the files is a recording previews would be generated for
+    pub fn wants_any(&self, files: &[FileMetadata]) -> bool {
+        self.ffmpeg.is_some() && files.iter().any(|file| FileKind::of(&file.original_name) == FileKind::Video)
+    }
+
+    /// Generates the previews of every video among `files` and records them on the originals.
+    /// Blocking, ffmpeg takes a few seconds per recording.
+    pub fn generate_all(&self, file_manager: &FileManager, files: &mut [FileMetadata]) {
+        let Some(ffmpeg) = &self.ffmpeg else { return };
+        let mut generated = false;
+        for file in files.iter_mut() {
+            if FileKind::of(&file.original_name) != FileKind::Video || !file.previews.is_empty() {
+                continue;
+            }
+            let started = Instant::now();
+            match self.generate(ffmpeg, file_manager, file) {
+                Ok(previews) if previews.is_empty() => {}
+                Ok(previews) => {
+                    info!("Generated {} previews for {} in {:?}", previews.len(), file.id, started.elapsed());
+                    if let Some(mut entry) = file_manager.files.get_mut(&file.id) {
+                        entry.previews = previews.clone();
+                    }
+                    file.previews = previews;
+                    generated = true;
+                }
+                Err(e) => warn!("Failed to generate previews for {} ({}): {}", file.id, file.original_name, e),
+            }
+        }
+        if generated {
+            file_manager.save_to_disk();
+        }
+    }
+
+    fn generate(&self, ffmpeg: &Path, file_manager: &FileManager, video: &FileMetadata) -> Result<Vec<Preview>, String> {
+        // ffmpeg needs a seekable file, recordings that are not on local disk are downloaded first
+        let (source, downloaded) = if video.path.is_file() {
+            (video.path.clone(), false)
+        } else {
+            let path = file_manager.temp_path(&Uuid::new_v4());
+            let mut reader = file_manager.open_file(video)?;
+            let mut file = fs::File::create(&path).map_err(|e| e.to_string())?;
+            io::copy(&mut reader, &mut file).map_err(|e| e.to_string())?;
+            (path, true)
+        };
+
+        let stem = Path::new(&video.original_name).file_stem().and_then(|s| s.to_str()).unwrap_or("video");
+        let mut previews = Vec::new();
+
+        // Recordings shorter than the poster offset have no frame there, their first frame is used instead
+        let poster_output = ["-frames:v", "1", "-vf", "scale='min(1280,iw)':-2", "-q:v", "3", "-c:v", "mjpeg", "-f", "image2"];
+        let poster = render(ffmpeg, file_manager, &source, &["-ss", POSTER_AT_SECS], &poster_output)
+            .or_else(|_| render(ffmpeg, file_manager, &source, &[], &poster_output));
+        match poster.and_then(|temp_id| register(file_manager, video, temp_id, &format!("{}_poster.jpg", stem))) {
+            Ok(file_id) => previews.push(Preview { kind: PreviewKind::Poster, file_id }),
+            Err(e) => warn!("No poster frame for {}: {}", video.id, e),
+        }
+
+        if let Some(format) = self.clip {
+            let duration = self.clip_secs.to_string();
+            let scale = format!("fps=10,scale={}:-2:flags=lanczos", self.clip_width);
+            let (filter, name) = match format {
+                // A palette made from the clip itself keeps GIFs small and free of dithering noise
+                ClipFormat::Gif => (format!("{},split[a][b];[a]palettegen[p];[b][p]paletteuse", scale), format!("{}_preview.gif", stem)),
+                ClipFormat::Webp => (scale, format!("{}_preview.webp", stem)),
+            };
+            let clip_output: &[&str] = match format {
+                ClipFormat::Gif => &["-t", &duration, "-vf", &filter, "-loop", "0", "-f", "gif"],
+                ClipFormat::Webp => &["-t", &duration, "-vf", &filter, "-c:v", "libwebp", "-quality", "60", "-loop", "0", "-f", "webp"],
+            };
+            match render(ffmpeg, file_manager, &source, &[], clip_output)
+                .and_then(|temp_id| register(file_manager, video, temp_id, &name)) {
+                Ok(file_id) => previews.push(Preview { kind: PreviewKind::Clip, file_id }),
+                Err(e) => warn!("No preview clip for {}: {}", video.id, e),
+            }
+        }
+
+        if downloaded {
+            let _ = fs::remove_file(&source);
+        }
+        Ok(previews)
+    }
+}
+
+/// Runs ffmpeg on `source` into a new file in TEMP_UPLOADS and returns its id
+fn render(ffmpeg: &Path, file_manager: &FileManager, source: &Path, input_args: &[&str], output_args: &[&str]) -> Result<Uuid, String> {
+    let temp_id = Uuid::new_v4();
+    let output = file_manager.temp_path(&temp_id);
+
+    let mut command = Command::new(ffmpeg);
+    command.args(["-hide_banner", "-loglevel", "error", "-nostdin", "-y"])
+        .args(input_args)
+        .arg("-i").arg(source)
+        .args(["-an", "-map_metadata", "-1"])
+        .args(output_args)
+        .arg(&output);
+
+    let result = run_with_timeout(command, FFMPEG_TIMEOUT).and_then(|()| {
+        match fs::metadata(&output) {
+            Ok(meta) if meta.len() > 0 => Ok(temp_id),
+            _ => Err("ffmpeg produced no output".to_string()),
+        }
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&output);
+    }
+    result
+}
+
+/// Commits a rendered preview like an upload and links it to the video it was made from.
+/// Previews have no expiration of their own, they are deleted together with the video.
+fn register(file_manager: &FileManager, video: &FileMetadata, temp_id: Uuid, name: &str) -> Result<Uuid, String> {
+    let meta = file_manager.commit_temp_file(temp_id, &video.mod_key, name, video.is_priority)?;
+    if let Some(mut entry) = file_manager.files.get_mut(&meta.id) {
+        entry.derived_from = Some(video.id);
+        entry.expires_at = None;
+    }
+    Ok(meta.id)
+}
+
+/// Waits for the command, killing it when it runs longer than `timeout`.
+/// Stderr is drained on a thread while waiting, a full pipe would block ffmpeg until the timeout.
+fn run_with_timeout(mut command: Command, timeout: Duration) -> Result<(), String> {
+    let mut child = command.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::piped())
+        .spawn().map_err(|e| e.to_string())?;
+    let stderr_reader = child.stderr.take().map(|mut pipe| {
+        std::thread::spawn(move || {
+            let mut stderr = Vec::new();
+            let _ = pipe.read_to_end(&mut stderr);
+            String::from_utf8_lossy(&stderr).into_owned()
+        })
+    });
+    let stderr = move || stderr_reader.and_then(|reader| reader.join().ok()).unwrap_or_default();
+
+    let started = Instant::now();
+    loop {
+        match child.try_wait().map_err(|e| e.to_string())? {
+            Some(status) if status.success() => {
+                stderr();
+                return Ok(());
+            }
+            Some(status) => {
+                return Err(format!("ffmpeg exited with {}: {}", status, stderr().trim().chars().take(300).collect::<String>()));
+            }
+            None if started.elapsed() > timeout => {
+                let _ = child.kill();
+                let _ = child.wait();
+                stderr();
+                return Err(format!("ffmpeg took longer than {:?} and was killed", timeout));
+            }
+            None => std::thread::sleep(Duration::from_millis(100)),
+        }
+    }
+}
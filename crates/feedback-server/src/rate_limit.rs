@@ -0,0 +1,312 @@
+use crate::models::{KeyLimits, ModeratorKeyData};
+use crate::state::ServerState;
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, HeaderValue, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use dashmap::DashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+const MINUTE_SECS: u64 = 60;
+const DAY_SECS: u64 = 24 * 60 * 60;
+const STORAGE_RETRY_SECS: u64 = 60 * 60; // when no stored file of the key is about to expire
+
+/// Fixed-window counter, `window` is the index of the current minute or day
+#[derive(Debug, Clone, Copy, Default)]
+struct Window {
+    window: u64,
+    used: u64,
+}
+
+/// Tracks request rates and uploaded bytes per moderator key, in memory
+#[derive(Default)]
+pub struct RateLimiter {
+    requests: DashMap<String, Window>,
+    upload_bytes: DashMap<String, Window>,
+}
+
+impl RateLimiter {
+    fn now() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+    }
+
+    /// Returns the number of seconds until the window resets when adding `amount` to the key's counter would exceed `limit`
+    fn check(counters: &DashMap<String, Window>, key: &str, window_secs: u64, limit: u64, amount: u64) -> Result<(), u64> {
+        let now = Self::now();
+        let window = now / window_secs;
+
+        let used = counters.get(key).filter(|counter| counter.window == window).map_or(0, |counter| counter.used);
+        if used + amount > limit {
+            return Err((window + 1) * window_secs - now);
+        }
+        Ok(())
+    }
+
+    /// Adds `amount` to the key's counter for the current window if it stays within `limit`.
+    /// Otherwise returns the number of seconds until the window resets.
+    fn take(counters: &DashMap<String, Window>, key: &str, window_secs: u64, limit: u64, amount: u64) -> Result<(), u64> {
+        let now = Self::now();
+        let window = now / window_secs;
+
+        let mut counter = counters.entry(key.to_string()).or_default();
+        if counter.window != window {
+            *counter = Window { window, used: 0 };
+        }
+        if counter.used + amount > limit {
+            return Err((window + 1) * window_secs - now);
+        }
+        counter.used += amount;
+        Ok(())
+    }
+
+    /// Adds `amount` to the key's counter for the current window, also past its limit
+    fn add(counters: &DashMap<String, Window>, key: &str, window_secs: u64, amount: u64) {
+        let window = Self::now() / window_secs;
+
+        let mut counter = counters.entry(key.to_string()).or_default();
+        if counter.window != window {
+            *counter = Window { window, used: 0 };
+        }
+        counter.used += amount;
+    }
+
+    /// Checks the request rate without counting the request
+    pub fn request_allowed(&self, key: &str, limits: &KeyLimits) -> Result<(), u64> {
+        match limits.requests_per_minute.or_else(default_requests_per_minute) {
+            Some(limit) => Self::check(&self.requests, key, MINUTE_SECS, limit as u64, 1),
+            None => Ok(()),
+        }
+    }
+
+    pub fn check_request(&self, key: &str, limits: &KeyLimits) -> Result<(), u64> {
+        match limits.requests_per_minute.or_else(default_requests_per_minute) {
+            Some(limit) => Self::take(&self.requests, key, MINUTE_SECS, limit as u64, 1),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks the daily upload volume without counting `bytes`
+    pub fn upload_bytes_allowed(&self, key: &str, limits: &KeyLimits, bytes: u64) -> Result<(), u64> {
+        match limits.upload_bytes_per_day.or_else(default_upload_bytes_per_day) {
+            Some(limit) => Self::check(&self.upload_bytes, key, DAY_SECS, limit, bytes),
+            None => Ok(()),
+        }
+    }
+
+    pub fn check_upload_bytes(&self, key: &str, limits: &KeyLimits, bytes: u64) -> Result<(), u64> {
+        match limits.upload_bytes_per_day.or_else(default_upload_bytes_per_day) {
+            Some(limit) => Self::take(&self.upload_bytes, key, DAY_SECS, limit, bytes),
+            None => Ok(()),
+        }
+    }
+
+    /// Counts bytes that already arrived, e.g. a chunk of a resumable upload
+    pub fn record_upload_bytes(&self, key: &str, bytes: u64) {
+        Self::add(&self.upload_bytes, key, DAY_SECS, bytes);
+    }
+
+    /// Takes back bytes counted for a request that was refused afterwards
+    fn refund_upload_bytes(&self, key: &str, bytes: u64) {
+        let window = Self::now() / DAY_SECS;
+        if let Some(mut counter) = self.upload_bytes.get_mut(key).filter(|counter| counter.window == window) {
+            counter.used = counter.used.saturating_sub(bytes);
+        }
+    }
+}
+
+fn env_u64(name: &str) -> Option<u64> {
+    std::env::var(name).ok().and_then(|v| v.parse().ok())
+}
+
+fn default_requests_per_minute() -> Option<u32> {
+    env_u64("KEY_REQUESTS_PER_MINUTE").map(|v| v as u32)
+}
+
+fn default_upload_bytes_per_day() -> Option<u64> {
+    env_u64("KEY_UPLOAD_MB_PER_DAY").map(|mb| mb * 1024 * 1024)
+}
+
+fn default_max_stored_bytes() -> Option<u64> {
+    env_u64("KEY_MAX_STORED_MB").map(|mb| mb * 1024 * 1024)
+}
+
+/// Bytes a request is about to upload: the declared length of a resumable upload, or the body size otherwise.
+/// Resumable uploads are only counted towards the daily volume as their chunks arrive.
+fn incoming_bytes(request: &Request<Body>) -> (u64, bool) {
+    let headers = request.headers();
+    if request.uri().path().ends_with("/upload/sessions") {
+        let declared = headers.get("X-Upload-Length").and_then(|h| h.to_str().ok()).and_then(|v| v.parse().ok()).unwrap_or(0);
+        return (declared, false);
+    }
+    if request.uri().path().ends_with("/upload") {
+        let body = headers.get(header::CONTENT_LENGTH).and_then(|h| h.to_str().ok()).and_then(|v| v.parse().ok()).unwrap_or(0);
+        return (body, true);
+    }
+    (0, false)
+}
+
+/// Applies the key's limits to a request. Nothing is counted unless every limit passes.
+fn check_limits(state: &ServerState, key: &str, data: &ModeratorKeyData, (bytes, charge_bytes): (u64, bool)) -> Result<(), u64> {
+    state.rate_limiter.request_allowed(key, &data.limits)?;
+
+    if bytes > 0 {
+        if let Some(max_stored) = data.limits.max_stored_bytes.or_else(default_max_stored_bytes) {
+            let stored = state.file_manager.key_usage(key);
+            if stored + bytes > max_stored {
+                let now = RateLimiter::now();
+                let retry_after = state.file_manager.next_expiry_for_key(key)
+                    .map(|exp| exp.saturating_sub(now).max(1))
+                    .unwrap_or(STORAGE_RETRY_SECS);
+                return Err(retry_after);
+            }
+        }
+
+        if charge_bytes {
+            state.rate_limiter.check_upload_bytes(key, &data.limits, bytes)?;
+        } else {
+            state.rate_limiter.upload_bytes_allowed(key, &data.limits, bytes)?;
+        }
+    }
+
+    // A concurrent request may have used the last request of the minute since the check above
+    if let Err(retry_after) = state.rate_limiter.check_request(key, &data.limits) {
+        if charge_bytes {
+            state.rate_limiter.refund_upload_bytes(key, bytes);
+        }
+        return Err(retry_after);
+    }
+    Ok(())
+}
+
+/// 429 answer telling the client when to try again
+pub fn too_many_requests(retry_after: u64) -> Response {
+    let mut response = StatusCode::TOO_MANY_REQUESTS.into_response();
+    response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+    response
+}
+
+/// Middleware for the submission and upload routes, answers 429 with `Retry-After` when a key is over its limits.
+/// Rotated keys are counted against their replacement. Unknown and revoked keys are passed through so the handler can reject them.
+pub async fn enforce(State(state): State<ServerState>, request: Request<Body>, next: Next) -> Response {
+    let Ok((key, data)) = crate::http_server::authorize(&state, request.headers()) else {
+        return next.run(request).await;
+    };
+
+    // Retries of a request that was already processed are answered from the idempotency cache, they cost nothing
+    let idempotency_key = request.headers().get("X-Idempotency-Key").and_then(|h| h.to_str().ok());
+    if idempotency_key.is_some_and(|idempotency_key| state.idempotency.contains(&key, idempotency_key)) {
+        return next.run(request).await;
+    }
+
+    if let Err(retry_after) = check_limits(&state, &key, &data, incoming_bytes(&request)) {
+        warn!("Key of '{}' is over its limits on {}, retry after {}s", data.server_name, request.uri().path(), retry_after);
+        return too_many_requests(retry_after);
+    }
+
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use axum::{middleware, routing::post, Router};
+    use tower::ServiceExt;
+
+    fn limited(server_name: &str, limits: KeyLimits) -> ModeratorKeyData {
+        ModeratorKeyData { limits, ..test_support::key_data(server_name) }
+    }
+
+    fn per_minute(requests: u32) -> KeyLimits {
+        KeyLimits { requests_per_minute: Some(requests), ..Default::default() }
+    }
+
+    fn router(state: &ServerState) -> Router {
+        Router::new()
+            .route("/upload", post(|| async { StatusCode::OK }))
+            .layer(middleware::from_fn_with_state(state.clone(), enforce))
+    }
+
+    async fn upload(state: &ServerState, key: &str, bytes: u64, idempotency_key: Option<&str>) -> Response {
+        let mut request = Request::post("/upload")
+            .header("X-Moderator-Key", key)
+            .header(header::CONTENT_LENGTH, bytes);
+        if let Some(idempotency_key) = idempotency_key {
+            request = request.header("X-Idempotency-Key", idempotency_key);
+        }
+        router(state).oneshot(request.body(Body::empty()).unwrap()).await.unwrap()
+    }
+
+    fn retry_after(response: &Response) -> u64 {
+        response.headers()[header::RETRY_AFTER].to_str().unwrap().parse().unwrap()
+    }
+
+    #[test]
+    fn fixed_window_counts_up_to_the_limit() {
+        let limiter = RateLimiter::default();
+        let limits = per_minute(2);
+
+        for _ in 0..5 {
+            assert_eq!(limiter.request_allowed("key", &limits), Ok(()));
+        }
+        assert_eq!(limiter.check_request("key", &limits), Ok(()));
+        assert_eq!(limiter.check_request("key", &limits), Ok(()));
+        let retry_after = limiter.check_request("key", &limits).unwrap_err();
+        assert!((1..=MINUTE_SECS).contains(&retry_after));
+        assert_eq!(limiter.check_request("other", &limits), Ok(()));
+    }
+
+    #[tokio::test]
+    async fn over_the_limit_is_answered_with_retry_after() {
+        let state = test_support::state(&[("key", limited("Server", per_minute(2)))]);
+
+        assert_eq!(upload(&state, "key", 0, None).await.status(), StatusCode::OK);
+        assert_eq!(upload(&state, "key", 0, None).await.status(), StatusCode::OK);
+        let response = upload(&state, "key", 0, None).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!((1..=MINUTE_SECS).contains(&retry_after(&response)));
+    }
+
+    #[tokio::test]
+    async fn retries_of_a_processed_request_are_free() {
+        let state = test_support::state(&[("key", limited("Server", per_minute(1)))]);
+        state.idempotency.begin("key", "retry", b"");
+        state.idempotency.complete("key", "retry", serde_json::json!({}));
+
+        assert_eq!(upload(&state, "key", 0, None).await.status(), StatusCode::OK);
+        assert_eq!(upload(&state, "key", 0, Some("retry")).await.status(), StatusCode::OK);
+        assert_eq!(upload(&state, "key", 0, None).await.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[tokio::test]
+    async fn rotated_key_shares_the_limits_of_its_replacement() {
+        let old = ModeratorKeyData { replaced_by: Some("new".to_string()), expires_at: Some(u64::MAX), ..test_support::key_data("Server") };
+        let state = test_support::state(&[("old", old), ("new", limited("Server", per_minute(2)))]);
+
+        assert_eq!(upload(&state, "new", 0, None).await.status(), StatusCode::OK);
+        assert_eq!(upload(&state, "old", 0, None).await.status(), StatusCode::OK);
+        assert_eq!(upload(&state, "old", 0, None).await.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[tokio::test]
+    async fn stored_bytes_quota_applies_to_the_replacement() {
+        let old = ModeratorKeyData { replaced_by: Some("new".to_string()), expires_at: Some(u64::MAX), ..test_support::key_data("Server") };
+        let new = limited("Server", KeyLimits { max_stored_bytes: Some(100), ..Default::default() });
+        let state = test_support::state(&[("old", old), ("new", new)]);
+
+        let path = state.file_manager.base_dir.join("stored.txt");
+        std::fs::write(&path, [0u8; 80]).unwrap();
+        state.file_manager.commit_file(uuid::Uuid::new_v4(), "new", "stored.txt", false, path, 80).unwrap();
+        assert_eq!(state.file_manager.key_usage("new"), 80);
+
+        assert_eq!(upload(&state, "old", 20, None).await.status(), StatusCode::OK);
+        let response = upload(&state, "old", 21, None).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        // The stored file never expires, so there is no better time to suggest
+        assert_eq!(retry_after(&response), STORAGE_RETRY_SECS);
+    }
+}
@@ -0,0 +1,227 @@
+use crate::file_manager::{FileManager, FileStatus};
+use crate::models::FormSubmission;
+use crate::submission_store::{StoredSubmission, SubmissionStore};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+/// What the startup reconciliation found and fixed
+#[derive(Debug, Default)]
+pub struct ReconcileReport {
+    pub missing_files: usize,        // active in the index, gone from storage, now marked expired
+    pub registered_answers: usize,   // submission JSONs in ANSWERS that were missing from the index
+    pub indexed_submissions: usize,  // of those, submissions that were missing from submissions.db too
+    pub unreadable_answers: usize,
+    pub pending_uploads: usize,      // files in TEMP_UPLOADS of upload sessions and temp uploads that can still be attached
+    pub removed_temp_files: usize,   // TEMP_UPLOADS and interrupted writes, nothing can attach them anymore
+    pub removed_blobs: usize,        // blobs no active file refers to
+    pub removed_bytes: u64,
+}
+
+impl ReconcileReport {
+    pub fn is_clean(&self) -> bool {
+        self.missing_files == 0
+            && self.registered_answers == 0
+            && self.unreadable_answers == 0
+            && self.removed_temp_files == 0
+            && self.removed_blobs == 0
+    }
+}
+
+impl fmt::Display for ReconcileReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} missing files marked expired, {} orphaned answers registered ({} added to the submission index), \
+            {} unreadable answers skipped, {} pending uploads kept, {} stale temp files and {} unreferenced blobs removed ({} bytes)",
+            self.missing_files, self.registered_answers, self.indexed_submissions, self.unreadable_answers,
+            self.pending_uploads, self.removed_temp_files, self.removed_blobs, self.removed_bytes
+        )
+    }
+}
+
+/// Compares the file index with what is actually on disk and repairs the differences.
+/// Runs once at startup, before any request can change the index.
+pub fn reconcile(file_manager: &FileManager, submissions: &SubmissionStore) -> ReconcileReport {
+    let mut report = ReconcileReport::default();
+
+    // Objects in the storage backend, listed once instead of asking for every blob
+    let stored_blobs = match file_manager.storage.list("BLOBS/") {
+        Ok(objects) => Some(objects.into_iter().map(|o| (o.key, o.size_bytes)).collect::<HashMap<String, u64>>()),
+        Err(e) => {
+            error!("Reconcile: failed to list blobs in {} storage, skipping them: {}", file_manager.storage.name(), e);
+            None
+        }
+    };
+
+    mark_missing_files(file_manager, stored_blobs.as_ref(), &mut report);
+    register_orphaned_answers(file_manager, submissions, &mut report);
+    remove_stale_temp_files(file_manager, &mut report);
+    // Reference counts must account for files marked missing before blobs are judged unreferenced
+    file_manager.rebuild_blob_refs();
+    if let Some(stored_blobs) = &stored_blobs {
+        remove_unreferenced_blobs(file_manager, stored_blobs, &mut report);
+    }
+
+    if report.is_clean() {
+        info!("Startup reconciliation: the file index matches the disk");
+    } else {
+        file_manager.save_to_disk();
+        warn!("Startup reconciliation: {}", report);
+    }
+    report
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+fn mark_missing_files(file_manager: &FileManager, stored_blobs: Option<&HashMap<String, u64>>, report: &mut ReconcileReport) {
+    let now = now_secs();
+    for mut entry in file_manager.files.iter_mut() {
+        let exists = match (&entry.content_hash, stored_blobs) {
+            (Some(hash), Some(stored_blobs)) => stored_blobs.contains_key(&FileManager::blob_key(hash)),
+            (Some(_), None) => true,
+            (None, _) => entry.path.exists(),
+        };
+        if matches!(entry.status, FileStatus::Active) && !exists {
+            warn!("Reconcile: {} ({}) is missing from storage, marking it expired", entry.id, entry.original_name);
+            entry.status = FileStatus::Expired { deleted_at: now };
+            report.missing_files += 1;
+        }
+    }
+}
+
+/// Submission JSONs are written before they are indexed, a crash in between leaves them unknown to the server
+fn register_orphaned_answers(file_manager: &FileManager, submissions: &SubmissionStore, report: &mut ReconcileReport) {
+    let indexed: HashSet<PathBuf> = file_manager.files.iter().map(|entry| entry.path.clone()).collect();
+
+    // Layout: ANSWERS/{mod_key}/{user_xuid}/{survey}_{timestamp}.json
+    let answers_dir = file_manager.base_dir.join("ANSWERS");
+    let walker = walkdir::WalkDir::new(&answers_dir).min_depth(3).max_depth(3);
+    for entry in walker.into_iter().filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if !path.is_file() {
+            continue;
+        }
+        match path.extension().and_then(|s| s.to_str()) {
+            Some("json") if !indexed.contains(path) => {}
+            Some("tmp") => {
+                remove_stale(path, report);
+                continue;
+            }
+            _ => continue,
+        }
+
+        let Some(mod_key) = path.strip_prefix(&answers_dir).ok()
+            .and_then(|rel| rel.components().next())
+            .and_then(|c| c.as_os_str().to_str())
+            .map(|s| s.to_string())
+        else {
+            continue;
+        };
+
+        let submission = match fs::read_to_string(path).map(|s| serde_json::from_str::<FormSubmission>(&s)) {
+            Ok(Ok(submission)) => submission,
+            Ok(Err(e)) => {
+                warn!("Reconcile: skipping unreadable answer {:?}: {}", path, e);
+                report.unreadable_answers += 1;
+                continue;
+            }
+            Err(e) => {
+                warn!("Reconcile: failed to read {:?}: {}", path, e);
+                report.unreadable_answers += 1;
+                continue;
+            }
+        };
+
+        // Reuse the id of an indexed submission, so its /data link matches the id known everywhere else
+        let existing_id = submissions.find_id(&mod_key, &submission).unwrap_or_else(|e| {
+            error!("Reconcile: failed to look up submission for {:?}: {}", path, e);
+            None
+        });
+        let id = existing_id.unwrap_or_else(Uuid::new_v4);
+
+        if existing_id.is_none() {
+            let record = StoredSubmission {
+                id,
+                mod_key: mod_key.clone(),
+                received_at: entry.metadata().ok()
+                    .and_then(|m| m.modified().ok())
+                    .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                    .map(|d| d.as_secs())
+                    .unwrap_or(submission.submission_timestamp),
+                file_ids: submission.files.iter()
+                    .filter_map(|(id, _)| Uuid::parse_str(id).ok())
+                    .filter(|id| file_manager.files.contains_key(id))
+                    .collect(),
+                submission,
+                survey_version: None,
+                possible_duplicates: Vec::new(),
+                bug: None,
+            };
+            match submissions.insert(&record) {
+                Ok(()) => report.indexed_submissions += 1,
+                Err(e) => error!("Reconcile: failed to add {:?} to the submission index: {}", path, e),
+            }
+        }
+
+        let filename = path.file_name().and_then(|s| s.to_str()).unwrap_or("answer.json");
+        let size_bytes = entry.metadata().map(|m| m.len()).unwrap_or(0);
+        let _ = file_manager.commit_file(id, &mod_key, filename, true, path.to_path_buf(), size_bytes);
+        info!("Reconcile: registered orphaned answer {:?} as {}", path, id);
+        report.registered_answers += 1;
+    }
+}
+
+/// Files of pending uploads are kept so clients can resume or attach them, anything else in TEMP_UPLOADS is unreachable
+fn remove_stale_temp_files(file_manager: &FileManager, report: &mut ReconcileReport) {
+    let Ok(entries) = fs::read_dir(file_manager.base_dir.join("TEMP_UPLOADS")) else { return };
+    for entry in entries.flatten() {
+        if !entry.path().is_file() {
+            continue;
+        }
+        let pending = entry.file_name().to_str()
+            .and_then(|name| Uuid::parse_str(name).ok())
+            .is_some_and(|id| file_manager.upload_sessions.contains_key(&id) || file_manager.temp_uploads.contains_key(&id));
+        if pending {
+            report.pending_uploads += 1;
+        } else {
+            remove_stale(&entry.path(), report);
+        }
+    }
+    if report.pending_uploads > 0 {
+        info!("Reconcile: kept {} pending uploads in TEMP_UPLOADS", report.pending_uploads);
+    }
+}
+
+fn remove_unreferenced_blobs(file_manager: &FileManager, stored_blobs: &HashMap<String, u64>, report: &mut ReconcileReport) {
+    let referenced: HashSet<String> = file_manager.blobs.iter().map(|blob| blob.key.clone()).collect();
+
+    for (key, size) in stored_blobs.iter().filter(|(key, _)| !referenced.contains(*key)) {
+        match file_manager.storage.delete(key) {
+            Ok(()) => {
+                warn!("Reconcile: removed unreferenced blob {}", key);
+                report.removed_blobs += 1;
+                report.removed_bytes += size;
+            }
+            Err(e) => error!("Reconcile: failed to remove unreferenced blob {}: {}", key, e),
+        }
+    }
+}
+
+fn remove_stale(path: &Path, report: &mut ReconcileReport) {
+    let size = fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+    match fs::remove_file(path) {
+        Ok(()) => {
+            info!("Reconcile: removed stale temp file {:?}", path);
+            report.removed_temp_files += 1;
+            report.removed_bytes += size;
+        }
+        Err(e) => error!("Reconcile: failed to remove stale temp file {:?}: {}", path, e),
+    }
+}
@@ -0,0 +1,120 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+const ONE_MB: u64 = 1024 * 1024;
+const ONE_DAY_SECS: u64 = 24 * 60 * 60;
+
+/// What an attachment is, inferred from the names the client gives its uploads
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FileKind {
+    Demo,  // demos_{map}_{timestamp}.zip, or any other .dem/.zip
+    Video, // screen recordings
+    Log,   // {map}_console_{timestamp}.log
+    Other,
+}
+
+impl FileKind {
+    pub fn of(file_name: &str) -> Self {
+        match extension(file_name).as_deref() {
+            Some("dem" | "zip") => Self::Demo,
+            Some("mp4" | "webm" | "mkv" | "avi" | "mov") => Self::Video,
+            Some("log" | "txt") => Self::Log,
+            _ => Self::Other,
+        }
+    }
+}
+
+fn extension(file_name: &str) -> Option<String> {
+    Path::new(file_name).extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase())
+}
+
+/// One entry of `retention.json`. Every condition that is set must match, empty lists match anything.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct RetentionRule {
+    #[serde(default)]
+    pub keys: Vec<String>,
+    #[serde(default)]
+    pub kinds: Vec<FileKind>,
+    #[serde(default)]
+    pub extensions: Vec<String>, // without the dot, case-insensitive
+    pub min_size_mb: Option<f64>, // inclusive
+    pub max_size_mb: Option<f64>, // exclusive
+    pub keep_days: Option<u64>,   // unset keeps the file until the storage limit needs the space
+}
+
+impl RetentionRule {
+    fn matches(&self, mod_key: &str, file_name: &str, size_bytes: u64) -> bool {
+        let size_mb = size_bytes as f64 / ONE_MB as f64;
+        (self.keys.is_empty() || self.keys.iter().any(|k| k == mod_key))
+            && (self.kinds.is_empty() || self.kinds.contains(&FileKind::of(file_name)))
+            && (self.extensions.is_empty() || extension(file_name).is_some_and(|ext| {
+                self.extensions.iter().any(|e| e.trim_start_matches('.').eq_ignore_ascii_case(&ext))
+            }))
+            && self.min_size_mb.is_none_or(|min| size_mb >= min)
+            && self.max_size_mb.is_none_or(|max| size_mb < max)
+    }
+}
+
+/// Retention rules and per-key reserved space, read from `RETENTION_CONFIG` (default `{BASE_DIR}/retention.json`).
+/// Without the file every attachment gets the built-in tiers.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct RetentionPolicy {
+    #[serde(default)]
+    pub rules: Vec<RetentionRule>, // the first matching rule wins
+    #[serde(default)]
+    pub reserved_mb: HashMap<String, u64>, // per mod_key, never reclaimed by smart deletion
+}
+
+impl RetentionPolicy {
+    pub fn config_path(base_dir: &Path) -> PathBuf {
+        std::env::var("RETENTION_CONFIG").map(PathBuf::from).unwrap_or_else(|_| base_dir.join("retention.json"))
+    }
+
+    /// Reads the policy, a missing file is the default policy
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let data = match fs::read_to_string(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(format!("{:?}: {}", path, e)),
+        };
+        let policy: Self = serde_json::from_str(&data).map_err(|e| format!("{:?}: {}", path, e))?;
+        info!("Loaded {} retention rules and {} space reservations from {:?}", policy.rules.len(), policy.reserved_mb.len(), path);
+        Ok(policy)
+    }
+
+    /// Warns about reservations that together leave no room for the other keys
+    pub fn check_reservations(&self, max_storage_bytes: u64) {
+        let reserved: u64 = self.reserved_mb.values().sum::<u64>() * ONE_MB;
+        if reserved > max_storage_bytes {
+            warn!("Reserved space ({} MB) exceeds MAX_STORAGE_MB ({} MB)", reserved / ONE_MB, max_storage_bytes / ONE_MB);
+        }
+    }
+
+    pub fn reserved_bytes(&self, mod_key: &str) -> u64 {
+        self.reserved_mb.get(mod_key).copied().unwrap_or(0) * ONE_MB
+    }
+
+    /// Expiration of an attachment uploaded at `uploaded_at`, from the first matching rule or the built-in tiers
+    pub fn expiration(&self, mod_key: &str, file_name: &str, size_bytes: u64, is_priority: bool, uploaded_at: u64) -> Option<u64> {
+        match self.rules.iter().find(|rule| rule.matches(mod_key, file_name, size_bytes)) {
+            Some(rule) => rule.keep_days.map(|days| uploaded_at + days * ONE_DAY_SECS),
+            None => default_expiration(is_priority, size_bytes, uploaded_at),
+        }
+    }
+}
+
+// Dynamic lifetime calculation based on file size
+pub fn default_expiration(is_priority: bool, size_bytes: u64, uploaded_at: u64) -> Option<u64> {
+    if size_bytes < ONE_MB || is_priority {
+        None // < 1MB: Keep forever (until global limit hit)
+    } else if size_bytes <= 10 * ONE_MB {
+        Some(uploaded_at + 30 * ONE_DAY_SECS) // 1-10MB: 30 days
+    } else {
+        Some(uploaded_at + 7 * ONE_DAY_SECS) // > 10MB: 7 days
+    }
+}
@@ -1,11 +1,23 @@
 use crate::models::{ModeratorKeyData, SubmissionEvent};
+use crate::dashboard::DashboardSessions;
+use crate::duplicates::DuplicateDetector;
 use crate::file_manager::FileManager;
+use crate::idempotency::IdempotencyCache;
+use crate::links::LinkSigner;
+use crate::metrics::Metrics;
+use crate::persistence::{self, Loaded};
+use crate::previews::VideoPreviews;
+use crate::rate_limit::RateLimiter;
+use crate::reconcile;
+use crate::submission_store::SubmissionStore;
+use crate::validation::SubmissionLimits;
 use dashmap::DashMap;
 use indexmap::IndexMap;
 use serde::{de::DeserializeOwned, Serialize};
 use serenity::prelude::TypeMapKey;
-use tracing::{info, warn};
-use std::{fs, io, sync::Arc};
+use tracing::{error, info, warn};
+use std::{io, path::Path, sync::Arc};
+use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::sync::broadcast;
 
 // Alias for thread-safe key store
@@ -17,6 +29,15 @@ pub struct ServerState {
     pub key_store: KeyStore,
     pub submission_sender: broadcast::Sender<SubmissionEvent>,
     pub file_manager: Arc<FileManager>,
+    pub submissions: Arc<SubmissionStore>,
+    pub idempotency: Arc<IdempotencyCache>,
+    pub rate_limiter: Arc<RateLimiter>,
+    pub metrics: Arc<Metrics>,
+    pub dashboard: Arc<DashboardSessions>,
+    pub submission_limits: Arc<SubmissionLimits>,
+    pub links: Arc<LinkSigner>,
+    pub previews: Arc<VideoPreviews>,
+    pub duplicates: Arc<DuplicateDetector>,
 }
 
 impl TypeMapKey for ServerState {
@@ -27,7 +48,8 @@ impl ServerState {
     pub fn new() -> Self {
         // Load keys from disk or start with an empty store
         let key_store = load_map_from_disk::<String, ModeratorKeyData>("keys.json").unwrap_or_else(|e| {
-            warn!("Could not load keys.json: {}. Starting empty.", e);
+            error!("Could not load keys.json: {}. Starting empty.", e);
+            persistence::quarantine(Path::new("keys.json"));
             Arc::new(DashMap::new())
         });
         info!("Loaded {} keys from disk.", key_store.len());
@@ -45,10 +67,31 @@ impl ServerState {
         let base_dir = std::env::var("BASE_DIR").unwrap_or_else(|_| ".".to_string());
         let file_manager = Arc::new(FileManager::new(max_storage_mb, base_dir));
 
+        // Open the submission index and import submissions written before it existed
+        let submissions = SubmissionStore::open(file_manager.base_dir.join("submissions.db"))
+            .expect("Failed to open submissions.db");
+        if let Err(e) = submissions.import_answers_dir(&file_manager) {
+            warn!("Failed to import existing ANSWERS into the submission index: {}", e);
+        }
+
+        // Repair what an unclean shutdown may have left behind, before requests touch the index
+        reconcile::reconcile(&file_manager, &submissions);
+
+        let links = Arc::new(LinkSigner::load(&file_manager.base_dir));
+
         Self {
             key_store,
             submission_sender: sender,
             file_manager,
+            submissions: Arc::new(submissions),
+            idempotency: Arc::new(IdempotencyCache::default()),
+            rate_limiter: Arc::new(RateLimiter::default()),
+            metrics: Arc::new(Metrics::default()),
+            dashboard: Arc::new(DashboardSessions::default()),
+            submission_limits: Arc::new(SubmissionLimits::from_env()),
+            links,
+            previews: Arc::new(VideoPreviews::from_env()),
+            duplicates: Arc::new(DuplicateDetector::from_env()),
         }
     }
 
@@ -58,17 +101,71 @@ impl ServerState {
         self.file_manager.save_to_disk();
         Ok(())
     }
+
+    pub fn save_keys_to_disk(&self) -> io::Result<()> {
+        save_map_to_disk("keys.json", &self.key_store)
+    }
+
+    /// Replaces `old_key` with `new_key`. The old key, and keys it replaced earlier, resolve to the new one until `grace_end`.
+    /// Collected submissions and files move to the new key, so stats, exports and the API keep working with it.
+    pub fn rotate_key(&self, old_key: &str, new_key: &str, new_data: ModeratorKeyData, grace_end: u64) {
+        self.key_store.insert(new_key.to_string(), new_data);
+        for mut entry in self.key_store.iter_mut() {
+            if entry.value().replaced_by.as_deref() == Some(old_key) {
+                entry.value_mut().replaced_by = Some(new_key.to_string());
+            }
+        }
+        if let Some(mut old) = self.key_store.get_mut(old_key) {
+            old.replaced_by = Some(new_key.to_string());
+            old.expires_at = Some(grace_end);
+        }
+
+        match self.submissions.reassign_key(old_key, new_key) {
+            Ok(moved) => info!("Moved {} submissions to the rotated key", moved),
+            Err(e) => error!("Failed to move submissions to the rotated key: {}", e),
+        }
+        self.file_manager.reassign_key(old_key, new_key);
+    }
+
+    /// Revokes `key` and the rotated keys still resolving to it, returns the keys that were not revoked before
+    pub fn revoke_key(&self, key: &str, now: u64) -> Vec<String> {
+        let mut revoked = Vec::new();
+        for mut entry in self.key_store.iter_mut() {
+            let is_target = entry.key() == key || entry.value().replaced_by.as_deref() == Some(key);
+            if is_target && entry.value().revoked_at.is_none() {
+                entry.value_mut().revoked_at = Some(now);
+                revoked.push(entry.key().clone());
+            }
+        }
+        revoked
+    }
+
+    /// Returns the current (not revoked, not rotated out) key bound to a Discord channel or forum
+    pub fn key_for_channel(&self, channel_id: &str) -> Option<(String, ModeratorKeyData)> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        self.key_store.iter()
+            .find(|entry| {
+                let data = entry.value();
+                (data.channel_id == channel_id || data.forum_channel_id.as_deref() == Some(channel_id)) && data.is_current(now)
+            })
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+    }
 }
 
-// Generic function to load any DashMap from a JSON file
-fn load_map_from_disk<K, V>(path: &str) -> Result<Arc<DashMap<K, V>>, io::Error>
+// Generic function to load any DashMap from a JSON file, or its backup
+fn load_map_from_disk<K, V>(path: &str) -> Result<Arc<DashMap<K, V>>, String>
 where
     K: Eq + std::hash::Hash + Ord + DeserializeOwned + Clone,
     V: DeserializeOwned + Clone,
 {
-    let data = fs::read_to_string(path)?;
-    let hash_map: IndexMap<K, V> = serde_json::from_str(&data)
-        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let hash_map: IndexMap<K, V> = match persistence::load_json(Path::new(path))? {
+        Loaded::Primary(map) => map,
+        Loaded::Backup(map) => {
+            warn!("{} was unreadable, restored it from {}.bak", path, path);
+            map
+        }
+        Loaded::Missing => IndexMap::new(),
+    };
 
     let dashmap = DashMap::new();
     for (k, v) in hash_map {
@@ -85,5 +182,78 @@ where
 {
     let hash_map: IndexMap<K, V> = map.iter().map(|item| (item.key().clone(), item.value().clone())).collect();
     let json_data = serde_json::to_string_pretty(&hash_map)?;
-    fs::write(path, json_data)
+    persistence::write_atomic_with_backup(Path::new(path), json_data.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http_server::resolve_key;
+    use crate::test_support;
+    use axum::http::StatusCode;
+
+    fn now() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+    }
+
+    fn resolved(state: &ServerState, key: &str) -> Result<String, StatusCode> {
+        resolve_key(state, key).map(|(key, _)| key)
+    }
+
+    fn rotate(state: &ServerState, old_key: &str, new_key: &str, grace_end: u64) {
+        let new_data = ModeratorKeyData { label: Some(new_key.to_string()), ..test_support::key_data("Server") };
+        state.rotate_key(old_key, new_key, new_data, grace_end);
+    }
+
+    #[test]
+    fn rotated_key_resolves_to_its_replacement_during_the_grace_period() {
+        let state = test_support::state(&[("old", test_support::key_data("Server"))]);
+        let path = state.file_manager.base_dir.join("a.txt");
+        std::fs::write(&path, b"data").unwrap();
+        state.file_manager.commit_file(uuid::Uuid::new_v4(), "old", "a.txt", false, path, 4).unwrap();
+
+        rotate(&state, "old", "new", now() + 3600);
+
+        assert_eq!(resolved(&state, "old"), Ok("new".to_string()));
+        assert_eq!(resolve_key(&state, "old").unwrap().1.label.as_deref(), Some("new"));
+        assert_eq!(resolved(&state, "new"), Ok("new".to_string()));
+        assert_eq!(state.key_for_channel("3").map(|(key, _)| key), Some("new".to_string()));
+        assert_eq!((state.file_manager.key_usage("old"), state.file_manager.key_usage("new")), (0, 4));
+    }
+
+    #[test]
+    fn rotated_key_is_gone_after_the_grace_period() {
+        let state = test_support::state(&[("old", test_support::key_data("Server"))]);
+        rotate(&state, "old", "new", now());
+
+        assert_eq!(resolved(&state, "old"), Err(StatusCode::GONE));
+        assert_eq!(resolved(&state, "new"), Ok("new".to_string()));
+    }
+
+    #[test]
+    fn earlier_keys_follow_a_second_rotation() {
+        let state = test_support::state(&[("first", test_support::key_data("Server"))]);
+        rotate(&state, "first", "second", now() + 3600);
+        rotate(&state, "second", "third", now() + 3600);
+
+        assert_eq!(resolved(&state, "first"), Ok("third".to_string()));
+        assert_eq!(resolved(&state, "second"), Ok("third".to_string()));
+    }
+
+    #[test]
+    fn revoked_keys_are_gone() {
+        let state = test_support::state(&[("old", test_support::key_data("Server")), ("other", test_support::key_data("Other"))]);
+        rotate(&state, "old", "new", now() + 3600);
+
+        // Revoking the replacement also revokes the rotated key that resolves to it
+        let mut revoked = state.revoke_key("new", now());
+        revoked.sort();
+        assert_eq!(revoked, ["new", "old"]);
+        assert_eq!(resolved(&state, "new"), Err(StatusCode::GONE));
+        assert_eq!(resolved(&state, "old"), Err(StatusCode::GONE));
+        assert!(state.revoke_key("new", now()).is_empty());
+
+        assert_eq!(resolved(&state, "other"), Ok("other".to_string()));
+        assert_eq!(resolved(&state, "unknown"), Err(StatusCode::FORBIDDEN));
+    }
 }
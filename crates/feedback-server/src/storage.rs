@@ -0,0 +1,131 @@
+use crate::s3_storage::S3Storage;
+use mime_guess::Mime;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::info;
+
+/// How a stored object is handed to a client
+pub enum Served {
+    File { path: PathBuf, content_type: Mime }, // streamed by the server from local disk
+    Redirect(String),                           // short-lived URL the client downloads from directly
+}
+
+/// Content type of a download, from its name. Blobs are stored without an extension.
+pub fn content_type(download_name: &str) -> Mime {
+    mime_guess::from_path(download_name).first_or_octet_stream()
+}
+
+/// An object found by `StorageBackend::list`
+#[derive(Debug, Clone)]
+pub struct StoredObject {
+    pub key: String,
+    pub size_bytes: u64,
+}
+
+/// Where committed attachments (`BLOBS/...`) and finished exports (`EXPORTS/...`) are kept.
+/// Keys are `/`-separated paths relative to the storage root. Temp uploads, submission JSONs and
+/// the state files always stay on local disk, objects only enter the backend once they are complete.
+pub trait StorageBackend: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Stores the finished local file under `key` and consumes it. Returns the stored size.
+    fn put_file(&self, key: &str, local_path: &Path) -> Result<u64, String>;
+
+    fn exists(&self, key: &str) -> bool;
+
+    /// Deleting a missing object is not an error
+    fn delete(&self, key: &str) -> Result<(), String>;
+
+    fn open(&self, key: &str) -> Result<Box<dyn Read + Send>, String>;
+
+    /// All objects whose key starts with `prefix`
+    fn list(&self, prefix: &str) -> Result<Vec<StoredObject>, String>;
+
+    /// How to deliver the object, downloaded by the client as `download_name`
+    fn serve(&self, key: &str, download_name: &str) -> Result<Served, String>;
+
+    /// Human-readable location recorded in the file index
+    fn location(&self, key: &str) -> PathBuf;
+}
+
+/// Selects the backend with `STORAGE_BACKEND` (`local` or `s3`, default `local`)
+pub fn from_env(base_dir: &Path) -> Arc<dyn StorageBackend> {
+    let backend: Arc<dyn StorageBackend> = match std::env::var("STORAGE_BACKEND").unwrap_or_default().to_ascii_lowercase().as_str() {
+        "s3" => Arc::new(S3Storage::from_env().expect("Invalid S3 storage configuration")),
+        "" | "local" => Arc::new(LocalStorage::new(base_dir)),
+        other => panic!("Unknown STORAGE_BACKEND '{}', expected 'local' or 's3'", other),
+    };
+    info!("Storage backend: {}", backend.name());
+    backend
+}
+
+/// Objects as plain files under `base_dir`, the layout the server has always used
+pub struct LocalStorage {
+    base_dir: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(base_dir: &Path) -> Self {
+        Self { base_dir: base_dir.to_path_buf() }
+    }
+}
+
+impl StorageBackend for LocalStorage {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    fn put_file(&self, key: &str, local_path: &Path) -> Result<u64, String> {
+        let target = self.location(key);
+        if target != local_path {
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+            }
+            fs::rename(local_path, &target).map_err(|e| e.to_string())?;
+        }
+        fs::metadata(&target).map(|m| m.len()).map_err(|e| e.to_string())
+    }
+
+    fn exists(&self, key: &str) -> bool {
+        self.location(key).exists()
+    }
+
+    fn delete(&self, key: &str) -> Result<(), String> {
+        match fs::remove_file(self.location(key)) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    fn open(&self, key: &str) -> Result<Box<dyn Read + Send>, String> {
+        let file = fs::File::open(self.location(key)).map_err(|e| e.to_string())?;
+        Ok(Box::new(file))
+    }
+
+    fn list(&self, prefix: &str) -> Result<Vec<StoredObject>, String> {
+        let mut objects = Vec::new();
+        for entry in walkdir::WalkDir::new(self.location(prefix)).into_iter().filter_map(|e| e.ok()) {
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let Ok(relative) = entry.path().strip_prefix(&self.base_dir) else { continue };
+            let key = relative.components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            objects.push(StoredObject { key, size_bytes: entry.metadata().map(|m| m.len()).unwrap_or(0) });
+        }
+        Ok(objects)
+    }
+
+    fn serve(&self, key: &str, download_name: &str) -> Result<Served, String> {
+        Ok(Served::File { path: self.location(key), content_type: content_type(download_name) })
+    }
+
+    fn location(&self, key: &str) -> PathBuf {
+        self.base_dir.join(key)
+    }
+}
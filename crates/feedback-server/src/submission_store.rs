@@ -0,0 +1,822 @@
+use crate::bug_status::{self, BugState, BugStatus};
+use crate::duplicates::DuplicateMatch;
+use crate::file_manager::FileManager;
+use crate::models::FormSubmission;
+use crate::surveys::{StoredDefinition, SurveyDefinition};
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS submissions (
+        id                   TEXT PRIMARY KEY,
+        mod_key              TEXT NOT NULL,
+        survey_id            TEXT NOT NULL,
+        map_name             TEXT NOT NULL,
+        user_name            TEXT NOT NULL,
+        user_xuid            TEXT NOT NULL,
+        game_timestamp       REAL NOT NULL,
+        submission_timestamp INTEGER NOT NULL,
+        received_at          INTEGER NOT NULL,
+        submission           TEXT NOT NULL, -- full FormSubmission JSON, answers included
+        survey_version       INTEGER        -- version of the survey definition answered, if registered
+    );
+    CREATE INDEX IF NOT EXISTS idx_submissions_survey ON submissions (mod_key, survey_id, submission_timestamp);
+    CREATE INDEX IF NOT EXISTS idx_submissions_map ON submissions (mod_key, map_name);
+    CREATE INDEX IF NOT EXISTS idx_submissions_user ON submissions (mod_key, user_xuid);
+
+    CREATE TABLE IF NOT EXISTS submission_files (
+        submission_id TEXT NOT NULL REFERENCES submissions (id) ON DELETE CASCADE,
+        file_id       TEXT NOT NULL,
+        PRIMARY KEY (submission_id, file_id)
+    );
+
+    CREATE TABLE IF NOT EXISTS submission_duplicates (
+        submission_id TEXT NOT NULL REFERENCES submissions (id) ON DELETE CASCADE,
+        duplicate_of  TEXT NOT NULL, -- an earlier submission the report likely duplicates
+        score         REAL NOT NULL,
+        distance      REAL NOT NULL,
+        PRIMARY KEY (submission_id, duplicate_of)
+    );
+
+    CREATE TABLE IF NOT EXISTS bug_reports (
+        submission_id TEXT PRIMARY KEY REFERENCES submissions (id) ON DELETE CASCADE,
+        status        TEXT NOT NULL DEFAULT 'open', -- bug reports without a row are open
+        assignee_id   TEXT,
+        assignee_name TEXT,
+        updated_by    TEXT,
+        updated_at    INTEGER,
+        channel_id    TEXT,         -- Discord notification of the report
+        message_id    TEXT
+    );
+
+    CREATE TABLE IF NOT EXISTS webhook_deliveries (
+        id            INTEGER PRIMARY KEY AUTOINCREMENT,
+        mod_key       TEXT NOT NULL,
+        webhook_id    TEXT NOT NULL,
+        submission_id TEXT NOT NULL,
+        attempt       INTEGER NOT NULL,
+        status_code   INTEGER,          -- NULL when no response was received
+        error         TEXT,
+        duration_ms   INTEGER NOT NULL,
+        attempted_at  INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_key ON webhook_deliveries (mod_key, attempted_at);
+
+    CREATE TABLE IF NOT EXISTS survey_definitions (
+        mod_key       TEXT NOT NULL,
+        survey_id     TEXT NOT NULL,
+        version       INTEGER NOT NULL,
+        content_hash  TEXT NOT NULL,
+        definition    TEXT NOT NULL, -- FormConfig JSON exactly as sent by the client
+        registered_at INTEGER NOT NULL,
+        PRIMARY KEY (mod_key, survey_id, version),
+        UNIQUE (mod_key, survey_id, content_hash)
+    );
+
+    CREATE TABLE IF NOT EXISTS store_meta (
+        key   TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+";
+
+const SELECT_SUBMISSIONS: &str = "SELECT id, mod_key, received_at, submission, survey_version FROM submissions";
+
+const ANSWERS_IMPORTED_KEY: &str = "answers_imported_at";
+
+/// A submission as recorded in the index
+#[derive(Debug, Clone)]
+pub struct StoredSubmission {
+    pub id: Uuid,
+    pub mod_key: String,
+    pub received_at: u64,
+    pub submission: FormSubmission,
+    pub file_ids: Vec<Uuid>,
+    pub survey_version: Option<u32>, // resolved from submission.survey_hash when stored
+    pub possible_duplicates: Vec<DuplicateMatch>, // recorded separately with `record_duplicates`
+    pub bug: Option<BugState>, // set for bug reports when loaded from the index
+}
+
+/// One attempt to deliver a submission event to a webhook
+#[derive(Debug, Clone)]
+pub struct WebhookDelivery {
+    pub webhook_id: Uuid,
+    pub submission_id: Uuid,
+    pub attempt: u32,
+    pub status_code: Option<u16>,
+    pub error: Option<String>,
+    pub duration_ms: u64,
+    pub attempted_at: u64,
+}
+
+/// Totals of the submissions for one map
+#[derive(Debug, Clone)]
+pub struct MapSummary {
+    pub map_name: String,
+    pub submissions: u64,
+    pub testers: u64,
+    pub attachments: u64,
+    pub last_submission: u64,
+}
+
+/// Distinct values a key's submissions can be filtered by
+#[derive(Debug, Clone, Default)]
+pub struct FilterOptions {
+    pub survey_ids: Vec<String>,
+    pub map_names: Vec<String>,
+    pub testers: Vec<(String, String)>, // (user_xuid, latest user_name)
+}
+
+/// Optional filters applied when querying submissions of a single key
+#[derive(Debug, Clone, Default)]
+pub struct SubmissionFilter {
+    pub survey_id: Option<String>,
+    pub map_name: Option<String>,
+    pub user_xuid: Option<String>,
+    pub from_timestamp: Option<u64>,
+    pub to_timestamp: Option<u64>,
+    pub after_seq: Option<i64>, // only submissions indexed after this `latest_seq`
+}
+
+/// Persistent SQLite index of every submission received by the server.
+/// The JSON files in ANSWERS stay on disk, but all reads go through here.
+pub struct SubmissionStore {
+    conn: Mutex<Connection>,
+}
+
+impl SubmissionStore {
+    pub fn open(path: impl AsRef<Path>) -> rusqlite::Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "foreign_keys", "ON")?;
+        conn.execute_batch(SCHEMA)?;
+        Self::migrate(&conn)?;
+
+        Ok(Self { conn: Mutex::new(conn) })
+    }
+
+    /// Adds columns introduced after the first release to existing databases
+    fn migrate(conn: &Connection) -> rusqlite::Result<()> {
+        if conn.prepare("SELECT survey_version FROM submissions LIMIT 0").is_err() {
+            info!("Adding survey_version to the submission index");
+            conn.execute_batch("ALTER TABLE submissions ADD COLUMN survey_version INTEGER")?;
+        }
+        Ok(())
+    }
+
+    pub fn insert(&self, record: &StoredSubmission) -> rusqlite::Result<()> {
+        let submission_json = serde_json::to_string(&record.submission)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT OR REPLACE INTO submissions
+                (id, mod_key, survey_id, map_name, user_name, user_xuid, game_timestamp, submission_timestamp, received_at, submission, survey_version)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10,
+                (SELECT version FROM survey_definitions WHERE mod_key = ?2 AND survey_id = ?3 AND content_hash = ?11))",
+            params![
+                record.id.to_string(),
+                record.mod_key,
+                record.submission.survey_id,
+                record.submission.map_name,
+                record.submission.user_name,
+                record.submission.user_xuid,
+                record.submission.game_timestamp,
+                record.submission.submission_timestamp as i64,
+                record.received_at as i64,
+                submission_json,
+                record.submission.survey_hash,
+            ],
+        )?;
+        for file_id in &record.file_ids {
+            tx.execute(
+                "INSERT OR IGNORE INTO submission_files (submission_id, file_id) VALUES (?1, ?2)",
+                params![record.id.to_string(), file_id.to_string()],
+            )?;
+        }
+        tx.commit()
+    }
+
+    /// Removes file links of a submission, for attachments that were indexed but could not be committed
+    pub fn detach_files(&self, submission_id: &Uuid, file_ids: &[Uuid]) -> rusqlite::Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        for file_id in file_ids {
+            tx.execute(
+                "DELETE FROM submission_files WHERE submission_id = ?1 AND file_id = ?2",
+                params![submission_id.to_string(), file_id.to_string()],
+            )?;
+        }
+        tx.commit()
+    }
+
+    /// Links a bug report to the earlier reports it likely duplicates
+    pub fn record_duplicates(&self, submission_id: &Uuid, duplicates: &[DuplicateMatch]) -> rusqlite::Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        for duplicate in duplicates {
+            tx.execute(
+                "INSERT OR REPLACE INTO submission_duplicates (submission_id, duplicate_of, score, distance) VALUES (?1, ?2, ?3, ?4)",
+                params![submission_id.to_string(), duplicate.submission_id.to_string(), duplicate.score, duplicate.distance],
+            )?;
+        }
+        tx.commit()
+    }
+
+    /// Changes the status of a bug report of `mod_key`, returns false when the key has no such submission
+    pub fn set_bug_status(&self, mod_key: &str, id: &Uuid, status: BugStatus, updated_by: &str) -> rusqlite::Result<bool> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let changed = self.conn.lock().unwrap().execute(
+            "INSERT INTO bug_reports (submission_id, status, updated_by, updated_at)
+             SELECT id, ?3, ?4, ?5 FROM submissions WHERE mod_key = ?1 AND id = ?2
+             ON CONFLICT (submission_id) DO UPDATE SET
+                status = excluded.status, updated_by = excluded.updated_by, updated_at = excluded.updated_at",
+            params![mod_key, id.to_string(), status.as_str(), updated_by, now as i64],
+        )?;
+        Ok(changed > 0)
+    }
+
+    /// Assigns a bug report of `mod_key` to a Discord user `(id, name)`, or unassigns it
+    pub fn set_bug_assignee(&self, mod_key: &str, id: &Uuid, assignee: Option<(&str, &str)>, updated_by: &str) -> rusqlite::Result<bool> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let (assignee_id, assignee_name) = assignee.unzip();
+        let changed = self.conn.lock().unwrap().execute(
+            "INSERT INTO bug_reports (submission_id, assignee_id, assignee_name, updated_by, updated_at)
+             SELECT id, ?3, ?4, ?5, ?6 FROM submissions WHERE mod_key = ?1 AND id = ?2
+             ON CONFLICT (submission_id) DO UPDATE SET
+                assignee_id = excluded.assignee_id, assignee_name = excluded.assignee_name,
+                updated_by = excluded.updated_by, updated_at = excluded.updated_at",
+            params![mod_key, id.to_string(), assignee_id, assignee_name, updated_by, now as i64],
+        )?;
+        Ok(changed > 0)
+    }
+
+    /// Remembers the Discord message a bug report was posted as, so `/open_bugs` can link to it
+    pub fn set_bug_message(&self, id: &Uuid, channel_id: &str, message_id: &str) -> rusqlite::Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO bug_reports (submission_id, channel_id, message_id) VALUES (?1, ?2, ?3)
+             ON CONFLICT (submission_id) DO UPDATE SET channel_id = excluded.channel_id, message_id = excluded.message_id",
+            params![id.to_string(), channel_id, message_id],
+        )?;
+        Ok(())
+    }
+
+    /// Returns the newest open or triaged bug reports of `mod_key`, optionally of one map, with the total number of them
+    pub fn open_bugs(&self, mod_key: &str, map_name: Option<&str>, limit: u32) -> rusqlite::Result<(u64, Vec<StoredSubmission>)> {
+        // Submissions are bug reports when they carry a player position, see bug_status::is_bug_report
+        let where_sql = "LEFT JOIN bug_reports ON submission_id = id
+            WHERE mod_key = ? AND json_extract(submission, '$.\"Player Position\"') IS NOT NULL
+              AND COALESCE(status, 'open') IN ('open', 'triaged') AND (? IS NULL OR map_name = ?)";
+        let mut values: Vec<rusqlite::types::Value> = vec![
+            mod_key.to_string().into(),
+            map_name.map(|m| m.to_string()).into(),
+            map_name.map(|m| m.to_string()).into(),
+        ];
+
+        let conn = self.conn.lock().unwrap();
+        let total: i64 = conn.query_row(
+            &format!("SELECT COUNT(*) FROM submissions {}", where_sql),
+            params_from_iter(values.iter()),
+            |row| row.get(0),
+        )?;
+
+        let sql = format!("{} {} ORDER BY submission_timestamp DESC, id LIMIT ?", SELECT_SUBMISSIONS, where_sql);
+        values.push((limit as i64).into());
+        let records = Self::load_records(&conn, &sql, values)?;
+
+        Ok((total as u64, records))
+    }
+
+    /// Returns all submissions of `mod_key` matching the filter, oldest first
+    pub fn query(&self, mod_key: &str, filter: &SubmissionFilter) -> rusqlite::Result<Vec<StoredSubmission>> {
+        let (where_sql, values) = Self::where_clause(mod_key, filter);
+        let sql = format!("{} {} ORDER BY submission_timestamp, id", SELECT_SUBMISSIONS, where_sql);
+
+        let conn = self.conn.lock().unwrap();
+        Self::load_records(&conn, &sql, values)
+    }
+
+    /// Returns one page of matching submissions, newest first, together with the total number of matches
+    pub fn query_page(
+        &self,
+        mod_key: &str,
+        filter: &SubmissionFilter,
+        limit: u32,
+        offset: u32,
+    ) -> rusqlite::Result<(u64, Vec<StoredSubmission>)> {
+        let (where_sql, mut values) = Self::where_clause(mod_key, filter);
+
+        let conn = self.conn.lock().unwrap();
+        let total: i64 = conn.query_row(
+            &format!("SELECT COUNT(*) FROM submissions {}", where_sql),
+            params_from_iter(values.iter()),
+            |row| row.get(0),
+        )?;
+
+        let sql = format!("{} {} ORDER BY submission_timestamp DESC, id LIMIT ? OFFSET ?", SELECT_SUBMISSIONS, where_sql);
+        values.push((limit as i64).into());
+        values.push((offset as i64).into());
+        let records = Self::load_records(&conn, &sql, values)?;
+
+        Ok((total as u64, records))
+    }
+
+    /// Position of the newest submission in the index. Rows are numbered in insertion order and never deleted,
+    /// so a later `SubmissionFilter::after_seq` with this value matches exactly what was indexed since.
+    pub fn latest_seq(&self) -> rusqlite::Result<i64> {
+        self.conn.lock().unwrap().query_row("SELECT COALESCE(MAX(rowid), 0) FROM submissions", [], |row| row.get(0))
+    }
+
+    /// Looks up a single submission, only if it belongs to `mod_key`
+    pub fn get(&self, mod_key: &str, id: &Uuid) -> rusqlite::Result<Option<StoredSubmission>> {
+        let sql = format!("{} WHERE mod_key = ? AND id = ?", SELECT_SUBMISSIONS);
+        let values = vec![mod_key.to_string().into(), id.to_string().into()];
+
+        let conn = self.conn.lock().unwrap();
+        Ok(Self::load_records(&conn, &sql, values)?.pop())
+    }
+
+    /// Finds a stored submission by the fields that identify it in the ANSWERS directory layout
+    pub fn find_id(&self, mod_key: &str, submission: &FormSubmission) -> rusqlite::Result<Option<Uuid>> {
+        let id: Option<String> = self.conn.lock().unwrap().query_row(
+            "SELECT id FROM submissions
+             WHERE mod_key = ?1 AND survey_id = ?2 AND user_xuid = ?3 AND submission_timestamp = ?4
+             LIMIT 1",
+            params![mod_key, submission.survey_id, submission.user_xuid, submission.submission_timestamp as i64],
+            |row| row.get(0),
+        ).optional()?;
+        Ok(id.and_then(|id| Uuid::parse_str(&id).ok()))
+    }
+
+    /// Per-map totals of the matching submissions, most reported maps first
+    pub fn map_summaries(&self, mod_key: &str, filter: &SubmissionFilter) -> rusqlite::Result<Vec<MapSummary>> {
+        let (where_sql, values) = Self::where_clause(mod_key, filter);
+        let sql = format!(
+            "SELECT map_name, COUNT(DISTINCT id), COUNT(DISTINCT user_xuid), COUNT(file_id), MAX(submission_timestamp)
+             FROM submissions LEFT JOIN submission_files ON submission_id = id
+             {} GROUP BY map_name ORDER BY 2 DESC, map_name",
+            where_sql
+        );
+
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt.query_map(params_from_iter(values), |row| {
+            Ok(MapSummary {
+                map_name: row.get(0)?,
+                submissions: row.get::<_, i64>(1)? as u64,
+                testers: row.get::<_, i64>(2)? as u64,
+                attachments: row.get::<_, i64>(3)? as u64,
+                last_submission: row.get::<_, i64>(4)? as u64,
+            })
+        })?;
+        rows.collect()
+    }
+
+    pub fn filter_options(&self, mod_key: &str) -> rusqlite::Result<FilterOptions> {
+        let conn = self.conn.lock().unwrap();
+        let distinct = |column: &str| -> rusqlite::Result<Vec<String>> {
+            let sql = format!("SELECT DISTINCT {0} FROM submissions WHERE mod_key = ?1 ORDER BY {0}", column);
+            let mut stmt = conn.prepare(&sql)?;
+            let rows = stmt.query_map(params![mod_key], |row| row.get(0))?;
+            rows.collect()
+        };
+        let survey_ids = distinct("survey_id")?;
+        let map_names = distinct("map_name")?;
+
+        // With a bare MAX(), SQLite takes user_name from the tester's latest submission
+        let mut stmt = conn.prepare(
+            "SELECT user_xuid, user_name, MAX(submission_timestamp) FROM submissions WHERE mod_key = ?1
+             GROUP BY user_xuid ORDER BY user_name COLLATE NOCASE",
+        )?;
+        let testers = stmt.query_map(params![mod_key], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<Vec<(String, String)>>>()?;
+
+        Ok(FilterOptions { survey_ids, map_names, testers })
+    }
+
+    /// Stores a survey definition unless the same content is already registered for the survey.
+    /// Returns the definition's version and whether it was newly created.
+    pub fn register_definition(
+        &self,
+        mod_key: &str,
+        survey_id: &str,
+        content_hash: &str,
+        definition_json: &str,
+    ) -> rusqlite::Result<(u32, bool)> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        let existing: Option<u32> = tx.query_row(
+            "SELECT version FROM survey_definitions WHERE mod_key = ?1 AND survey_id = ?2 AND content_hash = ?3",
+            params![mod_key, survey_id, content_hash],
+            |row| row.get(0),
+        ).optional()?;
+        if let Some(version) = existing {
+            return Ok((version, false));
+        }
+
+        let version: u32 = tx.query_row(
+            "SELECT COALESCE(MAX(version), 0) + 1 FROM survey_definitions WHERE mod_key = ?1 AND survey_id = ?2",
+            params![mod_key, survey_id],
+            |row| row.get(0),
+        )?;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        tx.execute(
+            "INSERT INTO survey_definitions (mod_key, survey_id, version, content_hash, definition, registered_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![mod_key, survey_id, version, content_hash, definition_json, now as i64],
+        )?;
+
+        // Submissions that arrived before their definition was registered
+        tx.execute(
+            "UPDATE submissions SET survey_version = ?1
+             WHERE mod_key = ?2 AND survey_id = ?3 AND survey_version IS NULL
+               AND json_extract(submission, '$.survey_hash') = ?4",
+            params![version, mod_key, survey_id, content_hash],
+        )?;
+        tx.commit()?;
+
+        Ok((version, true))
+    }
+
+    /// Returns the registered definitions of `mod_key`, optionally of a single survey, oldest version first
+    pub fn definitions(&self, mod_key: &str, survey_id: Option<&str>) -> rusqlite::Result<Vec<StoredDefinition>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT survey_id, version, content_hash, registered_at, definition FROM survey_definitions
+             WHERE mod_key = ?1 AND (?2 IS NULL OR survey_id = ?2)
+             ORDER BY survey_id, version",
+        )?;
+        let rows = stmt.query_map(params![mod_key, survey_id], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, u32>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, i64>(3)?,
+                row.get::<_, String>(4)?,
+            ))
+        })?;
+
+        let mut definitions = Vec::new();
+        for row in rows {
+            let (survey_id, version, content_hash, registered_at, definition_json) = row?;
+            match serde_json::from_str::<SurveyDefinition>(&definition_json) {
+                Ok(definition) => definitions.push(StoredDefinition {
+                    survey_id,
+                    version,
+                    content_hash,
+                    registered_at: registered_at as u64,
+                    definition,
+                }),
+                Err(e) => warn!("Skipping unreadable definition {} v{}: {}", survey_id, version, e),
+            }
+        }
+        Ok(definitions)
+    }
+
+    /// Moves all submissions of `old_key` to `new_key`, returns how many were moved
+    pub fn reassign_key(&self, old_key: &str, new_key: &str) -> rusqlite::Result<usize> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let moved = tx.execute("UPDATE submissions SET mod_key = ?1 WHERE mod_key = ?2", params![new_key, old_key])?;
+        tx.execute("UPDATE webhook_deliveries SET mod_key = ?1 WHERE mod_key = ?2", params![new_key, old_key])?;
+        tx.execute("UPDATE OR IGNORE survey_definitions SET mod_key = ?1 WHERE mod_key = ?2", params![new_key, old_key])?;
+        tx.commit()?;
+        Ok(moved)
+    }
+
+    pub fn log_delivery(&self, mod_key: &str, delivery: &WebhookDelivery) -> rusqlite::Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO webhook_deliveries
+                (mod_key, webhook_id, submission_id, attempt, status_code, error, duration_ms, attempted_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                mod_key,
+                delivery.webhook_id.to_string(),
+                delivery.submission_id.to_string(),
+                delivery.attempt,
+                delivery.status_code,
+                delivery.error,
+                delivery.duration_ms as i64,
+                delivery.attempted_at as i64,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Returns the latest delivery attempts of the key's webhooks, newest first
+    pub fn recent_deliveries(&self, mod_key: &str, limit: u32) -> rusqlite::Result<Vec<WebhookDelivery>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT webhook_id, submission_id, attempt, status_code, error, duration_ms, attempted_at
+             FROM webhook_deliveries WHERE mod_key = ?1 ORDER BY id DESC LIMIT ?2",
+        )?;
+        let rows = stmt.query_map(params![mod_key, limit], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                WebhookDelivery {
+                    webhook_id: Uuid::nil(),
+                    submission_id: Uuid::nil(),
+                    attempt: row.get(2)?,
+                    status_code: row.get(3)?,
+                    error: row.get(4)?,
+                    duration_ms: row.get::<_, i64>(5)? as u64,
+                    attempted_at: row.get::<_, i64>(6)? as u64,
+                },
+            ))
+        })?;
+
+        let mut deliveries = Vec::new();
+        for row in rows {
+            let (webhook_id, submission_id, mut delivery) = row?;
+            delivery.webhook_id = Uuid::parse_str(&webhook_id).unwrap_or_default();
+            delivery.submission_id = Uuid::parse_str(&submission_id).unwrap_or_default();
+            deliveries.push(delivery);
+        }
+        Ok(deliveries)
+    }
+
+    /// Deletes delivery log entries older than `before`
+    pub fn prune_deliveries(&self, before: u64) -> rusqlite::Result<usize> {
+        self.conn.lock().unwrap().execute(
+            "DELETE FROM webhook_deliveries WHERE attempted_at < ?1",
+            params![before as i64],
+        )
+    }
+
+    fn where_clause(mod_key: &str, filter: &SubmissionFilter) -> (String, Vec<rusqlite::types::Value>) {
+        let mut sql = String::from("WHERE mod_key = ?");
+        let mut values: Vec<rusqlite::types::Value> = vec![mod_key.to_string().into()];
+
+        if let Some(survey_id) = &filter.survey_id {
+            sql.push_str(" AND survey_id = ?");
+            values.push(survey_id.clone().into());
+        }
+        if let Some(map_name) = &filter.map_name {
+            sql.push_str(" AND map_name = ?");
+            values.push(map_name.clone().into());
+        }
+        if let Some(user_xuid) = &filter.user_xuid {
+            sql.push_str(" AND user_xuid = ?");
+            values.push(user_xuid.clone().into());
+        }
+        if let Some(from) = filter.from_timestamp {
+            sql.push_str(" AND submission_timestamp >= ?");
+            values.push((from as i64).into());
+        }
+        if let Some(to) = filter.to_timestamp {
+            sql.push_str(" AND submission_timestamp <= ?");
+            values.push((to as i64).into());
+        }
+        if let Some(seq) = filter.after_seq {
+            sql.push_str(" AND rowid > ?");
+            values.push(seq.into());
+        }
+
+        (sql, values)
+    }
+
+    fn load_records(
+        conn: &Connection,
+        sql: &str,
+        values: Vec<rusqlite::types::Value>,
+    ) -> rusqlite::Result<Vec<StoredSubmission>> {
+        let mut stmt = conn.prepare(sql)?;
+        let rows = stmt.query_map(params_from_iter(values), |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i64>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, Option<u32>>(4)?,
+            ))
+        })?;
+
+        let mut records = Vec::new();
+        for row in rows {
+            let (id, mod_key, received_at, submission_json, survey_version) = row?;
+            let Ok(id) = Uuid::parse_str(&id) else { continue };
+            let submission = match serde_json::from_str::<FormSubmission>(&submission_json) {
+                Ok(s) => s,
+                Err(e) => {
+                    warn!("Skipping unreadable submission {} in index: {}", id, e);
+                    continue;
+                }
+            };
+            records.push(StoredSubmission {
+                id,
+                mod_key,
+                received_at: received_at as u64,
+                submission,
+                file_ids: Vec::new(),
+                survey_version,
+                possible_duplicates: Vec::new(),
+                bug: None,
+            });
+        }
+
+        let mut file_stmt = conn.prepare("SELECT file_id FROM submission_files WHERE submission_id = ?1")?;
+        for record in &mut records {
+            record.file_ids = file_stmt
+                .query_map(params![record.id.to_string()], |row| row.get::<_, String>(0))?
+                .filter_map(|r| r.ok())
+                .filter_map(|s| Uuid::parse_str(&s).ok())
+                .collect();
+        }
+
+        // Linked reports that were deleted since are dropped by the join
+        let mut duplicate_stmt = conn.prepare(
+            "SELECT duplicate_of, score, distance, user_name, submission_timestamp
+             FROM submission_duplicates JOIN submissions ON submissions.id = duplicate_of
+             WHERE submission_id = ?1 ORDER BY score DESC",
+        )?;
+        for record in &mut records {
+            record.possible_duplicates = duplicate_stmt
+                .query_map(params![record.id.to_string()], |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        DuplicateMatch {
+                            submission_id: Uuid::nil(),
+                            score: row.get(1)?,
+                            distance: row.get(2)?,
+                            user_name: row.get(3)?,
+                            submission_timestamp: row.get::<_, i64>(4)? as u64,
+                        },
+                    ))
+                })?
+                .filter_map(|r| r.ok())
+                .filter_map(|(id, duplicate)| Some(DuplicateMatch { submission_id: Uuid::parse_str(&id).ok()?, ..duplicate }))
+                .collect();
+        }
+
+        let mut bug_stmt = conn.prepare(
+            "SELECT COALESCE(status, 'open'), assignee_id, assignee_name, updated_by, updated_at, channel_id, message_id
+             FROM bug_reports WHERE submission_id = ?1",
+        )?;
+        for record in records.iter_mut().filter(|record| bug_status::is_bug_report(&record.submission)) {
+            let state = bug_stmt.query_row(params![record.id.to_string()], |row| {
+                Ok(BugState {
+                    status: BugStatus::parse(&row.get::<_, String>(0)?).unwrap_or(BugStatus::Open),
+                    assignee_id: row.get(1)?,
+                    assignee_name: row.get(2)?,
+                    updated_by: row.get(3)?,
+                    updated_at: row.get::<_, Option<i64>>(4)?.map(|t| t as u64),
+                    message: row.get::<_, Option<String>>(5)?.zip(row.get::<_, Option<String>>(6)?),
+                })
+            }).optional()?;
+            record.bug = Some(state.unwrap_or_default());
+        }
+
+        Ok(records)
+    }
+
+    /// One-shot import of the JSON submissions that were written to ANSWERS before the index existed.
+    /// Does nothing once the import has been recorded as done.
+    pub fn import_answers_dir(&self, file_manager: &FileManager) -> rusqlite::Result<usize> {
+        let already_imported: Option<String> = self.conn.lock().unwrap()
+            .query_row("SELECT value FROM store_meta WHERE key = ?1", params![ANSWERS_IMPORTED_KEY], |row| row.get(0))
+            .optional()?;
+        if already_imported.is_some() {
+            return Ok(0);
+        }
+
+        // Submission JSONs are registered in the file index, reuse their ids so /data links stay valid
+        let ids_by_path: HashMap<PathBuf, Uuid> = file_manager.files.iter()
+            .map(|entry| (entry.value().path.clone(), *entry.key()))
+            .collect();
+
+        // Layout: ANSWERS/{mod_key}/{user_xuid}/{survey}_{timestamp}.json
+        let answers_dir = file_manager.base_dir.join("ANSWERS");
+        let walker = walkdir::WalkDir::new(&answers_dir).min_depth(3).max_depth(3);
+        let mut imported = 0;
+
+        for entry in walker.into_iter().filter_map(|e| e.ok()) {
+            let path = entry.path();
+            if !path.is_file() || path.extension().and_then(|s| s.to_str()) != Some("json") {
+                continue;
+            }
+
+            let Some(mod_key) = path.strip_prefix(&answers_dir).ok()
+                .and_then(|rel| rel.components().next())
+                .and_then(|c| c.as_os_str().to_str())
+                .map(|s| s.to_string())
+            else {
+                continue;
+            };
+
+            let submission = match std::fs::read_to_string(path).map(|s| serde_json::from_str::<FormSubmission>(&s)) {
+                Ok(Ok(s)) => s,
+                Ok(Err(e)) => {
+                    warn!("Skipping {:?} during import: {}", path, e);
+                    continue;
+                }
+                Err(e) => {
+                    warn!("Failed to read {:?} during import: {}", path, e);
+                    continue;
+                }
+            };
+
+            let file_ids = submission.files.iter()
+                .filter_map(|(id, _)| Uuid::parse_str(id).ok())
+                .filter(|id| file_manager.files.contains_key(id))
+                .collect();
+
+            let received_at = ids_by_path.get(path)
+                .and_then(|id| file_manager.files.get(id).map(|m| m.uploaded_at))
+                .unwrap_or(submission.submission_timestamp);
+
+            let record = StoredSubmission {
+                id: ids_by_path.get(path).copied().unwrap_or_else(Uuid::new_v4),
+                mod_key,
+                received_at,
+                submission,
+                file_ids,
+                survey_version: None,
+                possible_duplicates: Vec::new(),
+                bug: None,
+            };
+            self.insert(&record)?;
+            imported += 1;
+        }
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        self.conn.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO store_meta (key, value) VALUES (?1, ?2)",
+            params![ANSWERS_IMPORTED_KEY, now.to_string()],
+        )?;
+
+        info!("Imported {} existing submissions from ANSWERS into the index", imported);
+        Ok(imported)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::duplicates::POSITION_KEY;
+    use crate::test_support;
+
+    fn bug_report(store: &SubmissionStore, mod_key: &str) -> Uuid {
+        let submission = test_support::submission("Forest", &[], serde_json::json!({ POSITION_KEY: "1, 2, 3" }));
+        let record = test_support::stored(mod_key, submission);
+        store.insert(&record).unwrap();
+        record.id
+    }
+
+    fn status(store: &SubmissionStore, mod_key: &str, id: &Uuid) -> (BugStatus, Option<String>) {
+        let bug = store.get(mod_key, id).unwrap().unwrap().bug.unwrap();
+        (bug.status, bug.updated_by)
+    }
+
+    fn open_ids(store: &SubmissionStore, mod_key: &str) -> Vec<Uuid> {
+        store.open_bugs(mod_key, None, 10).unwrap().1.into_iter().map(|record| record.id).collect()
+    }
+
+    #[test]
+    fn bug_status_transitions() {
+        let store = SubmissionStore::open(test_support::temp_dir().join("submissions.db")).unwrap();
+        let id = bug_report(&store, "key");
+        assert_eq!(status(&store, "key", &id), (BugStatus::Open, None));
+        assert_eq!(open_ids(&store, "key"), [id]);
+
+        assert!(store.set_bug_status("key", &id, BugStatus::Triaged, "100").unwrap());
+        assert_eq!(status(&store, "key", &id), (BugStatus::Triaged, Some("100".to_string())));
+        assert_eq!(open_ids(&store, "key"), [id]);
+
+        assert!(store.set_bug_assignee("key", &id, Some(("200", "Dev")), "100").unwrap());
+        assert!(store.set_bug_status("key", &id, BugStatus::Fixed, "200").unwrap());
+        let bug = store.get("key", &id).unwrap().unwrap().bug.unwrap();
+        assert_eq!((bug.status, bug.assignee_id.as_deref()), (BugStatus::Fixed, Some("200")));
+        assert!(open_ids(&store, "key").is_empty());
+
+        // Resolved reports can be reopened
+        assert!(store.set_bug_status("key", &id, BugStatus::Open, "100").unwrap());
+        assert_eq!(open_ids(&store, "key"), [id]);
+    }
+
+    #[test]
+    fn bug_status_of_another_key_is_not_changed() {
+        let store = SubmissionStore::open(test_support::temp_dir().join("submissions.db")).unwrap();
+        let id = bug_report(&store, "key");
+
+        assert!(!store.set_bug_status("other", &id, BugStatus::WontFix, "100").unwrap());
+        assert!(!store.set_bug_status("key", &Uuid::new_v4(), BugStatus::WontFix, "100").unwrap());
+        assert_eq!(status(&store, "key", &id), (BugStatus::Open, None));
+    }
+
+    #[test]
+    fn bug_statuses_round_trip_through_their_ids() {
+        for status in BugStatus::ALL {
+            assert_eq!(BugStatus::parse(status.as_str()), Some(status));
+        }
+        assert_eq!(BugStatus::parse("closed"), None);
+        let resolved: Vec<BugStatus> = BugStatus::ALL.into_iter().filter(BugStatus::is_resolved).collect();
+        assert_eq!(resolved, [BugStatus::Fixed, BugStatus::WontFix, BugStatus::Duplicate]);
+    }
+}
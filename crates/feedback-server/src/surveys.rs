@@ -0,0 +1,140 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+/// Largest survey definition accepted by `POST /surveys`
+pub const MAX_DEFINITION_BYTES: usize = 256 * 1024;
+
+/// A widget of a survey config, mirrors `WidgetConfig` of the client.
+/// Answers are keyed by the widget's `text`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type")]
+pub enum Question {
+    OneToTen {
+        text: String,
+        #[serde(default)]
+        label_at_one: String,
+        #[serde(default)]
+        label_at_ten: String,
+        #[serde(default)]
+        required: bool,
+    },
+    Essay {
+        text: String,
+        #[serde(default)]
+        required: bool,
+    },
+    RadioChoices {
+        text: String,
+        choices: Vec<String>,
+        #[serde(default)]
+        required: bool,
+    },
+    Checkboxes {
+        text: String,
+        choices: Vec<String>,
+        #[serde(default)]
+        required: bool,
+    },
+    TextBlock {
+        text: String,
+    },
+    Header {
+        text: String,
+    },
+    Separator,
+    /// Widget types added to the client after this server version
+    #[serde(other)]
+    Unknown,
+}
+
+impl Question {
+    /// Question text for widgets that produce an answer
+    pub fn answer_key(&self) -> Option<&str> {
+        match self {
+            Question::OneToTen { text, .. }
+            | Question::Essay { text, .. }
+            | Question::RadioChoices { text, .. }
+            | Question::Checkboxes { text, .. } => Some(text),
+            _ => None,
+        }
+    }
+
+    pub fn choices(&self) -> Option<&[String]> {
+        match self {
+            Question::RadioChoices { choices, .. } | Question::Checkboxes { choices, .. } => Some(choices),
+            _ => None,
+        }
+    }
+}
+
+/// The parts of a survey config (`FormConfig` on the client) the server understands
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SurveyDefinition {
+    #[serde(default)]
+    pub title: String,
+    pub widgets: Vec<Question>,
+}
+
+impl SurveyDefinition {
+    pub fn question(&self, answer_key: &str) -> Option<&Question> {
+        self.widgets.iter().find(|q| q.answer_key() == Some(answer_key))
+    }
+}
+
+/// A registered version of a survey definition
+#[derive(Serialize, Debug, Clone)]
+pub struct StoredDefinition {
+    pub survey_id: String,
+    pub version: u32,
+    pub content_hash: String,
+    pub registered_at: u64,
+    pub definition: SurveyDefinition,
+}
+
+/// Hex SHA-256 of the definition exactly as the client serialized it
+pub fn content_hash(definition_bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(definition_bytes))
+}
+
+/// Resolves question types by text across the definitions answered by a set of submissions.
+/// Later versions win when a question changed type.
+pub fn question_types(definitions: &[&StoredDefinition]) -> HashMap<String, Question> {
+    let mut sorted: Vec<&&StoredDefinition> = definitions.iter().collect();
+    sorted.sort_by_key(|d| d.version);
+
+    let mut questions = HashMap::new();
+    for definition in sorted {
+        for question in &definition.definition.widgets {
+            if let Some(key) = question.answer_key() {
+                questions.insert(key.to_string(), question.clone());
+            }
+        }
+    }
+    questions
+}
+
+/// Splits a Checkboxes answer back into the selected choices.
+/// The client joins them with ", ", which choices may contain too, so known choices are matched first.
+pub fn selected_choices<'a>(answer: &str, choices: &'a [String]) -> Vec<&'a str> {
+    let parts: Vec<&str> = answer.split(", ").collect();
+    let mut selected = Vec::new();
+    let mut i = 0;
+
+    while i < parts.len() {
+        // Longest run of parts that forms a known choice
+        let matched = (i + 1..=parts.len()).rev()
+            .find_map(|end| {
+                let candidate = parts[i..end].join(", ");
+                choices.iter().find(|c| **c == candidate).map(|c| (end, c.as_str()))
+            });
+        match matched {
+            Some((end, choice)) => {
+                selected.push(choice);
+                i = end;
+            }
+            None => i += 1,
+        }
+    }
+    selected
+}
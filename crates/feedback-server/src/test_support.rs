@@ -0,0 +1,94 @@
+use crate::dashboard::DashboardSessions;
+use crate::duplicates::DuplicateDetector;
+use crate::file_manager::FileManager;
+use crate::idempotency::IdempotencyCache;
+use crate::links::LinkSigner;
+use crate::metrics::Metrics;
+use crate::models::{FormSubmission, ModeratorKeyData};
+use crate::previews::VideoPreviews;
+use crate::rate_limit::RateLimiter;
+use crate::state::ServerState;
+use crate::submission_store::{StoredSubmission, SubmissionStore};
+use crate::validation::SubmissionLimits;
+use dashmap::DashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+/// A fresh directory under the system temp dir, left behind for inspection when a test fails
+pub fn temp_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("feedback-server-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Server state over an empty base directory, with `keys` registered and video previews disabled
+pub fn state(keys: &[(&str, ModeratorKeyData)]) -> ServerState {
+    let dir = temp_dir();
+    let file_manager = Arc::new(FileManager::new(100, &dir));
+    let submissions = SubmissionStore::open(dir.join("submissions.db")).unwrap();
+    ServerState {
+        key_store: Arc::new(keys.iter().map(|(key, data)| (key.to_string(), data.clone())).collect::<DashMap<_, _>>()),
+        submission_sender: broadcast::channel(16).0,
+        file_manager,
+        submissions: Arc::new(submissions),
+        idempotency: Arc::new(IdempotencyCache::default()),
+        rate_limiter: Arc::new(RateLimiter::default()),
+        metrics: Arc::new(Metrics::default()),
+        dashboard: Arc::new(DashboardSessions::default()),
+        submission_limits: Arc::new(SubmissionLimits::from_env()),
+        links: Arc::new(LinkSigner::load(&dir)),
+        previews: Arc::new(VideoPreviews::disabled()),
+        duplicates: Arc::new(DuplicateDetector::from_env()),
+    }
+}
+
+/// Key data marked as just used, so resolving the key does not write keys.json into the working directory
+pub fn key_data(server_name: &str) -> ModeratorKeyData {
+    ModeratorKeyData {
+        last_used_at: Some(SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()),
+        owner_id: "1".to_string(),
+        guild_id: "2".to_string(),
+        channel_id: "3".to_string(),
+        server_name: server_name.to_string(),
+        ..Default::default()
+    }
+}
+
+/// A submission as the client sends it, `extra` is merged in as extra data
+pub fn submission(map_name: &str, answers: &[(&str, &str)], extra: serde_json::Value) -> FormSubmission {
+    let mut json = serde_json::json!({
+        "survey_id": "bug_report.json",
+        "user_name": "Tester",
+        "user_xuid": "2533274800000000",
+        "map_name": map_name,
+        "game_timestamp": 12.5,
+        "submission_timestamp": 1_700_000_000u64,
+        "answers": {},
+        "custom_embed_color": null,
+        "files": [],
+    });
+    if let (Some(json), serde_json::Value::Object(extra)) = (json.as_object_mut(), extra) {
+        json.extend(extra);
+    }
+    let mut submission: FormSubmission = serde_json::from_value(json).unwrap();
+    // Set afterwards, a JSON object would sort the questions
+    submission.answers = answers.iter().map(|(q, a)| (q.to_string(), a.to_string())).collect();
+    submission
+}
+
+/// A submission of `mod_key` as the index holds it after `process_submission`
+pub fn stored(mod_key: &str, submission: FormSubmission) -> StoredSubmission {
+    StoredSubmission {
+        id: Uuid::new_v4(),
+        mod_key: mod_key.to_string(),
+        received_at: submission.submission_timestamp,
+        submission,
+        file_ids: Vec::new(),
+        survey_version: None,
+        possible_duplicates: Vec::new(),
+        bug: None,
+    }
+}
@@ -0,0 +1,216 @@
+use crate::file_manager::FileManager;
+use crate::models::FormSubmission;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::time::{SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+/// Submissions claiming to be older than this predate the tool (2020-01-01)
+const EARLIEST_SUBMISSION_TIMESTAMP: u64 = 1_577_836_800;
+
+/// Limits applied to every `FormSubmission`, configurable through the environment
+#[derive(Debug, Clone)]
+pub struct SubmissionLimits {
+    pub max_answers: usize,
+    pub max_question_chars: usize,
+    pub max_answer_chars: usize,
+    pub max_field_chars: usize,      // survey_id, map_name, user_name and file names
+    pub max_files: usize,
+    pub max_extra_data_bytes: usize, // serialized size of all extra fields
+    pub max_clock_skew_secs: u64,    // how far submission_timestamp may be in the future
+}
+
+impl SubmissionLimits {
+    pub fn from_env() -> Self {
+        let env = |name: &str, default: usize| {
+            std::env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+        };
+        Self {
+            max_answers: env("SUBMISSION_MAX_ANSWERS", 200),
+            max_question_chars: env("SUBMISSION_MAX_QUESTION_CHARS", 1_000),
+            max_answer_chars: env("SUBMISSION_MAX_ANSWER_CHARS", 20_000),
+            max_field_chars: env("SUBMISSION_MAX_FIELD_CHARS", 256),
+            max_files: env("SUBMISSION_MAX_FILES", 20),
+            max_extra_data_bytes: env("SUBMISSION_MAX_EXTRA_DATA_KB", 64) * 1024,
+            max_clock_skew_secs: env("SUBMISSION_MAX_CLOCK_SKEW_SECS", 24 * 60 * 60) as u64,
+        }
+    }
+}
+
+/// One problem found in a submission, returned to the client in the 422 response
+#[derive(Serialize, Debug, Clone)]
+pub struct ValidationError {
+    pub field: String,
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl ValidationError {
+    fn new(field: impl Into<String>, code: &'static str, message: impl Into<String>) -> Self {
+        Self { field: field.into(), code, message: message.into() }
+    }
+}
+
+/// Checks a submission against the limits. Attached files must have been uploaded with `mod_key`,
+/// either still waiting in TEMP_UPLOADS or already committed by an earlier attempt of the same submission.
+pub fn validate_submission(
+    submission: &FormSubmission,
+    mod_key: &str,
+    limits: &SubmissionLimits,
+    file_manager: &FileManager,
+) -> Result<(), Vec<ValidationError>> {
+    let mut errors = Vec::new();
+
+    let mut check_text = |field: &str, value: &str, max_chars: usize, required: bool| {
+        if required && value.trim().is_empty() {
+            errors.push(ValidationError::new(field, "required", "must not be empty"));
+        }
+        if value.chars().count() > max_chars {
+            errors.push(ValidationError::new(field, "too_long", format!("must be at most {} characters", max_chars)));
+        }
+    };
+    check_text("survey_id", &submission.survey_id, limits.max_field_chars, true);
+    // Empty when the survey was opened in the main menu, the game has no level loaded there
+    check_text("map_name", &submission.map_name, limits.max_field_chars, false);
+    check_text("user_name", &submission.user_name, limits.max_field_chars, false);
+
+    if submission.user_xuid.is_empty() || submission.user_xuid.len() > 20 || !submission.user_xuid.bytes().all(|b| b.is_ascii_digit()) {
+        errors.push(ValidationError::new("user_xuid", "invalid", "must be a numeric XUID"));
+    }
+
+    // Timestamps
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    if submission.submission_timestamp < EARLIEST_SUBMISSION_TIMESTAMP {
+        errors.push(ValidationError::new("submission_timestamp", "out_of_range", "is too far in the past"));
+    } else if submission.submission_timestamp > now + limits.max_clock_skew_secs {
+        errors.push(ValidationError::new("submission_timestamp", "out_of_range", "is in the future"));
+    }
+    if !submission.game_timestamp.is_finite() || submission.game_timestamp < 0.0 {
+        errors.push(ValidationError::new("game_timestamp", "out_of_range", "must be a non-negative number"));
+    }
+
+    if let Some(color) = submission.custom_embed_color
+        && !(0..=0xFFFFFF).contains(&color) {
+        errors.push(ValidationError::new("custom_embed_color", "out_of_range", "must be an RGB value between 0 and 0xFFFFFF"));
+    }
+
+    // Answers
+    if submission.answers.len() > limits.max_answers {
+        errors.push(ValidationError::new("answers", "too_many", format!("must have at most {} entries", limits.max_answers)));
+    }
+    for (question, answer) in &submission.answers {
+        if question.chars().count() > limits.max_question_chars {
+            errors.push(ValidationError::new("answers", "too_long", format!("question text must be at most {} characters", limits.max_question_chars)));
+        }
+        if answer.chars().count() > limits.max_answer_chars {
+            let question: String = question.chars().take(100).collect();
+            errors.push(ValidationError::new(format!("answers.{}", question), "too_long", format!("must be at most {} characters", limits.max_answer_chars)));
+        }
+    }
+
+    let extra_data_bytes = serde_json::to_vec(&submission.extra_data).map(|v| v.len()).unwrap_or(usize::MAX);
+    if extra_data_bytes > limits.max_extra_data_bytes {
+        errors.push(ValidationError::new("extra_data", "too_large", format!("extra fields must be at most {} bytes in total", limits.max_extra_data_bytes)));
+    }
+
+    // Attachments
+    if submission.files.len() > limits.max_files {
+        errors.push(ValidationError::new("files", "too_many", format!("must have at most {} entries", limits.max_files)));
+    }
+    let mut seen = HashSet::new();
+    for (i, (file_id, file_name)) in submission.files.iter().enumerate() {
+        let field = format!("files[{}]", i);
+        if file_name.chars().count() > limits.max_field_chars {
+            errors.push(ValidationError::new(&field, "too_long", format!("file name must be at most {} characters", limits.max_field_chars)));
+        }
+        let Ok(file_id) = Uuid::parse_str(file_id) else {
+            errors.push(ValidationError::new(&field, "invalid", "file id is not a UUID"));
+            continue;
+        };
+        if !seen.insert(file_id) {
+            errors.push(ValidationError::new(&field, "duplicate", "file is attached more than once"));
+            continue;
+        }
+
+        let uploaded_by = file_manager.temp_uploads.get(&file_id).map(|t| t.mod_key.clone())
+            .or_else(|| file_manager.files.get(&file_id).map(|f| f.mod_key.clone()));
+        if uploaded_by.as_deref() != Some(mod_key) {
+            errors.push(ValidationError::new(&field, "unknown_file", "file was not uploaded with this moderator key"));
+        }
+    }
+
+    if errors.is_empty() { Ok(()) } else { Err(errors) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_manager::TempUpload;
+    use crate::test_support;
+
+    fn file_manager() -> FileManager {
+        FileManager::new(100, test_support::temp_dir())
+    }
+
+    fn submission() -> FormSubmission {
+        test_support::submission("sp_a1_intro1", &[("What happened?", "Fell through the floor")], serde_json::json!({}))
+    }
+
+    fn error_codes(result: Result<(), Vec<ValidationError>>) -> Vec<(String, &'static str)> {
+        result.unwrap_err().into_iter().map(|error| (error.field, error.code)).collect()
+    }
+
+    #[test]
+    fn valid_submission_passes() {
+        let limits = SubmissionLimits::from_env();
+        assert!(validate_submission(&submission(), "key", &limits, &file_manager()).is_ok());
+    }
+
+    #[test]
+    fn submission_from_the_main_menu_passes() {
+        let limits = SubmissionLimits::from_env();
+        let submission = FormSubmission { map_name: String::new(), ..submission() };
+        assert!(validate_submission(&submission, "key", &limits, &file_manager()).is_ok());
+    }
+
+    #[test]
+    fn xuid_must_be_numeric() {
+        let limits = SubmissionLimits::from_env();
+        for xuid in ["", "2533274800000000x", "../../etc", "123456789012345678901"] {
+            let submission = FormSubmission { user_xuid: xuid.to_string(), ..submission() };
+            let result = validate_submission(&submission, "key", &limits, &file_manager());
+            assert_eq!(error_codes(result), [("user_xuid".to_string(), "invalid")], "{:?}", xuid);
+        }
+    }
+
+    #[test]
+    fn timestamp_must_be_within_the_clock_skew() {
+        let limits = SubmissionLimits { max_clock_skew_secs: 60, ..SubmissionLimits::from_env() };
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+
+        let on_time = FormSubmission { submission_timestamp: now + 30, ..submission() };
+        assert!(validate_submission(&on_time, "key", &limits, &file_manager()).is_ok());
+
+        for timestamp in [now + 120, EARLIEST_SUBMISSION_TIMESTAMP - 1] {
+            let submission = FormSubmission { submission_timestamp: timestamp, ..submission() };
+            let result = validate_submission(&submission, "key", &limits, &file_manager());
+            assert_eq!(error_codes(result), [("submission_timestamp".to_string(), "out_of_range")], "{}", timestamp);
+        }
+    }
+
+    #[test]
+    fn files_must_be_uploaded_with_the_same_key() {
+        let limits = SubmissionLimits::from_env();
+        let file_manager = file_manager();
+        let (own, foreign, unknown) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        file_manager.temp_uploads.insert(own, TempUpload { mod_key: "key".to_string(), original_name: "a.dem".to_string() });
+        file_manager.temp_uploads.insert(foreign, TempUpload { mod_key: "other".to_string(), original_name: "b.dem".to_string() });
+
+        let mut submission = submission();
+        submission.files = [own, foreign, unknown].iter().map(|id| (id.to_string(), "demo.dem".to_string())).collect();
+        assert_eq!(error_codes(validate_submission(&submission, "key", &limits, &file_manager)), [
+            ("files[1]".to_string(), "unknown_file"),
+            ("files[2]".to_string(), "unknown_file"),
+        ]);
+    }
+}
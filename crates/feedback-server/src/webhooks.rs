@@ -0,0 +1,497 @@
+use crate::demos::DemoInfo;
+use crate::file_manager::FileMetadata;
+use crate::links::LinkSigner;
+use crate::log_triage::LogFindings;
+use crate::models::{FormSubmission, SubmissionEvent, WebhookConfig};
+use crate::notifier::Notifier;
+use crate::previews::PreviewKind;
+use crate::state::ServerState;
+use crate::submission_store::WebhookDelivery;
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use indexmap::IndexMap;
+use serde::Serialize;
+use sha2::Sha256;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+const WEBHOOK_MAX_ATTEMPTS: u32 = 5;
+const WEBHOOK_RETRY_BASE_SECS: u64 = 5; // 5s, 10s, 20s, 40s between attempts
+const WEBHOOK_TIMEOUT_SECS: u64 = 10;
+const DELIVERY_LOG_RETENTION_SECS: u64 = 30 * 24 * 60 * 60;
+
+/// Body of the JSON POST sent to webhooks for every new submission
+#[derive(Serialize)]
+struct WebhookPayload<'a> {
+    event: &'static str,
+    submission_id: Uuid,
+    sent_at: u64,
+    server_name: &'a str,
+    label: Option<&'a str>,
+    json_url: String,
+    links_expire_at: u64, // json_url and file URLs are signed and stop working after this
+    submission: &'a FormSubmission,
+    files: Vec<WebhookFile>,
+}
+
+/// An attachment as described to webhooks and file drops. Unlike the query API, these go to third parties,
+/// so the owning key and where the server stores the file are left out.
+#[derive(Serialize)]
+struct WebhookFile {
+    id: Uuid,
+    original_name: String,
+    size_bytes: u64,
+    url: String,
+    #[serde(skip_serializing_if = "IndexMap::is_empty")]
+    preview_urls: IndexMap<PreviewKind, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    demo_info: Option<DemoInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    log_findings: Option<LogFindings>,
+}
+
+impl WebhookFile {
+    fn new(meta: &FileMetadata, links: &LinkSigner) -> Self {
+        Self {
+            id: meta.id,
+            original_name: meta.original_name.clone(),
+            size_bytes: meta.size_bytes,
+            url: links.data_link(&meta.id).url,
+            preview_urls: meta.previews.iter()
+                .map(|preview| (preview.kind, links.data_link(&preview.file_id).url))
+                .collect(),
+            demo_info: meta.demo_info.clone(),
+            log_findings: meta.log_findings.clone(),
+        }
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+/// Hex HMAC-SHA256 of `{timestamp}.{body}`, sent as `X-Webhook-Signature: sha256=...`
+fn sign(secret: &str, timestamp: u64, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    format!("{:x}", mac.finalize().into_bytes())
+}
+
+/// Whether webhooks may target loopback and private network addresses, off unless `WEBHOOK_ALLOW_PRIVATE_NETWORKS=true`
+pub fn allow_private_networks() -> bool {
+    std::env::var("WEBHOOK_ALLOW_PRIVATE_NETWORKS").is_ok_and(|v| v == "true")
+}
+
+/// Addresses inside the server's own network: loopback, private, link-local (cloud metadata) and unspecified
+fn is_internal(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let shared = ip.octets()[0] == 100 && (ip.octets()[1] & 0xc0) == 64; // 100.64.0.0/10
+            ip.is_loopback() || ip.is_private() || ip.is_link_local() || ip.is_unspecified() || ip.is_broadcast() || shared
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(mapped) => is_internal(IpAddr::V4(mapped)),
+            None => {
+                let unique_local = (ip.segments()[0] & 0xfe00) == 0xfc00; // fc00::/7
+                let link_local = (ip.segments()[0] & 0xffc0) == 0xfe80; // fe80::/10
+                ip.is_loopback() || ip.is_unspecified() || unique_local || link_local
+            }
+        },
+    }
+}
+
+/// Resolves the host of a webhook URL and refuses it when any address is internal, so a key
+/// cannot make the server call its own network. Returns the host and the addresses that were checked.
+pub async fn resolve_target(url: &str, allow_private: bool) -> Result<(String, Vec<SocketAddr>), String> {
+    let url = reqwest::Url::parse(url).map_err(|e| format!("invalid URL: {}", e))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err("only http:// and https:// URLs are supported".to_string());
+    }
+    let host = url.host_str().ok_or("the URL has no host")?.to_string();
+    let port = url.port_or_known_default().ok_or("the URL has no port")?;
+
+    let lookup_host = host.trim_start_matches('[').trim_end_matches(']');
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((lookup_host, port)).await
+        .map_err(|e| format!("could not resolve {}: {}", host, e))?
+        .collect();
+    if addrs.is_empty() {
+        return Err(format!("{} has no addresses", host));
+    }
+    if !allow_private && let Some(addr) = addrs.iter().find(|addr| is_internal(addr.ip())) {
+        return Err(format!("{} resolves to {}, which is a loopback or private network address", host, addr.ip()));
+    }
+    Ok((host, addrs))
+}
+
+/// POSTs `body` to the webhook once. The connection goes to the addresses checked by `resolve_target`,
+/// and redirects are not followed, they could point anywhere.
+async fn send(webhook: &WebhookConfig, allow_private: bool, delivery_id: Uuid, body: &[u8]) -> Result<reqwest::StatusCode, String> {
+    let (host, addrs) = resolve_target(&webhook.url, allow_private).await?;
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(WEBHOOK_TIMEOUT_SECS))
+        .redirect(reqwest::redirect::Policy::none())
+        .resolve_to_addrs(&host, &addrs)
+        .build()
+        .map_err(|e| e.to_string())?;
+
+    let timestamp = now_secs();
+    let mut request = client.post(&webhook.url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header("X-Webhook-Event", "submission.created")
+        .header("X-Webhook-Delivery", delivery_id.to_string())
+        .header("X-Webhook-Timestamp", timestamp.to_string());
+    if let Some(secret) = &webhook.secret {
+        request = request.header("X-Webhook-Signature", format!("sha256={}", sign(secret, timestamp, body)));
+    }
+
+    let response = request.body(body.to_vec()).send().await.map_err(|e| e.to_string())?;
+    Ok(response.status())
+}
+
+/// Forwards submission events to the webhooks configured on their key
+pub struct WebhookNotifier {
+    state: ServerState,
+    allow_private: bool,
+}
+
+impl WebhookNotifier {
+    pub fn new(state: ServerState) -> Self {
+        Self { state, allow_private: allow_private_networks() }
+    }
+}
+
+#[async_trait]
+impl Notifier for WebhookNotifier {
+    fn name(&self) -> &'static str {
+        "webhooks"
+    }
+
+    async fn notify(&self, event: &SubmissionEvent) -> Result<(), String> {
+        if event.destination.webhooks.is_empty() {
+            return Ok(());
+        }
+        let body = build_payload(event, &self.state.links).map_err(|e| format!("Failed to serialize webhook payload: {}", e))?;
+
+        // Each webhook retries on its own, a slow endpoint must not hold back the others
+        for webhook in event.destination.webhooks.clone() {
+            let (state, mod_key, body) = (self.state.clone(), event.mod_key.clone(), body.clone());
+            let (submission_id, allow_private) = (event.submission_id, self.allow_private);
+            tokio::spawn(async move {
+                deliver(&state, allow_private, Duration::from_secs(WEBHOOK_RETRY_BASE_SECS), &mod_key, &webhook, submission_id, body).await;
+            });
+        }
+        Ok(())
+    }
+}
+
+/// JSON describing a submission event, shared by webhooks and the file-drop notifier
+pub(crate) fn build_payload(event: &SubmissionEvent, links: &LinkSigner) -> serde_json::Result<Vec<u8>> {
+    let json_link = links.data_link(&event.submission_id);
+    let payload = WebhookPayload {
+        event: "submission.created",
+        submission_id: event.submission_id,
+        sent_at: now_secs(),
+        server_name: &event.destination.server_name,
+        label: event.destination.label.as_deref(),
+        json_url: json_link.url,
+        links_expire_at: json_link.expires_at,
+        submission: &event.submission,
+        files: event.attached_files.iter().map(|meta| WebhookFile::new(meta, links)).collect(),
+    };
+    serde_json::to_vec(&payload)
+}
+
+/// Wait before the attempt after `attempt`, doubling from `base`
+fn retry_delay(base: Duration, attempt: u32) -> Duration {
+    base * (1 << (attempt - 1))
+}
+
+/// POSTs `body` until the webhook answers with 2xx, logging every attempt
+async fn deliver(
+    state: &ServerState,
+    allow_private: bool,
+    retry_base: Duration,
+    mod_key: &str,
+    webhook: &WebhookConfig,
+    submission_id: Uuid,
+    body: Vec<u8>,
+) {
+    let delivery_id = Uuid::new_v4();
+
+    for attempt in 1..=WEBHOOK_MAX_ATTEMPTS {
+        let attempted_at = now_secs();
+        let started = Instant::now();
+        let result = send(webhook, allow_private, delivery_id, &body).await;
+        let mut delivery = WebhookDelivery {
+            webhook_id: webhook.id,
+            submission_id,
+            attempt,
+            status_code: None,
+            error: None,
+            duration_ms: started.elapsed().as_millis() as u64,
+            attempted_at,
+        };
+
+        let succeeded = match result {
+            Ok(status) => {
+                delivery.status_code = Some(status.as_u16());
+                status.is_success()
+            }
+            Err(e) => {
+                delivery.error = Some(e);
+                false
+            }
+        };
+        state.metrics.record_webhook_delivery(succeeded);
+        if let Err(e) = state.submissions.log_delivery(mod_key, &delivery) {
+            warn!("Failed to log webhook delivery: {}", e);
+        }
+
+        if succeeded {
+            return;
+        }
+        if attempt < WEBHOOK_MAX_ATTEMPTS {
+            let delay = retry_delay(retry_base, attempt);
+            warn!("Webhook {} failed for submission {} (attempt {}), retrying in {:?}", webhook.id, submission_id, attempt, delay);
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    warn!("Giving up on webhook {} for submission {} after {} attempts", webhook.id, submission_id, WEBHOOK_MAX_ATTEMPTS);
+}
+
+/// Drops old delivery log entries once a day
+pub async fn prune_delivery_log(state: ServerState) {
+    let mut interval = tokio::time::interval(Duration::from_secs(24 * 60 * 60));
+    loop {
+        interval.tick().await;
+        match state.submissions.prune_deliveries(now_secs().saturating_sub(DELIVERY_LOG_RETENTION_SECS)) {
+            Ok(0) => {}
+            Ok(pruned) => info!("Pruned {} old webhook delivery log entries", pruned),
+            Err(e) => warn!("Failed to prune the webhook delivery log: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Local stand-in for a webhook receiver that redirects every request, returns its URL and a request counter
+    async fn redirecting_receiver() -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                counter.fetch_add(1, Ordering::SeqCst);
+                let mut buf = [0u8; 4096];
+                let _ = socket.read(&mut buf).await;
+                let response = "HTTP/1.1 302 Found\r\nLocation: http://169.254.169.254/latest/meta-data\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+        (url, requests)
+    }
+
+    /// Local stand-in for a webhook receiver answering with `statuses` in turn (the last one repeats),
+    /// returns its URL and the raw requests it received
+    async fn recording_receiver(statuses: Vec<u16>) -> (String, Arc<std::sync::Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let requests = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let received = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let request = read_request(&mut socket).await;
+                let index = received.lock().unwrap().len().min(statuses.len() - 1);
+                received.lock().unwrap().push(request);
+                let response = format!("HTTP/1.1 {} Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", statuses[index]);
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+        (url, requests)
+    }
+
+    /// Reads the head and the `Content-Length` body of one request
+    async fn read_request(socket: &mut tokio::net::TcpStream) -> String {
+        let mut data = Vec::new();
+        let mut buf = [0u8; 4096];
+        loop {
+            let n = socket.read(&mut buf).await.unwrap_or(0);
+            if n == 0 {
+                break;
+            }
+            data.extend_from_slice(&buf[..n]);
+            let text = String::from_utf8_lossy(&data);
+            if let Some(head_end) = text.find("\r\n\r\n") {
+                let length = header(&text, "content-length").and_then(|v| v.parse::<usize>().ok()).unwrap_or(0);
+                if data.len() >= head_end + 4 + length {
+                    break;
+                }
+            }
+        }
+        String::from_utf8_lossy(&data).into_owned()
+    }
+
+    fn header<'a>(request: &'a str, name: &str) -> Option<&'a str> {
+        request.lines()
+            .take_while(|line| !line.is_empty())
+            .find_map(|line| line.split_once(':').filter(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, v)| v.trim()))
+    }
+
+    fn webhook(url: &str) -> WebhookConfig {
+        WebhookConfig { id: Uuid::new_v4(), url: url.to_string(), secret: Some("secret".to_string()), created_at: 0 }
+    }
+
+    #[test]
+    fn internal_addresses() {
+        for ip in ["127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.169.254", "0.0.0.0", "100.64.0.1", "::1", "::", "fd00::1", "fe80::1", "::ffff:127.0.0.1"] {
+            assert!(is_internal(ip.parse().unwrap()), "{} should be internal", ip);
+        }
+        for ip in ["1.1.1.1", "93.184.216.34", "2606:4700::1111", "::ffff:8.8.8.8"] {
+            assert!(!is_internal(ip.parse().unwrap()), "{} should be public", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn refuses_internal_targets() {
+        for url in ["http://127.0.0.1/hook", "http://localhost:8080/hook", "http://169.254.169.254/latest", "http://[::1]/hook", "http://0.0.0.0/"] {
+            assert!(resolve_target(url, false).await.is_err(), "{} should be refused", url);
+        }
+        assert!(resolve_target("ftp://93.184.216.34/", false).await.is_err());
+        assert!(resolve_target("http://127.0.0.1/hook", true).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn send_does_not_reach_internal_receiver() {
+        let (url, requests) = redirecting_receiver().await;
+        let result = send(&webhook(&url), false, Uuid::new_v4(), b"{}").await;
+        assert!(result.is_err());
+        assert_eq!(requests.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn send_does_not_follow_redirects() {
+        let (url, requests) = redirecting_receiver().await;
+        let status = send(&webhook(&url), true, Uuid::new_v4(), b"{}").await.unwrap();
+        assert_eq!(status, reqwest::StatusCode::FOUND);
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn signature_is_hmac_of_timestamp_and_body() {
+        assert_eq!(sign("secret", 1_700_000_000, b"{}"), "b8569b78799ff9e3cbff0fc2d63a33a2b57f3282abd07c37ae5e8e7d79a5f163");
+    }
+
+    #[tokio::test]
+    async fn send_signs_the_request() {
+        let (url, requests) = recording_receiver(vec![200]).await;
+        let body = br#"{"event":"submission.created"}"#;
+        let status = send(&webhook(&url), true, Uuid::new_v4(), body).await.unwrap();
+        assert_eq!(status, reqwest::StatusCode::OK);
+
+        let requests = requests.lock().unwrap();
+        let request = &requests[0];
+        let timestamp: u64 = header(request, "x-webhook-timestamp").unwrap().parse().unwrap();
+        let expected = format!("sha256={}", sign("secret", timestamp, body));
+        assert_eq!(header(request, "x-webhook-signature"), Some(expected.as_str()));
+        assert_eq!(header(request, "x-webhook-event"), Some("submission.created"));
+        assert!(request.ends_with(std::str::from_utf8(body).unwrap()));
+    }
+
+    #[tokio::test]
+    async fn unsigned_without_secret() {
+        let (url, requests) = recording_receiver(vec![200]).await;
+        let webhook = WebhookConfig { secret: None, ..webhook(&url) };
+        send(&webhook, true, Uuid::new_v4(), b"{}").await.unwrap();
+        assert_eq!(header(&requests.lock().unwrap()[0], "x-webhook-signature"), None);
+    }
+
+    #[test]
+    fn retries_back_off_exponentially() {
+        let base = Duration::from_secs(WEBHOOK_RETRY_BASE_SECS);
+        let delays: Vec<u64> = (1..WEBHOOK_MAX_ATTEMPTS).map(|attempt| retry_delay(base, attempt).as_secs()).collect();
+        assert_eq!(delays, [5, 10, 20, 40]);
+    }
+
+    #[tokio::test]
+    async fn every_attempt_is_logged_until_success() {
+        let state = crate::test_support::state(&[]);
+        let (url, requests) = recording_receiver(vec![503, 500, 204]).await;
+        let webhook = webhook(&url);
+        let submission_id = Uuid::new_v4();
+
+        deliver(&state, true, Duration::from_millis(1), "key", &webhook, submission_id, b"{}".to_vec()).await;
+
+        let log = state.submissions.recent_deliveries("key", 10).unwrap();
+        let attempts: Vec<(u32, Option<u16>)> = log.iter().rev().map(|d| (d.attempt, d.status_code)).collect();
+        assert_eq!(attempts, [(1, Some(503)), (2, Some(500)), (3, Some(204))]);
+        assert!(log.iter().all(|d| d.webhook_id == webhook.id && d.submission_id == submission_id));
+        // The delivery id stays the same across retries, so receivers can drop duplicates
+        let requests = requests.lock().unwrap();
+        assert!(requests.iter().all(|r| header(r, "x-webhook-delivery") == header(&requests[0], "x-webhook-delivery")));
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_attempts() {
+        let state = crate::test_support::state(&[]);
+        let webhook = webhook("http://127.0.0.1/hook");
+
+        deliver(&state, false, Duration::from_millis(1), "key", &webhook, Uuid::new_v4(), b"{}".to_vec()).await;
+
+        let log = state.submissions.recent_deliveries("key", 10).unwrap();
+        assert_eq!(log.len(), WEBHOOK_MAX_ATTEMPTS as usize);
+        assert!(log.iter().all(|d| d.status_code.is_none() && d.error.as_deref().is_some_and(|e| e.contains("private network"))));
+    }
+
+    #[test]
+    fn payload_leaves_out_key_and_storage_paths() {
+        let state = crate::test_support::state(&[]);
+        let file = FileMetadata {
+            id: Uuid::new_v4(),
+            mod_key: "secret-moderator-key".to_string(),
+            original_name: "console.log".to_string(),
+            size_bytes: 42,
+            path: "/srv/feedback/BLOBS/ab/abcdef".into(),
+            uploaded_at: 1,
+            expires_at: None,
+            is_priority: false,
+            status: crate::file_manager::FileStatus::Active,
+            content_hash: Some("abcdef".to_string()),
+            previews: Vec::new(),
+            derived_from: None,
+            demo_info: None,
+            log_findings: None,
+        };
+        let event = SubmissionEvent {
+            submission_id: Uuid::new_v4(),
+            mod_key: "secret-moderator-key".to_string(),
+            destination: crate::test_support::key_data("Test Server"),
+            submission: crate::test_support::submission("mp_test", &[], serde_json::json!({})),
+            submission_bytes: Vec::new(),
+            filename: "bug_report_1.json".to_string(),
+            attached_files: vec![file.clone()],
+            possible_duplicates: Vec::new(),
+        };
+
+        let payload = String::from_utf8(build_payload(&event, &state.links).unwrap()).unwrap();
+        assert!(!payload.contains("secret-moderator-key"));
+        assert!(!payload.contains("/srv/feedback"));
+        let json: serde_json::Value = serde_json::from_str(&payload).unwrap();
+        let attached = json["files"][0].as_object().unwrap();
+        assert!(!attached.contains_key("mod_key") && !attached.contains_key("path"));
+        assert_eq!(attached["id"], file.id.to_string());
+        assert_eq!(attached["original_name"], "console.log");
+        assert!(attached["url"].as_str().unwrap().contains(&file.id.to_string()));
+    }
+}